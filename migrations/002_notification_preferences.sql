@@ -0,0 +1,12 @@
+-- Create table notification_preferences
+CREATE TABLE IF NOT EXISTS tyto.notification_preferences (
+	id bigserial NOT NULL, /* Unique ID for a preference. */
+	user_id int8 NOT NULL references tyto.users(id) ON DELETE CASCADE, /* Reference to a User the preference belongs to. */
+	event varchar(64) NOT NULL, /* Event to be notified about. Like link_created. */
+	channel varchar(32) NOT NULL, /* Channel to deliver the notification through. One of email, webhook, slack or log. */
+	destination varchar(2040) NULL, /* Channel specific destination like a webhook URL. Configured default is used when NULL. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when preference is created. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when preference is updated. */
+	CONSTRAINT notification_preferences_pkey PRIMARY KEY (id),
+	CONSTRAINT notification_preferences_unique UNIQUE (user_id, event, channel)
+);
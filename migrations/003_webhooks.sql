@@ -0,0 +1,31 @@
+-- Create table webhooks
+CREATE TABLE IF NOT EXISTS tyto.webhooks (
+	id bigserial NOT NULL, /* Unique ID for a webhook. */
+	user_id int8 NOT NULL references tyto.users(id) ON DELETE CASCADE, /* Reference to a User the webhook belongs to. */
+	url varchar(2040) NOT NULL, /* URL payloads are posted to. */
+	secret varchar(255) NOT NULL, /* Secret used to sign payloads with HMAC-SHA256. */
+	events text[] NOT NULL DEFAULT '{}', /* Events delivered to the webhook. All the events are delivered when empty. */
+	active bool NOT NULL DEFAULT true, /* Indicates if deliveries are made to the webhook. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when webhook is created. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when webhook is updated. */
+	CONSTRAINT webhooks_pkey PRIMARY KEY (id)
+);
+
+-- Create table webhook_deliveries
+CREATE TABLE IF NOT EXISTS tyto.webhook_deliveries (
+	id bigserial NOT NULL, /* Unique ID for a delivery. */
+	webhook_id int8 NOT NULL references tyto.webhooks(id) ON DELETE CASCADE, /* Reference to a Webhook the delivery belongs to. */
+	event varchar(64) NOT NULL, /* Name of the delivered event. Like link.created. */
+	payload jsonb NOT NULL, /* JSON payload posted to the webhook. */
+	status varchar(16) NOT NULL DEFAULT 'pending', /* One of pending, succeeded or failed. */
+	attempts int4 NOT NULL DEFAULT 0, /* Number of attempts made so far. */
+	response_status int4 NULL, /* HTTP status code of the last response. */
+	response_body text NULL, /* Body of the last response, truncated. */
+	error text NULL, /* Error of the last attempt if webhook could not be reached. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when delivery is created. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when delivery is updated. */
+	delivered_at timestamptz NULL, /* Timestamp indicating when delivery succeeded. */
+	CONSTRAINT webhook_deliveries_pkey PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_id_idx ON tyto.webhook_deliveries (webhook_id, created_at DESC);
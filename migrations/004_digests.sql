@@ -0,0 +1,14 @@
+-- Create table digest_subscriptions
+CREATE TABLE IF NOT EXISTS tyto.digest_subscriptions (
+	user_id int8 NOT NULL references tyto.users(id) ON DELETE CASCADE, /* Reference to a User the subscription belongs to. */
+	frequency varchar(16) NOT NULL, /* How often digest is sent. One of daily or weekly. */
+	timezone varchar(64) NOT NULL DEFAULT 'UTC', /* IANA time zone of a user. Like Europe/Berlin. */
+	hour int4 NOT NULL DEFAULT 8, /* Hour of a day in user's time zone when digest is sent. */
+	unsubscribe_token varchar(255) NOT NULL, /* Token used to unsubscribe without login. */
+	visit_counts jsonb NOT NULL DEFAULT '{}'::jsonb, /* Visit count of every link of a user when last digest was sent. */
+	last_sent_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when last digest is sent. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when subscription is created. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when subscription is updated. */
+	CONSTRAINT digest_subscriptions_pkey PRIMARY KEY (user_id),
+	CONSTRAINT digest_subscriptions_token_unique UNIQUE (unsubscribe_token)
+);
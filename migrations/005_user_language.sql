@@ -0,0 +1,2 @@
+-- Add preferred language of users
+ALTER TABLE tyto.users ADD COLUMN IF NOT EXISTS "language" varchar(16) NULL; /* Preferred language of a user for emails. Like de. Configured default is used when NULL. */
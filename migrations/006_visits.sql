@@ -0,0 +1,14 @@
+-- Create table visits
+CREATE TABLE IF NOT EXISTS tyto.visits (
+	id bigserial NOT NULL, /* Unique ID for a visit. */
+	url_id int8 NOT NULL references tyto.urls(id) ON DELETE CASCADE, /* Reference to a URL that is visited. */
+	address varchar(255) NOT NULL, /* Shortened URL part that is visited. */
+	referrer varchar(2040) NULL, /* Referer header of a visit. */
+	user_agent varchar(1024) NULL, /* User-Agent header of a visit. */
+	ip_hash varchar(64) NULL, /* Salted SHA-256 of client IP address. Raw addresses are never stored. */
+	country varchar(2) NULL, /* ISO 3166-1 alpha-2 country code of a client. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when visit is made. */
+	CONSTRAINT visits_pkey PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS visits_url_id_created_at_idx ON tyto.visits (url_id, created_at);
@@ -0,0 +1,4 @@
+-- Add user agent families to visits
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS browser varchar(32) NULL; /* Browser family parsed from User-Agent header, like chrome or firefox. */
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS os varchar(32) NULL; /* Operating system family parsed from User-Agent header, like android or windows. */
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS device varchar(32) NULL; /* Device class parsed from User-Agent header, like desktop or mobile. */
@@ -0,0 +1,12 @@
+-- Add traffic sources to visits
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS referrer_domain varchar(255) NULL; /* Host of Referer header without www, direct when there is no referrer and unknown when it can not be parsed. */
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS utm_source varchar(255) NULL; /* utm_source parameter of a short link request. */
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS utm_medium varchar(255) NULL; /* utm_medium parameter of a short link request. */
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS utm_campaign varchar(255) NULL; /* utm_campaign parameter of a short link request. */
+
+-- Fill referring domains of visits recorded before
+UPDATE tyto.visits SET referrer_domain = CASE
+	WHEN referrer IS NULL OR referrer = '' THEN 'direct'
+	ELSE COALESCE(regexp_replace(lower(substring(referrer from '^[A-Za-z][A-Za-z0-9+.-]*://(?:[^@/]*@)?([^:/?#]+)')), '^www\.', ''), 'unknown')
+END
+WHERE referrer_domain IS NULL;
@@ -0,0 +1,3 @@
+-- Add client location to visits
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS region varchar(255) NULL; /* Name of the largest subdivision, like a state, resolved from client IP address. */
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS city varchar(255) NULL; /* Name of a city resolved from client IP address. */
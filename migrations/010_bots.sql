@@ -0,0 +1,50 @@
+-- Add roles to users
+ALTER TABLE tyto.users ADD COLUMN IF NOT EXISTS "role" varchar(16) NOT NULL DEFAULT 'regular'; /* Role of a user, regular or admin. */
+
+-- Create table bot_signatures
+CREATE TABLE IF NOT EXISTS tyto.bot_signatures (
+	id bigserial NOT NULL, /* Unique ID for a bot signature. */
+	pattern varchar(255) NOT NULL, /* Lowercase text that User-Agent header of a bot contains. */
+	description varchar(255) NULL, /* Description of bots matched by a signature. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when signature is created. */
+	CONSTRAINT bot_signatures_pkey PRIMARY KEY (id),
+	CONSTRAINT bot_signatures_pattern_unique UNIQUE (pattern)
+);
+
+INSERT INTO tyto.bot_signatures (pattern, description) VALUES
+	('bot', 'Generic bots'),
+	('crawler', 'Generic crawlers'),
+	('spider', 'Generic spiders'),
+	('slackbot', 'Slack link previews'),
+	('twitterbot', 'Twitter link previews'),
+	('facebookexternalhit', 'Facebook link previews'),
+	('facebot', 'Facebook crawler'),
+	('linkedinbot', 'LinkedIn link previews'),
+	('discordbot', 'Discord link previews'),
+	('telegrambot', 'Telegram link previews'),
+	('whatsapp', 'WhatsApp link previews'),
+	('skypeuripreview', 'Skype link previews'),
+	('embedly', 'Embedly link previews'),
+	('uptimerobot', 'UptimeRobot uptime checks'),
+	('pingdom', 'Pingdom uptime checks'),
+	('statuscake', 'StatusCake uptime checks'),
+	('site24x7', 'Site24x7 uptime checks'),
+	('headlesschrome', 'Headless Chrome'),
+	('curl/', 'curl'),
+	('wget/', 'Wget'),
+	('python-requests', 'Python requests library'),
+	('go-http-client', 'Go HTTP client')
+ON CONFLICT (pattern) DO NOTHING;
+
+-- Count bot visits separately
+ALTER TABLE tyto.visits ADD COLUMN IF NOT EXISTS is_bot bool NOT NULL DEFAULT false; /* Indicates if a visit is made by a bot, crawler or prefetcher. */
+ALTER TABLE tyto.urls ADD COLUMN IF NOT EXISTS bot_visit_count int4 NOT NULL DEFAULT 0; /* Number of visits paid to a URL by bots. They are not part of visit_count. */
+
+-- Classify visits recorded before and move their bot visits out of visit_count
+UPDATE tyto.visits v SET is_bot = true
+WHERE v.user_agent IS NULL OR v.user_agent = '' OR v.device = 'bot'
+	OR EXISTS (SELECT 1 FROM tyto.bot_signatures s WHERE strpos(lower(v.user_agent), s.pattern) > 0);
+
+UPDATE tyto.urls u SET bot_visit_count = b.visits, visit_count = GREATEST(u.visit_count - b.visits, 0)
+FROM (SELECT url_id, COUNT(*) AS visits FROM tyto.visits WHERE is_bot GROUP BY url_id) b
+WHERE u.id = b.url_id;
@@ -0,0 +1,26 @@
+-- Create table visitor_sketches
+CREATE TABLE IF NOT EXISTS tyto.visitor_sketches (
+	url_id int8 NOT NULL references tyto.urls(id) ON DELETE CASCADE, /* Reference to a URL whose visitors are counted. */
+	"day" date NOT NULL, /* UTC day covered by a sketch. */
+	registers bytea NOT NULL, /* 4096 HyperLogLog registers of hashed visitor identifiers of human visits. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when sketch is updated. */
+	CONSTRAINT visitor_sketches_pkey PRIMARY KEY (url_id, "day")
+);
+
+-- Fill sketches from visits recorded before. The first 12 bits of an IP hash pick a register
+-- and the register keeps the position of the first set bit among the next 52 bits.
+WITH registers AS (
+	SELECT url_id, (created_at AT TIME ZONE 'UTC')::date AS "day",
+		('x' || substr(ip_hash, 1, 3))::bit(12)::int AS idx,
+		MAX(COALESCE(NULLIF(position('1' in ('x' || substr(ip_hash, 4, 13))::bit(52)::text), 0), 53)) AS "rank"
+	FROM tyto.visits
+	WHERE ip_hash IS NOT NULL AND NOT is_bot
+	GROUP BY 1, 2, 3
+)
+INSERT INTO tyto.visitor_sketches (url_id, "day", registers)
+SELECT d.url_id, d."day", decode(string_agg(lpad(to_hex(COALESCE(r."rank", 0)), 2, '0'), '' ORDER BY s.idx), 'hex')
+FROM (SELECT DISTINCT url_id, "day" FROM registers) d
+CROSS JOIN generate_series(0, 4095) AS s(idx)
+LEFT JOIN registers r ON r.url_id = d.url_id AND r."day" = d."day" AND r.idx = s.idx
+GROUP BY d.url_id, d."day"
+ON CONFLICT DO NOTHING;
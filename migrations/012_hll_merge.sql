@@ -0,0 +1,16 @@
+-- Merges two HyperLogLog sketches of visitor_sketches by taking maximum of every register.
+CREATE OR REPLACE FUNCTION tyto.hll_merge(a bytea, b bytea) RETURNS bytea AS $$
+DECLARE
+	merged bytea := a;
+BEGIN
+	IF a IS NULL OR length(a) <> length(b) THEN
+		RETURN b;
+	END IF;
+	FOR i IN 0 .. length(b) - 1 LOOP
+		IF get_byte(b, i) > get_byte(merged, i) THEN
+			merged := set_byte(merged, i, get_byte(b, i));
+		END IF;
+	END LOOP;
+	RETURN merged;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
@@ -0,0 +1,29 @@
+-- Create table visit_rollups_hourly
+CREATE TABLE IF NOT EXISTS tyto.visit_rollups_hourly (
+	url_id int8 NOT NULL references tyto.urls(id) ON DELETE CASCADE, /* Reference to a URL whose visits are counted. */
+	"hour" timestamptz NOT NULL, /* Start of an hour covered by a rollup. */
+	clicks int8 NOT NULL DEFAULT 0, /* Number of human visits within the hour. */
+	bot_clicks int8 NOT NULL DEFAULT 0, /* Number of bot visits within the hour. */
+	unique_visitors int8 NOT NULL DEFAULT 0, /* Number of distinct IP hashes of human visits within the hour. */
+	CONSTRAINT visit_rollups_hourly_pkey PRIMARY KEY (url_id, "hour")
+);
+
+-- Create table visit_rollups_daily
+CREATE TABLE IF NOT EXISTS tyto.visit_rollups_daily (
+	url_id int8 NOT NULL references tyto.urls(id) ON DELETE CASCADE, /* Reference to a URL whose visits are counted. */
+	"day" date NOT NULL, /* UTC day covered by a rollup. */
+	dimension varchar(32) NOT NULL, /* Breakdown dimension counted. Like browser or referrer. */
+	"name" varchar(1024) NOT NULL, /* Value of the dimension. Like firefox or example.com. */
+	clicks int8 NOT NULL DEFAULT 0, /* Number of human visits with the value within the day. */
+	CONSTRAINT visit_rollups_daily_pkey PRIMARY KEY (url_id, "day", dimension, "name")
+);
+
+-- Create table visit_rollup_state
+CREATE TABLE IF NOT EXISTS tyto.visit_rollup_state (
+	id boolean NOT NULL DEFAULT true, /* Always true, so the table holds a single row. */
+	rolled_until date NULL, /* UTC day visits are rolled up before. Visits are not rolled up yet when NULL. */
+	CONSTRAINT visit_rollup_state_pkey PRIMARY KEY (id),
+	CONSTRAINT visit_rollup_state_single CHECK (id)
+);
+
+INSERT INTO tyto.visit_rollup_state (id, rolled_until) VALUES (true, NULL) ON CONFLICT DO NOTHING;
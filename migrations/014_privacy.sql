@@ -0,0 +1,10 @@
+-- Allow owners to turn storage of visit details off for a link
+ALTER TABLE tyto.urls ADD COLUMN IF NOT EXISTS track_visits boolean NOT NULL DEFAULT true; /* Indicates if details of every visit are stored. Only visit counts are kept when false. */
+
+-- Create table ip_salts
+CREATE TABLE IF NOT EXISTS tyto.ip_salts (
+	"day" date NOT NULL, /* UTC day client IP addresses are hashed with a salt on. */
+	salt varchar(64) NOT NULL, /* Random salt. It is deleted once its day has passed, so hashes can not be linked back to addresses. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when salt is generated. */
+	CONSTRAINT ip_salts_pkey PRIMARY KEY ("day")
+);
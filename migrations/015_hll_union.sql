@@ -0,0 +1,5 @@
+-- Merges HyperLogLog sketches of visitor_sketches of many rows, like days of a month.
+CREATE OR REPLACE AGGREGATE tyto.hll_union(bytea) (
+	SFUNC = tyto.hll_merge,
+	STYPE = bytea
+);
@@ -0,0 +1,2 @@
+-- Allow owners to publish statistics of a link
+ALTER TABLE tyto.urls ADD COLUMN IF NOT EXISTS public_stats boolean NOT NULL DEFAULT false; /* Indicates if statistics of a URL are public at its address followed by +. */
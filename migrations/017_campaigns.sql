@@ -0,0 +1,18 @@
+-- Create table campaigns
+CREATE TABLE IF NOT EXISTS tyto.campaigns (
+	id bigserial NOT NULL, /* Unique ID for a campaign. */
+	user_id int8 NOT NULL references tyto.users(id) ON DELETE CASCADE, /* Reference to a User the campaign belongs to. */
+	"name" varchar(255) NOT NULL, /* Name of the campaign. Unique among campaigns of a user. */
+	description varchar(1024) NULL, /* Description of the campaign. */
+	starts_at timestamptz NULL, /* Start of the campaign. Statistics of the campaign start here by default. */
+	ends_at timestamptz NULL, /* End of the campaign. Statistics of the campaign end here by default. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when campaign is created. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when campaign is updated. */
+	CONSTRAINT campaigns_pkey PRIMARY KEY (id),
+	CONSTRAINT campaigns_user_name_key UNIQUE (user_id, "name"),
+	CONSTRAINT campaigns_range_check CHECK (starts_at IS NULL OR ends_at IS NULL OR starts_at < ends_at)
+);
+
+-- Group links into campaigns
+ALTER TABLE tyto.urls ADD COLUMN IF NOT EXISTS campaign_id int8 NULL references tyto.campaigns(id) ON DELETE SET NULL; /* Reference to a Campaign the URL belongs to. */
+CREATE INDEX IF NOT EXISTS urls_campaign_id_idx ON tyto.urls (campaign_id);
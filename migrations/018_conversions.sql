@@ -0,0 +1,36 @@
+-- Allow owners to track conversions of a link
+ALTER TABLE tyto.urls ADD COLUMN IF NOT EXISTS track_conversions boolean NOT NULL DEFAULT false; /* Indicates if a click identifier is appended to the target on redirect. */
+
+-- Create table conversion_goals
+CREATE TABLE IF NOT EXISTS tyto.conversion_goals (
+	id bigserial NOT NULL, /* Unique ID for a goal. */
+	user_id int8 NOT NULL references tyto.users(id) ON DELETE CASCADE, /* Reference to a User the goal belongs to. */
+	"name" varchar(255) NOT NULL, /* Name of the goal. Like signup or purchase. Unique among goals of a user. */
+	"key" varchar(64) NOT NULL, /* Random key identifying the goal in tracking pixels and postbacks. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when goal is created. */
+	updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when goal is updated. */
+	CONSTRAINT conversion_goals_pkey PRIMARY KEY (id),
+	CONSTRAINT conversion_goals_key_key UNIQUE ("key"),
+	CONSTRAINT conversion_goals_user_name_key UNIQUE (user_id, "name")
+);
+
+-- Create table clicks
+CREATE TABLE IF NOT EXISTS tyto.clicks (
+	id varchar(64) NOT NULL, /* Random click identifier appended to the target of a redirect. */
+	url_id int8 NOT NULL references tyto.urls(id) ON DELETE CASCADE, /* Reference to a URL that is clicked. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when the click is made. */
+	CONSTRAINT clicks_pkey PRIMARY KEY (id)
+);
+CREATE INDEX IF NOT EXISTS clicks_created_at_idx ON tyto.clicks (created_at);
+
+-- Create table conversions
+CREATE TABLE IF NOT EXISTS tyto.conversions (
+	id bigserial NOT NULL, /* Unique ID for a conversion. */
+	goal_id int8 NOT NULL references tyto.conversion_goals(id) ON DELETE CASCADE, /* Reference to a Goal that is reached. */
+	url_id int8 NOT NULL references tyto.urls(id) ON DELETE CASCADE, /* Reference to a URL whose click led to the conversion. */
+	click_id varchar(64) NOT NULL, /* Identifier of the click that led to the conversion. */
+	created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP, /* Timestamp indicating when the conversion is reported. */
+	CONSTRAINT conversions_pkey PRIMARY KEY (id),
+	CONSTRAINT conversions_goal_click_key UNIQUE (goal_id, click_id)
+);
+CREATE INDEX IF NOT EXISTS conversions_url_id_created_at_idx ON tyto.conversions (url_id, created_at);
@@ -0,0 +1,2 @@
+-- Allow users to opt out of an event
+ALTER TABLE tyto.notification_preferences ALTER COLUMN channel DROP NOT NULL; /* A preference without a channel opts out of its event, so none of its default channels are used. */
@@ -0,0 +1,5 @@
+-- Allow owners to set a time a link stops redirecting at
+ALTER TABLE tyto.urls ADD COLUMN IF NOT EXISTS expires_at timestamptz NULL; /* Timestamp after which the link is not redirected. Never when null. */
+
+-- Digests list links of a user that expired since the last digest
+CREATE INDEX IF NOT EXISTS urls_user_expires_at_idx ON tyto.urls (user_id, expires_at) WHERE expires_at IS NOT NULL;
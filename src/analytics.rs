@@ -0,0 +1,798 @@
+pub mod bots;
+pub mod buffer;
+pub mod export;
+pub mod geoip;
+pub mod hll;
+pub mod live;
+pub mod overview;
+pub mod privacy;
+pub mod rollups;
+pub mod user_agent;
+
+use crate::config::AnalyticsConfig;
+use crate::constants;
+use crate::error::Error;
+use crate::types::{
+    Breakdown, BreakdownItem, GeoLevel, Stats, StatsBucket, StatsInterval, StatsQuery, UrlClicks,
+    UtmParameter,
+};
+use crate::utils::parse_timezone;
+use actix_web::{http::header, web::Query, HttpRequest};
+use bots::BotFilter;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use geoip::GeoIp;
+use hll::HyperLogLog;
+use ipnetwork::IpNetwork;
+use privacy::IpAnonymizer;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::UNIX_EPOCH;
+
+/// A visit to a shortened URL waiting to be recorded.
+pub struct VisitEvent {
+    /// Shortened URL part that is visited.
+    pub address: String,
+    /// Referer header of a visit.
+    pub referrer: Option<String>,
+    /// Referring domain, direct or unknown.
+    pub referrer_domain: String,
+    /// utm_source parameter of a request.
+    pub utm_source: Option<String>,
+    /// utm_medium parameter of a request.
+    pub utm_medium: Option<String>,
+    /// utm_campaign parameter of a request.
+    pub utm_campaign: Option<String>,
+    /// User-Agent header of a visit.
+    pub user_agent: Option<String>,
+    /// Browser family parsed from User-Agent header.
+    pub browser: &'static str,
+    /// Operating system family parsed from User-Agent header.
+    pub os: &'static str,
+    /// Device class parsed from User-Agent header.
+    pub device: &'static str,
+    /// Salted hash of client IP address.
+    pub ip_hash: Option<String>,
+    /// Country code of a client.
+    pub country: Option<String>,
+    /// Region of a client, like a state.
+    pub region: Option<String>,
+    /// City of a client.
+    pub city: Option<String>,
+    /// Indicates if a visit is made by a bot, crawler or prefetcher.
+    pub is_bot: bool,
+    /// Timestamp when visit is made.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returns hex encoded SHA-256 of a salted IP address.
+pub fn hash_ip(ip: &IpAddr, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(ip.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Parses an address of Forwarded or X-Forwarded-For header, which may be quoted or carry a
+/// port along with the address.
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    let addr = addr.trim().trim_matches('"');
+    addr.parse::<IpAddr>()
+        .or_else(|_| addr.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| {
+            addr.trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+        })
+        .ok()
+}
+
+/// Returns addresses a request is forwarded for, from the client to the last proxy. Forwarded
+/// header is preferred over X-Forwarded-For.
+fn forwarded_for(req: &HttpRequest) -> Vec<String> {
+    let values = |name: &str| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+    };
+    let forwarded: Vec<String> = values("forwarded")
+        .into_iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| value.trim().to_string())
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    values("x-forwarded-for")
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns IP address of a client.
+/// How does it work:
+/// 1. Take the address of the peer connected to tyto. It is the client unless it is one of the
+///    trusted proxies.
+/// 2. Otherwise walk addresses of Forwarded or X-Forwarded-For header from the last proxy back
+///    to the client, and take the first one which is not a trusted proxy. Addresses before it
+///    are given by the client, so they can not be trusted. The client is unknown when an
+///    address like `unknown` can not be parsed.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpNetwork]) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = |ip: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(*ip));
+    if !trusted(&peer) {
+        return Some(peer);
+    }
+
+    let mut client = peer;
+    for addr in forwarded_for(req).iter().rev() {
+        client = parse_ip(addr)?;
+        if !trusted(&client) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Returns domain of a referrer without www. prefix. It is direct when there is no referrer
+/// and unknown when the referrer is not an absolute URL with a host.
+pub fn referrer_domain(referrer: Option<&str>) -> String {
+    let referrer = match referrer.map(str::trim) {
+        Some(referrer) if !referrer.is_empty() => referrer,
+        _ => return "direct".to_string(),
+    };
+    match reqwest::Url::parse(referrer)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+    {
+        Some(host) => host.strip_prefix("www.").unwrap_or(&host).to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Returns normalized UTM parameters (source, medium, campaign) of a request.
+fn utm_parameters(req: &HttpRequest) -> (Option<String>, Option<String>, Option<String>) {
+    let mut params = Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|params| params.into_inner())
+        .unwrap_or_default();
+    let mut take = |name: &str| {
+        params
+            .remove(name)
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+            .map(|value| truncate(value, constants::analytics::DETAIL_WIDTH))
+    };
+    (take("utm_source"), take("utm_medium"), take("utm_campaign"))
+}
+
+/// Cuts a value down to at most a number of characters, so it fits its column.
+fn truncate(mut value: String, width: usize) -> String {
+    if let Some((end, _)) = value.char_indices().nth(width) {
+        value.truncate(end);
+    }
+    value
+}
+
+/// Returns value of a header as a string if it is present and valid.
+fn header_value(req: &HttpRequest, name: impl header::AsHeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+impl VisitEvent {
+    /// Captures a visit from a redirect request.
+    /// How does it work:
+    /// 1. Take referrer and user agent from headers. Stored details are cut down to the widths
+    ///    of their columns.
+    /// 2. Anonymize client IP address as configured. Forwarded and X-Forwarded-For headers are
+    ///    respected only for requests of trusted proxies.
+    /// 3. Parse user agent into browser, OS and device families.
+    /// 4. Take referring domain and UTM parameters of the short link request.
+    /// 5. Resolve location from client IP address. Take country from the configured header of
+    ///    a trusted proxy or CDN when GeoIP database can not resolve it.
+    /// 6. Classify a visit as a bot or human visit.
+    pub fn from_request(
+        req: &HttpRequest,
+        cfg: &AnalyticsConfig,
+        trusted_proxies: &[IpNetwork],
+        geoip: &GeoIp,
+        bots: &BotFilter,
+        ips: &IpAnonymizer,
+        address: &str,
+    ) -> Self {
+        let created_at = Utc::now();
+        let ip = client_ip(req, trusted_proxies);
+        let ip_hash = ip.as_ref().and_then(|ip| ips.anonymize(ip, created_at));
+        let location = ip.map(|ip| geoip.lookup(ip)).unwrap_or_default();
+
+        let country = location.country.or_else(|| {
+            cfg.country_header
+                .as_deref()
+                .and_then(|name| header_value(req, name))
+                .map(|country| country.trim().to_uppercase())
+                .filter(|country| country.len() == 2)
+        });
+
+        let user_agent = header_value(req, header::USER_AGENT);
+        let families = user_agent::parse(user_agent.as_deref().unwrap_or_default());
+        let referrer = header_value(req, header::REFERER);
+        let (utm_source, utm_medium, utm_campaign) = utm_parameters(req);
+        let is_bot = bots.is_bot(req, user_agent.as_deref(), families.device);
+
+        // Headers are limited only by the server, so details are cut down to their columns.
+        let detail_width = constants::analytics::DETAIL_WIDTH;
+        VisitEvent {
+            address: truncate(address.to_string(), constants::analytics::ADDRESS_WIDTH),
+            referrer_domain: truncate(referrer_domain(referrer.as_deref()), detail_width),
+            referrer: referrer
+                .map(|referrer| truncate(referrer, constants::analytics::REFERRER_WIDTH)),
+            utm_source,
+            utm_medium,
+            utm_campaign,
+            user_agent: user_agent
+                .map(|user_agent| truncate(user_agent, constants::analytics::USER_AGENT_WIDTH)),
+            browser: families.browser,
+            os: families.os,
+            device: families.device,
+            ip_hash,
+            country,
+            region: location.region.map(|region| truncate(region, detail_width)),
+            city: location.city.map(|city| truncate(city, detail_width)),
+            is_bot,
+            created_at,
+        }
+    }
+}
+
+/// Returns visitor sketches of days within [from, to] in a scope. Sketches of different
+/// URLs of a day are merged.
+pub async fn visitor_sketches(
+    db_connection: &Pool<Postgres>,
+    scope: Scope,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<(NaiveDate, HyperLogLog)>, Error> {
+    let (filter, id) = scope.filter();
+    // Filter comes from a fixed list, so it is safe to format it in.
+    let query = format!(
+        r#"SELECT day, registers FROM tyto.visitor_sketches
+           WHERE {} AND day >= $2 AND day <= $3
+           ORDER BY day"#,
+        filter
+    );
+    let rows = sqlx::query_as::<_, (NaiveDate, Vec<u8>)>(&query)
+        .bind(id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(db_connection)
+        .await?;
+
+    let mut sketches: Vec<(NaiveDate, HyperLogLog)> = Vec::new();
+    for (row_day, registers) in rows {
+        let sketch = HyperLogLog::from_bytes(registers);
+        match sketches.last_mut() {
+            Some((day, merged)) if *day == row_day => merged.merge(&sketch),
+            _ => sketches.push((row_day, sketch)),
+        }
+    }
+    Ok(sketches)
+}
+
+/// Returns estimated number of unique visitors out of sketches of days within [from, to).
+fn estimate_visitors(sketches: &[(NaiveDate, HyperLogLog)], from: NaiveDate, to: NaiveDate) -> i64 {
+    let mut merged = HyperLogLog::new();
+    sketches
+        .iter()
+        .filter(|(day, _)| *day >= from && *day < to)
+        .for_each(|(_, sketch)| merged.merge(sketch));
+    merged.estimate()
+}
+
+/// Returns the first day after a bucket starting on a day.
+fn bucket_end(start: NaiveDate, interval: StatsInterval) -> NaiveDate {
+    match interval {
+        StatsInterval::Hour | StatsInterval::Day => start + Duration::days(1),
+        StatsInterval::Week => start + Duration::days(7),
+        StatsInterval::Month => start
+            .with_day(1)
+            .and_then(|start| start.checked_add_months(Months::new(1)))
+            .unwrap_or(start),
+    }
+}
+
+/// A column of visits that can be broken down into counts per value.
+#[derive(Clone, Copy)]
+pub enum Dimension {
+    Browser,
+    Os,
+    Device,
+    Referrer,
+    UtmSource,
+    UtmMedium,
+    UtmCampaign,
+    Country,
+    Region,
+    City,
+}
+
+impl Dimension {
+    /// All the dimensions, in the order they are rolled up.
+    pub const ALL: [Dimension; 10] = [
+        Dimension::Browser,
+        Dimension::Os,
+        Dimension::Device,
+        Dimension::Referrer,
+        Dimension::UtmSource,
+        Dimension::UtmMedium,
+        Dimension::UtmCampaign,
+        Dimension::Country,
+        Dimension::Region,
+        Dimension::City,
+    ];
+
+    /// Returns name a dimension is stored with in daily rollups.
+    fn name(&self) -> &'static str {
+        match self {
+            Dimension::Browser => "browser",
+            Dimension::Os => "os",
+            Dimension::Device => "device",
+            Dimension::Referrer => "referrer",
+            Dimension::UtmSource => "utm_source",
+            Dimension::UtmMedium => "utm_medium",
+            Dimension::UtmCampaign => "utm_campaign",
+            Dimension::Country => "country",
+            Dimension::Region => "region",
+            Dimension::City => "city",
+        }
+    }
+
+    /// Returns expression over tyto.visits holding values of a dimension. Regions and cities
+    /// are qualified with their country, since their names are not unique.
+    fn column(&self) -> &'static str {
+        match self {
+            Dimension::Browser => "browser",
+            Dimension::Os => "os",
+            Dimension::Device => "device",
+            Dimension::Referrer => "referrer_domain",
+            Dimension::UtmSource => "utm_source",
+            Dimension::UtmMedium => "utm_medium",
+            Dimension::UtmCampaign => "utm_campaign",
+            Dimension::Country => "country",
+            Dimension::Region => {
+                "CASE WHEN region IS NULL THEN NULL ELSE concat_ws(', ', region, country) END"
+            }
+            Dimension::City => {
+                "CASE WHEN city IS NULL THEN NULL ELSE concat_ws(', ', city, region, country) END"
+            }
+        }
+    }
+
+    /// Returns value visits without a value are counted as. Visits recorded before a dimension
+    /// was tracked are unknown, while a missing UTM parameter means direct traffic.
+    fn fallback(&self) -> &'static str {
+        match self {
+            Dimension::UtmSource | Dimension::UtmMedium | Dimension::UtmCampaign => "direct",
+            _ => "unknown",
+        }
+    }
+}
+
+impl From<UtmParameter> for Dimension {
+    fn from(parameter: UtmParameter) -> Self {
+        match parameter {
+            UtmParameter::Source => Dimension::UtmSource,
+            UtmParameter::Medium => Dimension::UtmMedium,
+            UtmParameter::Campaign => Dimension::UtmCampaign,
+        }
+    }
+}
+
+impl From<GeoLevel> for Dimension {
+    fn from(level: GeoLevel) -> Self {
+        match level {
+            GeoLevel::Country => Dimension::Country,
+            GeoLevel::Region => Dimension::Region,
+            GeoLevel::City => Dimension::City,
+        }
+    }
+}
+
+/// Visits statistics are computed over.
+#[derive(Clone, Copy)]
+pub enum Scope {
+    /// Visits of a single URL.
+    Url(i64),
+    /// Visits of all URLs of a user.
+    User(i64),
+    /// Visits of all URLs of a campaign.
+    Campaign(i64),
+}
+
+impl Scope {
+    /// Returns condition over url_id selecting visits of a scope, with its ID as $1 parameter.
+    fn filter(&self) -> (&'static str, i64) {
+        match self {
+            Scope::Url(url_id) => ("url_id=$1", *url_id),
+            Scope::User(user_id) => (
+                "url_id IN (SELECT id FROM tyto.urls WHERE user_id=$1)",
+                *user_id,
+            ),
+            Scope::Campaign(campaign_id) => (
+                "url_id IN (SELECT id FROM tyto.urls WHERE campaign_id=$1)",
+                *campaign_id,
+            ),
+        }
+    }
+}
+
+/// Returns number of human visits per value of a dimension, most frequent first. Only visits
+/// made since `since` are counted when it is supplied, and only top `limit` values are returned
+/// when it is supplied, while total still counts all of them.
+///
+/// Days that are rolled up are counted from daily rollups, so `since` within one of them
+/// counts the whole UTC day.
+pub async fn breakdown(
+    db_connection: &Pool<Postgres>,
+    scope: Scope,
+    dimension: Dimension,
+    since: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<Breakdown, Error> {
+    let (filter, id) = scope.filter();
+    let rolled_until = rollups::rolled_until(db_connection).await?;
+    let since = since.unwrap_or_else(|| DateTime::from(UNIX_EPOCH));
+    // Column, fallback and filter come from fixed lists, so it is safe to format them in.
+    let query = format!(
+        r#"WITH counts AS (
+               SELECT "name", clicks AS count FROM tyto.visit_rollups_daily
+               WHERE {filter} AND dimension=$3 AND "day" >= ($5 AT TIME ZONE 'UTC')::date
+                 AND "day" < ($4 AT TIME ZONE 'UTC')::date
+               UNION ALL
+               SELECT COALESCE({column}, '{fallback}'), COUNT(*) FROM tyto.visits
+               WHERE {filter} AND NOT is_bot AND created_at >= GREATEST($4, $5) GROUP BY 1
+           )
+           SELECT "name", SUM(count)::int8 AS count, (SUM(SUM(count)) OVER ())::int8 AS total
+           FROM counts GROUP BY 1 ORDER BY 2 DESC, 1 LIMIT $2"#,
+        filter = filter,
+        column = dimension.column(),
+        fallback = dimension.fallback(),
+    );
+    let rows: Vec<(String, i64, i64)> = sqlx::query_as(&query)
+        .bind(id)
+        .bind(limit)
+        .bind(dimension.name())
+        .bind(rolled_until)
+        .bind(since)
+        .fetch_all(db_connection)
+        .await?;
+    let total = rows.first().map(|(_, _, total)| *total).unwrap_or(0);
+    let items = rows
+        .into_iter()
+        .map(|(name, count, _)| BreakdownItem { name, count })
+        .collect();
+    Ok(Breakdown { total, items })
+}
+
+/// Returns click statistics of visits within a scope bucketed by an interval.
+/// How does it work:
+/// 1. Fill defaults in and validate time range, interval and time zone.
+/// 2. Count clicks and visitors per bucket. Buckets are truncated in requested time zone so a
+///    day starts at local midnight, and a series of buckets fills gaps with zeroes. Rolled up
+///    days are counted from hourly rollups and the rest from raw visits. Rolled up hours are
+///    counted whole when they start within the range.
+/// 3. Count clicks over the whole range by adding buckets up.
+/// 4. Estimate unique visitors by merging daily visitor sketches of each bucket and of the
+///    whole range, so visitors are not counted twice across days. Sketches cover UTC days
+///    and are matched to buckets by date, so buckets in other time zones and ranges starting
+///    or ending within a day are approximate. Hourly buckets count distinct IP hashes of
+///    visits instead, since sketches are not kept per hour. Rolled up hours of several URLs add
+///    their counts up, so visitors of more than one of them are counted more than once.
+///
+/// Clicks of bots are counted separately and are not part of clicks or unique visitors.
+pub async fn stats(
+    db_connection: &Pool<Postgres>,
+    scope: Scope,
+    query: StatsQuery,
+) -> Result<Stats, Error> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query
+        .from
+        .unwrap_or_else(|| to - Duration::days(constants::analytics::DEFAULT_STATS_DAYS));
+    let interval = query.interval.unwrap_or(StatsInterval::Day);
+    let tz_name = query.tz.unwrap_or_else(|| "UTC".to_string());
+    let tz = parse_timezone(&tz_name)?;
+
+    if from >= to {
+        return Err(Error::InvalidTimeRange);
+    }
+    // One more bucket for a range not aligned to bucket boundaries.
+    let buckets = (to - from).num_seconds() / interval.min_seconds() + 1;
+    if buckets > constants::analytics::MAX_STATS_BUCKETS {
+        return Err(Error::TooManyBuckets {
+            max: constants::analytics::MAX_STATS_BUCKETS,
+        });
+    }
+
+    let rolled_until = rollups::rolled_until(db_connection).await?;
+    let (filter, id) = scope.filter();
+    // Filter comes from a fixed list, so it is safe to format it in.
+    let query = format!(
+        r#"WITH counts AS (
+               SELECT bucket, SUM(clicks)::int8 AS clicks, SUM(bot_clicks)::int8 AS bot_clicks,
+                      SUM(unique_visitors)::int8 AS unique_visitors
+               FROM (
+                   SELECT date_trunc($2, "hour" AT TIME ZONE $5) AS bucket,
+                          clicks, bot_clicks, unique_visitors
+                   FROM tyto.visit_rollups_hourly
+                   WHERE {filter} AND "hour" >= $3 AND "hour" < LEAST($4, $6::timestamptz)
+                   UNION ALL
+                   SELECT date_trunc($2, created_at AT TIME ZONE $5),
+                          COUNT(*) FILTER (WHERE NOT is_bot),
+                          COUNT(*) FILTER (WHERE is_bot),
+                          COUNT(DISTINCT ip_hash) FILTER (WHERE NOT is_bot)
+                   FROM tyto.visits
+                   WHERE {filter} AND created_at >= GREATEST($3, $6::timestamptz) AND created_at < $4
+                   GROUP BY 1
+               ) AS sources
+               GROUP BY 1
+           )
+           SELECT series.bucket AT TIME ZONE $5,
+                  COALESCE(counts.clicks, 0),
+                  COALESCE(counts.bot_clicks, 0),
+                  COALESCE(counts.unique_visitors, 0)
+           FROM generate_series(
+               date_trunc($2, $3 AT TIME ZONE $5),
+               ($4 AT TIME ZONE $5) - interval '1 microsecond',
+               ('1 ' || $2)::interval
+           ) AS series(bucket)
+           LEFT JOIN counts ON counts.bucket = series.bucket
+           ORDER BY series.bucket"#,
+        filter = filter
+    );
+    let rows = sqlx::query_as::<_, (DateTime<Utc>, i64, i64, i64)>(&query)
+        .bind(id)
+        .bind(interval.as_str())
+        .bind(from)
+        .bind(to)
+        .bind(&tz_name)
+        .bind(rolled_until)
+        .fetch_all(db_connection)
+        .await?;
+    let clicks = rows.iter().map(|(_, clicks, _, _)| clicks).sum();
+    let bot_clicks = rows.iter().map(|(_, _, bot_clicks, _)| bot_clicks).sum();
+
+    let from_day = from.with_timezone(&tz).date_naive();
+    let to_day = (to - Duration::microseconds(1))
+        .with_timezone(&tz)
+        .date_naive();
+    let first_day = rows
+        .first()
+        .map(|(start, _, _, _)| start.with_timezone(&tz).date_naive())
+        .unwrap_or(from_day);
+    let sketches = visitor_sketches(db_connection, scope, first_day, to_day).await?;
+
+    let buckets = rows
+        .into_iter()
+        .map(|(start, clicks, bot_clicks, unique_visitors)| {
+            let start = start.with_timezone(&tz);
+            let unique_visitors = match interval {
+                StatsInterval::Hour => unique_visitors,
+                _ => {
+                    let day = start.date_naive();
+                    estimate_visitors(&sketches, day, bucket_end(day, interval))
+                }
+            };
+            StatsBucket {
+                start: start.to_rfc3339(),
+                clicks,
+                bot_clicks,
+                unique_visitors,
+            }
+        })
+        .collect();
+    let unique_visitors = estimate_visitors(&sketches, from_day, to_day + Duration::days(1));
+
+    Ok(Stats {
+        from,
+        to,
+        interval,
+        tz: tz_name,
+        clicks,
+        bot_clicks,
+        unique_visitors,
+        buckets,
+    })
+}
+
+/// Returns clicks of every URL within a scope made in [from, to), most clicked first. URLs
+/// without clicks are included. Rolled up hours are counted whole when they start within the
+/// range, like [stats] does.
+pub async fn clicks_per_url(
+    db_connection: &Pool<Postgres>,
+    scope: Scope,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<UrlClicks>, Error> {
+    let rolled_until = rollups::rolled_until(db_connection).await?;
+    let (filter, id) = scope.filter();
+    // Filter comes from a fixed list, so it is safe to format it in.
+    let query = format!(
+        r#"WITH counts AS (
+               SELECT url_id, clicks, bot_clicks FROM tyto.visit_rollups_hourly
+               WHERE {filter} AND "hour" >= $2 AND "hour" < LEAST($3, $4::timestamptz)
+               UNION ALL
+               SELECT url_id, COUNT(*) FILTER (WHERE NOT is_bot), COUNT(*) FILTER (WHERE is_bot)
+               FROM tyto.visits
+               WHERE {filter} AND created_at >= GREATEST($2, $4::timestamptz) AND created_at < $3
+               GROUP BY 1
+           )
+           SELECT url_id, members.address, COALESCE(SUM(counts.clicks), 0)::int8 AS clicks,
+                  COALESCE(SUM(counts.bot_clicks), 0)::int8
+           FROM (SELECT id AS url_id, address FROM tyto.urls) AS members
+           LEFT JOIN counts USING (url_id)
+           WHERE {filter}
+           GROUP BY url_id, members.address
+           ORDER BY clicks DESC, url_id"#,
+        filter = filter
+    );
+    let rows = sqlx::query_as::<_, (i64, String, i64, i64)>(&query)
+        .bind(id)
+        .bind(from)
+        .bind(to)
+        .bind(rolled_until)
+        .fetch_all(db_connection)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(url_id, address, clicks, bot_clicks)| UrlClicks {
+            url_id,
+            address,
+            clicks,
+            bot_clicks,
+        })
+        .collect())
+}
+
+/// Returns number of conversions per URL and goal within a scope reported in [from, to), as
+/// (url_id, goal_id, conversions).
+pub async fn conversions_per_url(
+    db_connection: &Pool<Postgres>,
+    scope: Scope,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<(i64, i64, i64)>, Error> {
+    let (filter, id) = scope.filter();
+    // Filter comes from a fixed list, so it is safe to format it in.
+    let query = format!(
+        r#"SELECT url_id, goal_id, COUNT(*) FROM tyto.conversions
+           WHERE {} AND created_at >= $2 AND created_at < $3
+           GROUP BY 1, 2"#,
+        filter
+    );
+    let rows = sqlx::query_as::<_, (i64, i64, i64)>(&query)
+        .bind(id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(db_connection)
+        .await?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_truncated_to_their_width_in_characters() {
+        assert_eq!(truncate("short".to_string(), 255), "short");
+        assert_eq!(truncate("abcdef".to_string(), 3), "abc");
+        // Columns count characters, so multi-byte ones are not cut in half.
+        assert_eq!(truncate("ääää".to_string(), 2), "ää");
+        let referrer = format!("https://example.com/{}", "a".repeat(3000));
+        let truncated = truncate(referrer, constants::analytics::REFERRER_WIDTH);
+        assert_eq!(
+            truncated.chars().count(),
+            constants::analytics::REFERRER_WIDTH
+        );
+    }
+
+    #[test]
+    fn referrers_are_reduced_to_domains() {
+        assert_eq!(referrer_domain(None), "direct");
+        assert_eq!(referrer_domain(Some("")), "direct");
+        assert_eq!(referrer_domain(Some("  ")), "direct");
+        assert_eq!(
+            referrer_domain(Some("https://www.Example.com/path?q=1")),
+            "example.com"
+        );
+        assert_eq!(
+            referrer_domain(Some(" http://news.ycombinator.com:8080/item ")),
+            "news.ycombinator.com"
+        );
+        // Only a leading www. is dropped.
+        assert_eq!(
+            referrer_domain(Some("https://www.www2.example.org")),
+            "www2.example.org"
+        );
+        assert_eq!(
+            referrer_domain(Some("https://wwwexample.com")),
+            "wwwexample.com"
+        );
+        assert_eq!(referrer_domain(Some("http://192.0.2.1/")), "192.0.2.1");
+        assert_eq!(referrer_domain(Some("example.com/page")), "unknown");
+        assert_eq!(referrer_domain(Some("/relative/path")), "unknown");
+        assert_eq!(
+            referrer_domain(Some("mailto:someone@example.com")),
+            "unknown"
+        );
+        assert_eq!(referrer_domain(Some("not a url")), "unknown");
+    }
+
+    fn request(peer: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut req = actix_web::test::TestRequest::default().peer_addr(peer.parse().unwrap());
+        for (name, value) in headers {
+            req = req.insert_header((*name, *value));
+        }
+        req.to_http_request()
+    }
+
+    fn ip(addr: &str) -> Option<IpAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn forwarded_headers_of_untrusted_peers_are_ignored() {
+        let req = request("203.0.113.7:5000", &[("x-forwarded-for", "198.51.100.1")]);
+        assert_eq!(client_ip(&req, &[]), ip("203.0.113.7"));
+        let proxies = ["10.0.0.0/8".parse().unwrap()];
+        assert_eq!(client_ip(&req, &proxies), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn client_is_the_last_address_not_of_a_trusted_proxy() {
+        let proxies = ["10.0.0.0/8".parse().unwrap(), "192.0.2.1".parse().unwrap()];
+        // The first address is made up by the client.
+        let req = request(
+            "10.0.0.2:5000",
+            &[("x-forwarded-for", "1.1.1.1, 198.51.100.1, 192.0.2.1")],
+        );
+        assert_eq!(client_ip(&req, &proxies), ip("198.51.100.1"));
+
+        let req = request("10.0.0.2:5000", &[("x-forwarded-for", "10.0.0.9")]);
+        assert_eq!(client_ip(&req, &proxies), ip("10.0.0.9"));
+        let req = request("10.0.0.2:5000", &[]);
+        assert_eq!(client_ip(&req, &proxies), ip("10.0.0.2"));
+        let req = request("10.0.0.2:5000", &[("x-forwarded-for", "unknown")]);
+        assert_eq!(client_ip(&req, &proxies), None);
+    }
+
+    #[test]
+    fn forwarded_header_is_preferred() {
+        let proxies = ["10.0.0.0/8".parse().unwrap()];
+        let req = request(
+            "10.0.0.2:5000",
+            &[
+                (
+                    "forwarded",
+                    r#"for=198.51.100.1;proto=https, For="[2001:db8::17]:4711""#,
+                ),
+                ("x-forwarded-for", "198.51.100.2"),
+            ],
+        );
+        assert_eq!(client_ip(&req, &proxies), ip("2001:db8::17"));
+        let req = request("10.0.0.2:5000", &[("forwarded", "for=198.51.100.1:80")]);
+        assert_eq!(client_ip(&req, &proxies), ip("198.51.100.1"));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{BotSignature, CreateBotSignatureRequest};
+use actix_web::{web, HttpRequest};
+use std::sync::RwLock;
+
+/// Tells visits of bots, crawlers, link previews, uptime checkers and prefetchers apart from
+/// visits of humans. Signatures are kept in the bot signature repository and cached in memory.
+pub struct BotFilter {
+    state: web::Data<State>,
+    patterns: RwLock<Vec<String>>,
+}
+
+impl BotFilter {
+    /// Creates a new instance of [BotFilter] and loads signatures.
+    pub async fn new(state: web::Data<State>) -> Result<Self, Error> {
+        let filter = BotFilter {
+            state,
+            patterns: RwLock::new(Vec::new()),
+        };
+        filter.reload().await?;
+        Ok(filter)
+    }
+
+    /// Loads signatures from the repository again.
+    async fn reload(&self) -> Result<(), Error> {
+        let patterns = self
+            .state
+            .bot_signatures
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|signature| signature.pattern)
+            .collect();
+        *self.patterns.write().unwrap() = patterns;
+        Ok(())
+    }
+
+    /// Returns true if a request is made by a bot.
+    /// How does it work:
+    /// A request is made by a bot when any of these is true.
+    /// 1. It has no User-Agent header. Browsers always send one.
+    /// 2. User agent parser recognizes a crawler. `device` is the parsed device class.
+    /// 3. It is a prefetch or a preview announced through Purpose, Sec-Purpose, X-Moz or
+    ///    X-Purpose headers. A human may follow it later with a real visit.
+    /// 4. User agent contains one of the signatures.
+    pub fn is_bot(&self, req: &HttpRequest, user_agent: Option<&str>, device: &str) -> bool {
+        let user_agent = match user_agent.map(str::trim) {
+            Some(user_agent) if !user_agent.is_empty() => user_agent.to_lowercase(),
+            _ => return true,
+        };
+        if device == "bot" {
+            return true;
+        }
+
+        let prefetch = ["purpose", "sec-purpose", "x-moz", "x-purpose"]
+            .iter()
+            .filter_map(|name| req.headers().get(*name))
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| {
+                let value = value.to_lowercase();
+                value.contains("prefetch") || value.contains("preview")
+            });
+        if prefetch {
+            return true;
+        }
+
+        self.patterns
+            .read()
+            .unwrap()
+            .iter()
+            .any(|pattern| user_agent.contains(pattern.as_str()))
+    }
+
+    /// Returns all the signatures.
+    pub async fn get_all(&self) -> Result<Vec<BotSignature>, Error> {
+        self.state.bot_signatures.get_all().await
+    }
+
+    /// Adds a signature. Patterns are matched case insensitively, so they are stored in
+    /// lowercase. Visits recorded before are not classified again.
+    pub async fn create(&self, request: CreateBotSignatureRequest) -> Result<BotSignature, Error> {
+        let pattern = request.pattern.trim().to_lowercase();
+        if pattern.chars().count() < 3 || pattern.chars().count() > 255 {
+            return Err(Error::InvalidBotSignature);
+        }
+
+        let signature = self
+            .state
+            .bot_signatures
+            .create(&pattern, request.description.as_deref())
+            .await?;
+
+        self.reload().await?;
+        Ok(signature)
+    }
+
+    /// Deletes a signature.
+    pub async fn delete(&self, id: i64) -> Result<(), Error> {
+        self.state.bot_signatures.delete(id).await?;
+        self.reload().await
+    }
+}
@@ -0,0 +1,430 @@
+use crate::analytics::hll::{self, HyperLogLog};
+use crate::analytics::live::LiveClicks;
+use crate::analytics::VisitEvent;
+use crate::error::Error;
+use crate::state::State;
+use crate::types::WebhookEvent;
+use crate::webhooks::WebhookManager;
+use actix_web::web;
+use chrono::NaiveDate;
+use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// A visit of a URL waiting in [ClickBuffer].
+pub struct BufferedVisit {
+    /// ID of the visited URL.
+    pub url_id: i64,
+    /// ID of a user the URL belongs to.
+    pub user_id: i64,
+    /// The visit.
+    pub visit: VisitEvent,
+    /// Indicates if details of the visit are stored. Only visit counts are updated otherwise.
+    pub stored: bool,
+    /// Identifier of the click appended to the target, when the URL tracks conversions.
+    pub click_id: Option<String>,
+}
+
+/// Visit counts of a URL within a batch.
+#[derive(Default)]
+pub struct VisitCounts {
+    /// ID of a user the URL belongs to.
+    pub user_id: i64,
+    /// Address of the URL.
+    pub address: String,
+    /// Number of visits of humans.
+    pub visits: i32,
+    /// Number of visits of bots.
+    pub bot_visits: i32,
+}
+
+/// Buffered visits of a flush added up to be written by [crate::core::traits::VisitStore].
+pub struct VisitBatch<'a> {
+    /// Visit counts by URL ID.
+    pub counts: HashMap<i64, VisitCounts>,
+    /// Visits whose details are stored.
+    pub stored: Vec<&'a BufferedVisit>,
+    /// Visitors of humans by URL ID and UTC day of visits.
+    pub sketches: HashMap<(i64, NaiveDate), HyperLogLog>,
+}
+
+impl<'a> VisitBatch<'a> {
+    /// Adds up visits of a batch.
+    fn new(batch: &'a [BufferedVisit]) -> Self {
+        let mut counts: HashMap<i64, VisitCounts> = HashMap::new();
+        let mut stored = Vec::new();
+        let mut sketches: HashMap<(i64, NaiveDate), HyperLogLog> = HashMap::new();
+        for buffered in batch {
+            let counts = counts.entry(buffered.url_id).or_default();
+            counts.user_id = buffered.user_id;
+            counts.address = buffered.visit.address.clone();
+            if buffered.stored {
+                stored.push(buffered);
+            }
+            if buffered.visit.is_bot {
+                counts.bot_visits += 1;
+                continue;
+            }
+            counts.visits += 1;
+            if !buffered.stored {
+                continue;
+            }
+            if let Some(hash) = buffered
+                .visit
+                .ip_hash
+                .as_deref()
+                .and_then(hll::visitor_hash)
+            {
+                sketches
+                    .entry((buffered.url_id, buffered.visit.created_at.date_naive()))
+                    .or_default()
+                    .insert(hash);
+            }
+        }
+        VisitBatch {
+            counts,
+            stored,
+            sketches,
+        }
+    }
+}
+
+/// Buffers visits in memory and writes them to the visit repository in batches, so redirects do
+/// not wait for database and popular URLs do not become a point of row lock contention.
+/// How does it work:
+/// 1. Redirects push visits with [ClickBuffer::push], which publishes them to [LiveClicks].
+/// 2. A background task started with [ClickBuffer::start] flushes them every configured
+///    interval, or sooner when the configured batch size is reached.
+/// 3. A flush adds up visit counts and visitors per URL in a [VisitBatch], which the repository
+///    writes all at once. Then it delivers link.click_threshold events for thresholds crossed
+///    by the batch.
+/// 4. A batch failing because of some of its visits, like a value a column can not hold, is
+///    written in halves until those visits are found and dropped. Visits of a flush failing
+///    because database is unavailable are put back and tried again with the next one.
+/// 5. [ClickBuffer::drain] flushes what is left when the server shuts down.
+pub struct ClickBuffer {
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+    live: web::Data<LiveClicks>,
+    pending: Mutex<Vec<BufferedVisit>>,
+    /// Makes sure only one flush runs at a time.
+    flushing: tokio::sync::Mutex<()>,
+    /// Wakes the background task up when the batch size is reached.
+    full: Notify,
+}
+
+impl ClickBuffer {
+    /// Creates a new instance of [ClickBuffer]
+    pub fn new(
+        state: web::Data<State>,
+        webhooks: web::Data<WebhookManager>,
+        live: web::Data<LiveClicks>,
+    ) -> Self {
+        ClickBuffer {
+            state,
+            webhooks,
+            live,
+            pending: Mutex::new(Vec::new()),
+            flushing: tokio::sync::Mutex::new(()),
+            full: Notify::new(),
+        }
+    }
+
+    /// Starts flushing buffered visits periodically on a separate tokio task.
+    pub fn start(buffer: web::Data<ClickBuffer>) {
+        let period = Duration::from_millis(buffer.state.config.analytics.flush_interval_ms);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = buffer.full.notified() => {},
+                }
+                // TODO: Use log here
+                if let Err(e) = buffer.flush().await {
+                    println!("Error in flushing visits: {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Publishes a visit to live click streams and adds it to the buffer.
+    pub fn push(&self, visit: BufferedVisit) {
+        self.live
+            .publish(visit.url_id, visit.user_id, &visit.visit, visit.stored);
+        let mut pending = self.pending.lock().unwrap();
+        pending.push(visit);
+        if pending.len() >= self.state.config.analytics.flush_batch_size {
+            self.full.notify_one();
+        }
+    }
+
+    /// Writes all the buffered visits. Called when the server is shutting down.
+    pub async fn drain(&self) -> Result<(), Error> {
+        let count = self.pending.lock().unwrap().len();
+        // TODO: Use log here
+        println!("Flushing {} buffered visits", count);
+        self.flush().await
+    }
+
+    /// Writes buffered visits in a batch.
+    async fn flush(&self) -> Result<(), Error> {
+        let _flushing = self.flushing.lock().await;
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let written = write_in_parts(batch.len(), |part| self.write(&batch[part])).await;
+        if written.dropped > 0 {
+            // TODO: Use log here
+            println!("Dropped {} visits that can not be written", written.dropped);
+        }
+        let unavailable = written.unavailable.map(|(parts, e)| {
+            let unwritten = batch
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| parts.iter().any(|part| part.contains(index)))
+                .map(|(_, visit)| visit)
+                .collect();
+            self.requeue(unwritten);
+            e
+        });
+
+        for (user_id, data) in written.results.into_iter().flatten() {
+            // TODO: Use log here
+            if let Err(e) = self
+                .webhooks
+                .emit(user_id, WebhookEvent::LinkClickThreshold, data)
+                .await
+            {
+                println!("Error: {:?}", e);
+            }
+        }
+        match unavailable {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Puts visits of a failed flush back in front of visits buffered meanwhile.
+    fn requeue(&self, batch: Vec<BufferedVisit>) {
+        let mut pending = self.pending.lock().unwrap();
+        let max = self.state.config.analytics.max_buffered_visits;
+        let dropped = requeue(batch, &mut pending, max);
+        if dropped > 0 {
+            // TODO: Use log here
+            println!("Dropped {} buffered visits", dropped);
+        }
+    }
+
+    /// Writes a batch of visits to the visit repository. Returns link.click_threshold event
+    /// payloads along with their user IDs for thresholds crossed by the batch.
+    async fn write(&self, batch: &[BufferedVisit]) -> Result<Vec<(i64, serde_json::Value)>, Error> {
+        let batch = VisitBatch::new(batch);
+        let visit_counts = self.state.visits.record(&batch).await?;
+
+        // URLs deleted since their visits were buffered have no visit count.
+        let mut crossed = Vec::new();
+        for (url_id, visit_count) in visit_counts {
+            let counts = &batch.counts[&url_id];
+            let previous = visit_count - counts.visits;
+            let thresholds = &self.state.config.webhooks.click_thresholds;
+            for threshold in crossed_thresholds(thresholds, previous, visit_count) {
+                let data = json!({
+                    "id": url_id,
+                    "address": counts.address,
+                    "threshold": threshold,
+                    "visit_count": visit_count,
+                });
+                crossed.push((counts.user_id, data));
+            }
+        }
+        Ok(crossed)
+    }
+}
+
+/// Returns click thresholds crossed when visit count of a URL grows from previous to
+/// visit_count.
+fn crossed_thresholds(thresholds: &[i32], previous: i32, visit_count: i32) -> Vec<i32> {
+    thresholds
+        .iter()
+        .copied()
+        .filter(|threshold| previous < *threshold && *threshold <= visit_count)
+        .collect()
+}
+
+/// Puts items of a failed flush back in front of items buffered meanwhile and returns how many
+/// of the oldest are dropped to keep no more than max.
+fn requeue<T>(mut batch: Vec<T>, pending: &mut Vec<T>, max: usize) -> usize {
+    batch.append(pending);
+    let dropped = batch.len().saturating_sub(max);
+    batch.drain(..dropped);
+    *pending = batch;
+    dropped
+}
+
+/// Indicates if an error means database can not be reached or is overloaded, so a batch may be
+/// written once it recovers. Other errors are caused by the batch itself.
+fn is_unavailable(e: &Error) -> bool {
+    let source = match e {
+        Error::Database { source } => source,
+        _ => return false,
+    };
+    match source {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => true,
+        // Connection exceptions, transaction rollbacks like deadlocks, insufficient resources
+        // and operator intervention like a shutdown.
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            ["08", "40", "53", "57P"]
+                .iter()
+                .any(|class| code.starts_with(class))
+        }),
+        _ => false,
+    }
+}
+
+/// Outcome of [write_in_parts].
+struct Written<R> {
+    /// Results of parts written, in order.
+    results: Vec<R>,
+    /// Number of items dropped because they can not be written.
+    dropped: usize,
+    /// Parts not written because database became unavailable, along with its error.
+    unavailable: Option<(Vec<Range<usize>>, Error)>,
+}
+
+/// Writes items in parts given as ranges of their indexes.
+/// How does it work:
+/// 1. Write all of them at once.
+/// 2. Split a part failing because of its items in halves and write them in order, until the
+///    items that can not be written are found. Those are dropped.
+/// 3. Stop when database is unavailable, returning the parts not written yet.
+async fn write_in_parts<R, F, Fut>(len: usize, mut write: F) -> Written<R>
+where
+    F: FnMut(Range<usize>) -> Fut,
+    Fut: Future<Output = Result<R, Error>>,
+{
+    let mut written = Written {
+        results: Vec::new(),
+        dropped: 0,
+        unavailable: None,
+    };
+    // Parts are taken from the end, so halves are pushed in reverse order.
+    let mut parts = Vec::new();
+    parts.push(0..len);
+    while let Some(part) = parts.pop() {
+        match write(part.clone()).await {
+            Ok(result) => written.results.push(result),
+            Err(e) if is_unavailable(&e) => {
+                parts.push(part);
+                written.unavailable = Some((parts, e));
+                break;
+            }
+            Err(e) if part.len() == 1 => {
+                // TODO: Use log here
+                println!("Error in writing a visit: {:?}", e);
+                written.dropped += 1;
+            }
+            Err(_) => {
+                let middle = part.start + part.len() / 2;
+                parts.push(middle..part.end);
+                parts.push(part.start..middle);
+            }
+        }
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::{ready, Ready};
+
+    /// Writes a part unless it holds a zero, failing as database would on a value too long for
+    /// its column. Fails as unavailable database once as many parts as available are written.
+    fn write(
+        items: &[i32],
+        part: Range<usize>,
+        available: &mut usize,
+    ) -> Ready<Result<Vec<i32>, Error>> {
+        if *available == 0 {
+            return ready(Err(Error::Database {
+                source: sqlx::Error::PoolTimedOut,
+            }));
+        }
+        if items[part.clone()].contains(&0) {
+            return ready(Err(Error::Database {
+                source: sqlx::Error::RowNotFound,
+            }));
+        }
+        *available -= 1;
+        ready(Ok(items[part].to_vec()))
+    }
+
+    #[test]
+    fn crossed_thresholds_are_reached_within_a_batch() {
+        let thresholds = [100, 1000, 10000];
+        assert_eq!(crossed_thresholds(&thresholds, 0, 99), Vec::<i32>::new());
+        assert_eq!(crossed_thresholds(&thresholds, 99, 100), vec![100]);
+        assert_eq!(crossed_thresholds(&thresholds, 100, 101), Vec::<i32>::new());
+        assert_eq!(crossed_thresholds(&thresholds, 50, 5000), vec![100, 1000]);
+    }
+
+    #[test]
+    fn requeued_items_go_first_and_oldest_are_dropped() {
+        let mut pending = vec![4, 5];
+        assert_eq!(requeue(vec![1, 2, 3], &mut pending, 10), 0);
+        assert_eq!(pending, vec![1, 2, 3, 4, 5]);
+
+        let mut pending = vec![4, 5];
+        assert_eq!(requeue(vec![1, 2, 3], &mut pending, 3), 2);
+        assert_eq!(pending, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn only_unavailable_database_is_retried() {
+        let unavailable = |source| is_unavailable(&Error::Database { source });
+        assert!(unavailable(sqlx::Error::PoolTimedOut));
+        assert!(unavailable(sqlx::Error::Io(
+            std::io::ErrorKind::BrokenPipe.into()
+        )));
+        assert!(!unavailable(sqlx::Error::RowNotFound));
+        assert!(!is_unavailable(&Error::UrlNotFound));
+    }
+
+    #[tokio::test]
+    async fn items_that_can_not_be_written_are_dropped() {
+        let items = [1, 2, 0, 4, 5, 0, 7, 8];
+        let mut available = usize::MAX;
+        let written = write_in_parts(items.len(), |part| write(&items, part, &mut available)).await;
+
+        assert_eq!(written.dropped, 2);
+        assert!(written.unavailable.is_none());
+        let results: Vec<i32> = written.results.into_iter().flatten().collect();
+        assert_eq!(results, vec![1, 2, 4, 5, 7, 8]);
+    }
+
+    #[tokio::test]
+    async fn items_are_kept_when_database_becomes_unavailable() {
+        let items = [1, 0, 3, 4];
+        let mut available = 1;
+        let written = write_in_parts(items.len(), |part| write(&items, part, &mut available)).await;
+
+        assert_eq!(written.dropped, 0);
+        assert_eq!(written.results, vec![vec![1]]);
+        let (mut parts, e) = written.unavailable.unwrap();
+        parts.sort_by_key(|part| part.start);
+        assert_eq!(parts, vec![1..2, 2..4]);
+        assert!(is_unavailable(&e));
+    }
+}
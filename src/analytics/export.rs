@@ -0,0 +1,350 @@
+use crate::analytics::{hll::HyperLogLog, rollups, Scope};
+use crate::constants::analytics::{EXPORT_CHUNKS_IN_FLIGHT, EXPORT_CHUNK_SIZE};
+use crate::error::Error;
+use crate::types::{ExportFormat, ExportedStats, StatsInterval, Visit};
+use actix_web::web::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream, TryStreamExt};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::sync::mpsc;
+
+/// A record that can be exported as a CSV row or an NDJSON line.
+trait ExportRow: Serialize {
+    /// Names of CSV columns.
+    const HEADER: &'static [&'static str];
+
+    /// Returns values of CSV columns in order of [ExportRow::HEADER].
+    fn fields(&self) -> Vec<String>;
+}
+
+/// Returns an optional value as a CSV field, empty when absent.
+fn text(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+impl ExportRow for Visit {
+    const HEADER: &'static [&'static str] = &[
+        "id",
+        "url_id",
+        "address",
+        "referrer",
+        "referrer_domain",
+        "utm_source",
+        "utm_medium",
+        "utm_campaign",
+        "browser",
+        "os",
+        "device",
+        "ip_hash",
+        "country",
+        "region",
+        "city",
+        "is_bot",
+        "created_at",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.url_id.to_string(),
+            self.address.clone(),
+            text(&self.referrer),
+            text(&self.referrer_domain),
+            text(&self.utm_source),
+            text(&self.utm_medium),
+            text(&self.utm_campaign),
+            text(&self.browser),
+            text(&self.os),
+            text(&self.device),
+            text(&self.ip_hash),
+            text(&self.country),
+            text(&self.region),
+            text(&self.city),
+            self.is_bot.to_string(),
+            self.created_at.to_rfc3339(),
+        ]
+    }
+}
+
+impl ExportRow for ExportedStats {
+    const HEADER: &'static [&'static str] = &[
+        "url_id",
+        "address",
+        "start",
+        "clicks",
+        "bot_clicks",
+        "unique_visitors",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.url_id.to_string(),
+            self.address.clone(),
+            self.start.to_rfc3339(),
+            self.clicks.to_string(),
+            self.bot_clicks.to_string(),
+            self.unique_visitors.to_string(),
+        ]
+    }
+}
+
+/// Returns a CSV field quoted when needed. Values that spreadsheets would evaluate as formulas
+/// are prefixed with an apostrophe, since referrers and UTM parameters come from visitors.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Returns a CSV row terminated by CRLF.
+fn csv_line<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+    let mut line = fields.map(csv_field).collect::<Vec<String>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// Sends encoded rows to a response in chunks of about [EXPORT_CHUNK_SIZE].
+struct ChunkWriter {
+    sender: mpsc::Sender<Result<Bytes, Error>>,
+    format: ExportFormat,
+    chunk: String,
+}
+
+impl ChunkWriter {
+    /// Writes a row. Returns false when the client is gone.
+    async fn write<T: ExportRow>(&mut self, row: &T) -> bool {
+        match self.format {
+            ExportFormat::Csv => self
+                .chunk
+                .push_str(&csv_line(row.fields().iter().map(String::as_str))),
+            ExportFormat::Ndjson => {
+                self.chunk.push_str(&serde_json::to_string(row).unwrap());
+                self.chunk.push('\n');
+            }
+        }
+        if self.chunk.len() < EXPORT_CHUNK_SIZE {
+            return true;
+        }
+        let chunk = std::mem::take(&mut self.chunk);
+        self.sender.send(Ok(Bytes::from(chunk))).await.is_ok()
+    }
+
+    /// Sends what is left, or an error that aborts the response.
+    async fn finish(self, result: Result<(), Error>) {
+        let last = match result {
+            Ok(()) if self.chunk.is_empty() => return,
+            Ok(()) => Ok(Bytes::from(self.chunk)),
+            Err(e) => {
+                // TODO: Use log here
+                println!("Error in exporting analytics: {:?}", e);
+                Err(e)
+            }
+        };
+        let _ = self.sender.send(last).await;
+    }
+}
+
+/// Starts exporting rows of type T on a separate tokio task and returns the encoded stream.
+/// How does it work:
+/// 1. `rows` gets a writer and reads rows from database one by one, writing each of them.
+/// 2. Encoded rows are sent in chunks over a bounded channel, so reading waits while a client
+///    is slow and at most a few chunks are held in memory.
+/// 3. Reading stops when the client disconnects.
+/// 4. An error ends the stream with an error, which aborts the response so the client does
+///    not mistake a partial export for a complete one.
+fn spawn_export<T, F, Fut>(
+    format: ExportFormat,
+    rows: F,
+) -> impl Stream<Item = Result<Bytes, Error>>
+where
+    T: ExportRow,
+    F: FnOnce(ChunkWriter) -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(EXPORT_CHUNKS_IN_FLIGHT);
+    let mut chunk = String::new();
+    if format == ExportFormat::Csv {
+        chunk.push_str(&csv_line(T::HEADER.iter().copied()));
+    }
+    tokio::spawn(rows(ChunkWriter {
+        sender,
+        format,
+        chunk,
+    }));
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+/// Returns a stream of visits within a scope made in [from, to), oldest first. Only retained
+/// visits are exported.
+pub fn visits(
+    db_connection: Pool<Postgres>,
+    scope: Scope,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, Error>> {
+    spawn_export::<Visit, _, _>(format, move |mut writer| async move {
+        let (filter, id) = scope.filter();
+        // Filter comes from a fixed list, so it is safe to format it in.
+        let query = format!(
+            r#"SELECT id, url_id, address, referrer, referrer_domain, utm_source, utm_medium, utm_campaign,
+                      browser, os, device, ip_hash, country, region, city, is_bot, created_at
+               FROM tyto.visits WHERE {} AND created_at >= $2 AND created_at < $3
+               ORDER BY created_at, id"#,
+            filter
+        );
+        let result = async {
+            let mut rows = sqlx::query_as::<_, Visit>(&query)
+                .bind(id)
+                .bind(from)
+                .bind(to)
+                .fetch(&db_connection);
+            while let Some(visit) = rows.try_next().await? {
+                if !writer.write(&visit).await {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        .await;
+        writer.finish(result).await;
+    })
+}
+
+/// Returns a stream of clicks per URL within a scope in buckets of an interval aligned to UTC,
+/// over [from, to). Buckets without clicks are left out. Rolled up days are counted from
+/// hourly rollups, like [crate::analytics::stats] does. Unique visitors of hourly buckets are
+/// distinct IP hashes, and of longer buckets are estimated by merging their daily sketches.
+pub fn stats(
+    db_connection: Pool<Postgres>,
+    scope: Scope,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval: StatsInterval,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, Error>> {
+    spawn_export::<ExportedStats, _, _>(format, move |mut writer| async move {
+        let (filter, id) = scope.filter();
+        // Filter comes from a fixed list, so it is safe to format it in.
+        let query = format!(
+            r#"WITH counts AS (
+                   SELECT url_id, date_trunc($4, "hour", 'UTC') AS bucket, clicks, bot_clicks, unique_visitors
+                   FROM tyto.visit_rollups_hourly
+                   WHERE {filter} AND "hour" >= $2 AND "hour" < LEAST($3, $5)
+                   UNION ALL
+                   SELECT url_id, date_trunc($4, created_at, 'UTC'),
+                          COUNT(*) FILTER (WHERE NOT is_bot),
+                          COUNT(*) FILTER (WHERE is_bot),
+                          COUNT(DISTINCT ip_hash) FILTER (WHERE NOT is_bot)
+                   FROM tyto.visits
+                   WHERE {filter} AND created_at >= GREATEST($2, $5) AND created_at < $3
+                   GROUP BY 1, 2
+               ), totals AS (
+                   SELECT url_id, bucket, SUM(clicks)::int8 AS clicks, SUM(bot_clicks)::int8 AS bot_clicks,
+                          SUM(unique_visitors)::int8 AS unique_visitors
+                   FROM counts GROUP BY 1, 2
+               )
+               SELECT totals.url_id, urls.address, totals.bucket, totals.clicks, totals.bot_clicks,
+                      totals.unique_visitors,
+                      CASE WHEN $4 = 'hour' THEN NULL ELSE (
+                          SELECT tyto.hll_union(registers) FROM tyto.visitor_sketches
+                          WHERE visitor_sketches.url_id = totals.url_id
+                            AND "day" >= (totals.bucket AT TIME ZONE 'UTC')::date
+                            AND "day" < ((totals.bucket + ('1 ' || $4)::interval) AT TIME ZONE 'UTC')::date
+                      ) END AS registers
+               FROM totals JOIN tyto.urls ON urls.id = totals.url_id
+               ORDER BY totals.bucket, totals.url_id"#,
+            filter = filter
+        );
+        let result = async {
+            let rolled_until = rollups::rolled_until(&db_connection).await?;
+            let mut rows = sqlx::query_as::<
+                _,
+                (i64, String, DateTime<Utc>, i64, i64, i64, Option<Vec<u8>>),
+            >(&query)
+            .bind(id)
+            .bind(from)
+            .bind(to)
+            .bind(interval.as_str())
+            .bind(rolled_until)
+            .fetch(&db_connection);
+            while let Some((
+                url_id,
+                address,
+                start,
+                clicks,
+                bot_clicks,
+                unique_visitors,
+                registers,
+            )) = rows.try_next().await?
+            {
+                let unique_visitors = match registers {
+                    Some(registers) => HyperLogLog::from_bytes(registers).estimate(),
+                    None if interval == StatsInterval::Hour => unique_visitors,
+                    None => 0,
+                };
+                let row = ExportedStats {
+                    url_id,
+                    address,
+                    start,
+                    clicks,
+                    bot_clicks,
+                    unique_visitors,
+                };
+                if !writer.write(&row).await {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        .await;
+        writer.finish(result).await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_formulas() {
+        assert_eq!(csv_field("=SUM(A1:A9)"), "'=SUM(A1:A9)");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@cmd"), "'@cmd");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+        // Only leading characters start a formula.
+        assert_eq!(csv_field("a=b-c"), "a=b-c");
+        assert_eq!(csv_field("example.com"), "example.com");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn quotes_fields() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\r\nlines"), "\"two\r\nlines\"");
+        assert_eq!(
+            csv_field("=HYPERLINK(\"x\",1)"),
+            "\"'=HYPERLINK(\"\"x\"\",1)\""
+        );
+    }
+
+    #[test]
+    fn terminates_lines_with_crlf() {
+        let line = csv_line(["a", "b,c", "=d"].iter().copied());
+        assert_eq!(line, "a,\"b,c\",'=d\r\n");
+    }
+}
@@ -0,0 +1,134 @@
+use crate::config::AnalyticsConfig;
+use crate::core::traits::Job;
+use crate::error::Error;
+use actix_web::web;
+use async_trait::async_trait;
+use maxminddb::{geoip2, Reader};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// Location of a client resolved from its IP address. Fields are absent when unknown.
+#[derive(Debug, Default)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2 country code.
+    pub country: Option<String>,
+    /// English name of the largest subdivision, like a state.
+    pub region: Option<String>,
+    /// English name of a city.
+    pub city: Option<String>,
+}
+
+/// Resolves IP addresses to locations using a local MaxMind format (.mmdb) database, like
+/// GeoLite2 City or GeoLite2 Country.
+/// Lookups return an empty [Location] when no database is configured or it can not be read,
+/// so visits are still recorded without a location.
+pub struct GeoIp {
+    path: Option<PathBuf>,
+    reader: RwLock<Option<Reader<Vec<u8>>>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl GeoIp {
+    /// Creates a new instance of [GeoIp] and loads the configured database if there is one.
+    pub fn new(cfg: &AnalyticsConfig) -> Self {
+        let geoip = GeoIp {
+            path: cfg.geoip_database.as_ref().map(PathBuf::from),
+            reader: RwLock::new(None),
+            modified: Mutex::new(None),
+        };
+        // TODO: Use log here
+        if let Err(e) = geoip.reload() {
+            println!("GeoIP database is not loaded. Error: {:?}", e);
+        }
+        geoip
+    }
+
+    /// Loads the database again if its file has changed since it was last loaded.
+    /// A missing or broken file keeps the previously loaded database in use.
+    pub fn reload(&self) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                // TODO: Use log here
+                println!("GeoIP database {:?} is not available. Error: {:?}", path, e);
+                return Ok(());
+            }
+        };
+        let mut last_modified = self.modified.lock().unwrap();
+        if *last_modified == Some(modified) {
+            return Ok(());
+        }
+
+        // Remember the attempt even if it fails, so a broken file is not read over and over.
+        *last_modified = Some(modified);
+        let reader = Reader::open_readfile(path)?;
+        *self.reader.write().unwrap() = Some(reader);
+        Ok(())
+    }
+
+    /// Resolves an IP address to a location.
+    pub fn lookup(&self, ip: IpAddr) -> Location {
+        let guard = self.reader.read().unwrap();
+        let reader = match guard.as_ref() {
+            Some(reader) => reader,
+            None => return Location::default(),
+        };
+        // City databases are a superset of country databases, so one record type covers both.
+        let record: geoip2::City = match reader.lookup(ip) {
+            Ok(record) => record,
+            Err(_) => return Location::default(),
+        };
+        let english = |names: Option<std::collections::BTreeMap<&str, &str>>| {
+            names.and_then(|names| names.get("en").map(|name| name.to_string()))
+        };
+
+        Location {
+            country: record
+                .country
+                .and_then(|country| country.iso_code)
+                .map(|code| code.to_uppercase()),
+            region: record
+                .subdivisions
+                .and_then(|subdivisions| subdivisions.into_iter().next())
+                .and_then(|subdivision| english(subdivision.names)),
+            city: record.city.and_then(|city| english(city.names)),
+        }
+    }
+}
+
+/// A [Job] that reloads GeoIP database when its file is replaced, like by geoipupdate.
+pub struct GeoIpReloadJob {
+    geoip: web::Data<GeoIp>,
+    interval: Duration,
+}
+
+impl GeoIpReloadJob {
+    /// Creates a new instance of [GeoIpReloadJob]
+    pub fn new(geoip: web::Data<GeoIp>, cfg: &AnalyticsConfig) -> Self {
+        GeoIpReloadJob {
+            geoip,
+            interval: Duration::from_secs(cfg.geoip_reload_seconds),
+        }
+    }
+}
+
+#[async_trait()]
+impl Job for GeoIpReloadJob {
+    fn name(&self) -> &'static str {
+        "geoip_reload"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        self.geoip.reload()
+    }
+}
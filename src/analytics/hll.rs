@@ -0,0 +1,205 @@
+/// Number of bits of a hash used to pick a register. Migration 011_visitor_sketches.sql fills
+/// sketches of visits recorded before with the same layout, so it must not change.
+/// Sketches are merged in database by tyto.hll_merge.
+const PRECISION: u32 = 12;
+
+/// Number of registers in a sketch.
+pub const REGISTERS: usize = 1 << PRECISION;
+
+/// HyperLogLog sketch estimating number of distinct visitors. Every register keeps the
+/// longest run of leading zeros seen among hashes falling into it. Sketches of different
+/// days or links merge by taking maximum of every register, which makes the estimate of a
+/// range as accurate as the estimate of a single day, about 1.6% standard error.
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog::new()
+    }
+}
+
+impl HyperLogLog {
+    /// Creates an empty sketch.
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; REGISTERS],
+        }
+    }
+
+    /// Creates a sketch out of registers stored in database. Returns an empty sketch when the
+    /// registers do not have the expected length.
+    pub fn from_bytes(registers: Vec<u8>) -> Self {
+        if registers.len() != REGISTERS {
+            return HyperLogLog::new();
+        }
+        HyperLogLog { registers }
+    }
+
+    /// Returns index of a register and value for a 64 bit hash of a visitor. Top bits pick the
+    /// register and the value is the position of the first set bit among the remaining ones.
+    pub fn position(hash: u64) -> (usize, u8) {
+        let index = (hash >> (64 - PRECISION)) as usize;
+        let rest = hash << PRECISION;
+        let rank = if rest == 0 {
+            64 - PRECISION + 1
+        } else {
+            rest.leading_zeros() + 1
+        };
+        (index, rank as u8)
+    }
+
+    /// Adds a 64 bit hash of a visitor to the sketch.
+    pub fn insert(&mut self, hash: u64) {
+        let (index, rank) = HyperLogLog::position(hash);
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Returns registers of the sketch to be stored in database.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Merges another sketch into this one.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    /// Returns estimated number of distinct visitors. Linear counting is used for small
+    /// numbers where raw HyperLogLog estimate is biased.
+    pub fn estimate(&self) -> i64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-(*register as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|register| **register == 0)
+            .count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as i64
+        } else {
+            estimate.round() as i64
+        }
+    }
+}
+
+/// Returns 64 bit hash of a visitor out of its hex encoded IP hash. IP hashes are SHA-256, so
+/// their first 8 bytes are already uniformly distributed.
+pub fn visitor_hash(ip_hash: &str) -> Option<u64> {
+    ip_hash
+        .get(..16)
+        .and_then(|prefix| u64::from_str_radix(prefix, 16).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a uniformly distributed 64 bit hash of a number, like hashes of visitors.
+    fn hash(n: u64) -> u64 {
+        let mut z = n.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn sketch(visitors: std::ops::Range<u64>) -> HyperLogLog {
+        let mut sketch = HyperLogLog::new();
+        for n in visitors {
+            sketch.insert(hash(n));
+        }
+        sketch
+    }
+
+    fn assert_close(estimate: i64, actual: i64) {
+        let error = (estimate - actual).abs() as f64 / actual as f64;
+        assert!(error < 0.05, "estimated {} for {}", estimate, actual);
+    }
+
+    #[test]
+    fn estimates_distinct_visitors() {
+        assert_eq!(HyperLogLog::new().estimate(), 0);
+        assert_eq!(sketch(0..1).estimate(), 1);
+        for &visitors in &[100, 1_000, 20_000, 200_000] {
+            assert_close(sketch(0..visitors).estimate(), visitors as i64);
+        }
+        // Repeated visitors are counted once.
+        let mut repeated = sketch(0..1_000);
+        for n in 0..1_000 {
+            repeated.insert(hash(n));
+        }
+        assert_close(repeated.estimate(), 1_000);
+    }
+
+    #[test]
+    fn switches_to_linear_counting_for_small_numbers() {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+
+        // Half of the registers are empty, so raw estimate is small and biased.
+        let half = (0..REGISTERS).map(|i| (i % 2) as u8).collect();
+        let expected = (m * 2f64.ln()).round() as i64;
+        assert_eq!(HyperLogLog::from_bytes(half).estimate(), expected);
+
+        // No register is empty, so raw estimate is used.
+        let full = vec![1; REGISTERS];
+        let expected = (alpha * m * 2.0).round() as i64;
+        assert_eq!(HyperLogLog::from_bytes(full).estimate(), expected);
+    }
+
+    #[test]
+    fn ignores_registers_of_other_layouts() {
+        let sketch = HyperLogLog::from_bytes(vec![5; REGISTERS / 2]);
+        assert_eq!(sketch.as_bytes(), HyperLogLog::new().as_bytes());
+    }
+
+    #[test]
+    fn merges_like_a_sketch_of_the_union() {
+        let mut merged = sketch(0..30_000);
+        merged.merge(&sketch(20_000..50_000));
+        assert_eq!(merged.as_bytes(), sketch(0..50_000).as_bytes());
+        assert_close(merged.estimate(), 50_000);
+    }
+
+    /// Merges sketches with tyto.hll_merge of the database at DATABASE_URL and compares them
+    /// with merges of [HyperLogLog::merge].
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL with migrations applied"]
+    async fn merges_like_database() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+
+        let a = sketch(0..3_000);
+        let b = sketch(2_000..6_000);
+        let (merged,): (Vec<u8>,) = sqlx::query_as("SELECT tyto.hll_merge($1, $2)")
+            .bind(a.as_bytes())
+            .bind(b.as_bytes())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let mut expected = sketch(0..3_000);
+        expected.merge(&b);
+        assert_eq!(merged, expected.as_bytes());
+
+        let (merged,): (Vec<u8>,) = sqlx::query_as(
+            "SELECT tyto.hll_union(sketch) FROM (VALUES ($1::bytea), ($2), ($3)) AS days(sketch)",
+        )
+        .bind(a.as_bytes())
+        .bind(b.as_bytes())
+        .bind(sketch(5_000..7_000).as_bytes())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(merged, sketch(0..7_000).as_bytes());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::analytics::{Scope, VisitEvent};
+use crate::constants;
+use crate::error::Error;
+use crate::types::LiveClick;
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Broadcasts clicks from redirects to live click streams within the server process.
+/// How does it work:
+/// 1. Redirects publish every click with [LiveClicks::publish]. Nothing is kept when there
+///    are no subscribers.
+/// 2. Every stream subscribes to the broadcast and keeps clicks of its scope, formatted as
+///    Server-Sent Events.
+/// 3. A stream that falls behind by more than the channel capacity skips the oldest clicks
+///    and is told how many it missed with a lagged event.
+/// 4. A comment is sent on idle streams periodically, so proxies do not close them.
+///
+/// Clicks served by other server processes are not streamed.
+pub struct LiveClicks {
+    sender: broadcast::Sender<LiveClick>,
+}
+
+impl Default for LiveClicks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveClicks {
+    /// Creates a new instance of [LiveClicks]
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(constants::analytics::LIVE_CHANNEL_CAPACITY);
+        LiveClicks { sender }
+    }
+
+    /// Publishes a click of a URL. Details of the visit are left out unless they are stored.
+    pub fn publish(&self, url_id: i64, user_id: i64, visit: &VisitEvent, stored: bool) {
+        let detail = |value: &str| Some(value.to_string()).filter(|_| stored);
+        let click = LiveClick {
+            url_id,
+            user_id,
+            address: visit.address.clone(),
+            referrer_domain: detail(&visit.referrer_domain),
+            browser: detail(visit.browser),
+            os: detail(visit.os),
+            device: detail(visit.device),
+            country: visit.country.clone().filter(|_| stored),
+            is_bot: visit.is_bot,
+            created_at: visit.created_at,
+        };
+        // Sending only fails when nobody is subscribed.
+        let _ = self.sender.send(click);
+    }
+
+    /// Returns a stream of Server-Sent Events carrying clicks within a scope. Clicks do not
+    /// carry campaigns, so campaigns can not be streamed.
+    pub fn subscribe(
+        &self,
+        scope: Scope,
+    ) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error> {
+        if let Scope::Campaign(_) = scope {
+            return Err(Error::UnsupportedLiveScope);
+        }
+        let receiver = self.sender.subscribe();
+        let keepalive = tokio::time::interval(Duration::from_secs(
+            constants::analytics::LIVE_KEEPALIVE_SECONDS,
+        ));
+        let stream = stream::unfold(
+            (receiver, keepalive),
+            move |(mut receiver, mut keepalive)| async move {
+                loop {
+                    let event = tokio::select! {
+                        _ = keepalive.tick() => ": keep-alive\n\n".to_string(),
+                        received = receiver.recv() => match received {
+                            Ok(click) if in_scope(&click, scope) => format!(
+                                "event: click\ndata: {}\n\n",
+                                serde_json::to_string(&click).unwrap()
+                            ),
+                            Ok(_) => continue,
+                            Err(RecvError::Lagged(missed)) => {
+                                format!("event: lagged\ndata: {{\"missed\":{}}}\n\n", missed)
+                            }
+                            Err(RecvError::Closed) => return None,
+                        },
+                    };
+                    return Some((Ok(Bytes::from(event)), (receiver, keepalive)));
+                }
+            },
+        );
+        Ok(stream)
+    }
+}
+
+/// Returns true when a click belongs to a scope.
+fn in_scope(click: &LiveClick, scope: Scope) -> bool {
+    match scope {
+        Scope::Url(url_id) => click.url_id == url_id,
+        Scope::User(user_id) => click.user_id == user_id,
+        // Refused by subscribe.
+        Scope::Campaign(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use futures_util::StreamExt;
+
+    fn visit(address: &str) -> VisitEvent {
+        VisitEvent {
+            address: address.to_string(),
+            referrer: None,
+            referrer_domain: "example.com".to_string(),
+            utm_source: None,
+            utm_medium: None,
+            utm_campaign: None,
+            user_agent: None,
+            browser: "Firefox",
+            os: "Linux",
+            device: "desktop",
+            ip_hash: None,
+            country: Some("DE".to_string()),
+            region: None,
+            city: None,
+            is_bot: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Returns the next event of a stream that is not a keep-alive comment.
+    async fn next_event(stream: &mut (impl Stream<Item = Result<Bytes, Error>> + Unpin)) -> String {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(1), stream.next())
+                .await
+                .expect("no event within a second")
+                .expect("stream ended")
+                .unwrap();
+            let event = String::from_utf8(event.to_vec()).unwrap();
+            if !event.starts_with(':') {
+                return event;
+            }
+        }
+    }
+
+    /// Returns the clicked address carried by a click event.
+    fn address(event: &str) -> String {
+        let data = event
+            .strip_prefix("event: click\ndata: ")
+            .expect("not a click event");
+        let click: serde_json::Value = serde_json::from_str(data.trim()).unwrap();
+        click["address"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn clicks_are_streamed_within_their_scope() {
+        let live = LiveClicks::new();
+        let mut url_stream = Box::pin(live.subscribe(Scope::Url(1)).unwrap());
+        let mut user_stream = Box::pin(live.subscribe(Scope::User(10)).unwrap());
+
+        live.publish(2, 10, &visit("b"), true);
+        live.publish(3, 20, &visit("c"), true);
+        live.publish(1, 10, &visit("a"), true);
+
+        assert_eq!(address(&next_event(&mut url_stream).await), "a");
+        assert_eq!(address(&next_event(&mut user_stream).await), "b");
+        assert_eq!(address(&next_event(&mut user_stream).await), "a");
+    }
+
+    #[tokio::test]
+    async fn details_are_left_out_unless_stored() {
+        let live = LiveClicks::new();
+        let mut stream = Box::pin(live.subscribe(Scope::Url(1)).unwrap());
+        live.publish(1, 10, &visit("a"), false);
+
+        let event = next_event(&mut stream).await;
+        let data = event.strip_prefix("event: click\ndata: ").unwrap();
+        let click: serde_json::Value = serde_json::from_str(data.trim()).unwrap();
+        assert_eq!(click["address"], "a");
+        for detail in &["referrer_domain", "browser", "os", "device", "country"] {
+            assert!(click[detail].is_null(), "{} is streamed", detail);
+        }
+    }
+
+    #[tokio::test]
+    async fn lagged_streams_keep_streaming() {
+        let live = LiveClicks::new();
+        let mut stream = Box::pin(live.subscribe(Scope::Url(1)).unwrap());
+        let capacity = constants::analytics::LIVE_CHANNEL_CAPACITY;
+        for i in 0..capacity + 3 {
+            live.publish(1, 10, &visit(&i.to_string()), true);
+        }
+
+        assert_eq!(
+            next_event(&mut stream).await,
+            "event: lagged\ndata: {\"missed\":3}\n\n"
+        );
+        // The oldest clicks are skipped and the rest are streamed.
+        assert_eq!(address(&next_event(&mut stream).await), "3");
+        for _ in 1..capacity {
+            next_event(&mut stream).await;
+        }
+        live.publish(1, 10, &visit("new"), true);
+        assert_eq!(address(&next_event(&mut stream).await), "new");
+    }
+}
@@ -0,0 +1,202 @@
+use crate::analytics::rollups;
+use crate::error::Error;
+use crate::types::{
+    AdminOverview, ErrorRate, ErrorRates, Growth, SignupFunnel, TopDomain, TopLink,
+};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Pool, Postgres};
+
+/// Human clicks per URL within [$1, $2), counted from hourly rollups before $3 and from raw
+/// visits after it, like statistics of a single URL are.
+const CLICKS: &str = r#"SELECT url_id, clicks FROM tyto.visit_rollups_hourly
+    WHERE "hour" >= $1 AND "hour" < LEAST($2, $3::timestamptz)
+    UNION ALL
+    SELECT url_id, COUNT(*) FROM tyto.visits
+    WHERE created_at >= GREATEST($1, $3::timestamptz) AND created_at < $2 AND NOT is_bot
+    GROUP BY 1"#;
+
+/// Returns a ratio of two counts, zero when there is nothing to divide by.
+fn ratio(part: i64, whole: i64) -> f64 {
+    if whole > 0 {
+        part as f64 / whole as f64
+    } else {
+        0.0
+    }
+}
+
+/// Returns a count along with its growth in the last period over the one before it.
+fn growth(total: i64, current: i64, previous: i64) -> Growth {
+    Growth {
+        total,
+        current,
+        previous,
+        change: if previous > 0 {
+            Some((current - previous) as f64 / previous as f64)
+        } else {
+            None
+        },
+    }
+}
+
+/// Returns number of human clicks within [from, to).
+async fn clicks_between(
+    db_connection: &Pool<Postgres>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    rolled_until: DateTime<Utc>,
+) -> Result<i64, Error> {
+    let query = format!(
+        "SELECT COALESCE(SUM(clicks), 0)::int8 FROM ({}) AS counts",
+        CLICKS
+    );
+    let (clicks,) = sqlx::query_as::<_, (i64,)>(&query)
+        .bind(from)
+        .bind(to)
+        .bind(rolled_until)
+        .fetch_one(db_connection)
+        .await?;
+    Ok(clicks)
+}
+
+/// Returns a service-wide usage overview of the last `days` days compared with the days
+/// before them, listing `limit` top links and target domains.
+/// How does it work:
+/// 1. Count users and links over all time and those created within each period. Deleted
+///    users are left out of users, but counted in the signup funnel.
+/// 2. Count human clicks over all time from visit counts of links, and within each period
+///    from hourly rollups and raw visits.
+/// 3. Rank links and target domains by human clicks within the last period. Domains are hosts
+///    of targets without www, and also carry the number of links leading to them.
+/// 4. Compare accounts created within the last period with those of them that are activated.
+/// 5. Compute error rates of operations whose outcomes are stored. Only webhook deliveries
+///    are, so failed HTTP requests are not part of the overview.
+pub async fn overview(
+    db_connection: &Pool<Postgres>,
+    days: i64,
+    limit: i64,
+) -> Result<AdminOverview, Error> {
+    let to = Utc::now();
+    let from = to - Duration::days(days);
+    let previous_from = from - Duration::days(days);
+    let rolled_until = rollups::rolled_until(db_connection).await?;
+
+    let users = sqlx::query!(
+        r#"SELECT COUNT(*) FILTER (WHERE NOT deleted) AS "total!",
+                  COUNT(*) FILTER (WHERE NOT deleted AND created_at >= $1 AND created_at < $2) AS "current!",
+                  COUNT(*) FILTER (WHERE NOT deleted AND created_at >= $3 AND created_at < $1) AS "previous!",
+                  COUNT(*) FILTER (WHERE created_at >= $1 AND created_at < $2) AS "created!",
+                  COUNT(*) FILTER (WHERE activated AND created_at >= $1 AND created_at < $2) AS "activated!"
+           FROM tyto.users"#,
+        from,
+        to,
+        previous_from,
+    )
+    .fetch_one(db_connection)
+    .await?;
+
+    let links = sqlx::query!(
+        r#"SELECT COUNT(*) AS "total!",
+                  COUNT(*) FILTER (WHERE created_at >= $1 AND created_at < $2) AS "current!",
+                  COUNT(*) FILTER (WHERE created_at >= $3 AND created_at < $1) AS "previous!",
+                  COALESCE(SUM(visit_count), 0)::int8 AS "clicks!"
+           FROM tyto.urls"#,
+        from,
+        to,
+        previous_from,
+    )
+    .fetch_one(db_connection)
+    .await?;
+
+    let current_clicks = clicks_between(db_connection, from, to, rolled_until).await?;
+    let previous_clicks = clicks_between(db_connection, previous_from, from, rolled_until).await?;
+
+    let query = format!(
+        r#"WITH counts AS ({})
+           SELECT urls.id, urls.address, urls.target, SUM(counts.clicks)::int8 AS clicks
+           FROM counts JOIN tyto.urls ON urls.id = counts.url_id
+           GROUP BY urls.id
+           ORDER BY clicks DESC, urls.id
+           LIMIT $4"#,
+        CLICKS
+    );
+    let top_links = sqlx::query_as::<_, (i64, String, String, i64)>(&query)
+        .bind(from)
+        .bind(to)
+        .bind(rolled_until)
+        .bind(limit)
+        .fetch_all(db_connection)
+        .await?
+        .into_iter()
+        .map(|(url_id, address, target, clicks)| TopLink {
+            url_id,
+            address,
+            target,
+            clicks,
+        })
+        .collect();
+
+    let query = format!(
+        r#"WITH counts AS (
+               SELECT url_id, SUM(clicks)::int8 AS clicks FROM ({}) AS clicks GROUP BY 1
+           ), domains AS (
+               SELECT id, regexp_replace(
+                   lower(substring(target from '^(?:[A-Za-z][A-Za-z0-9+.-]*://)?(?:[^@/?#]*@)?([^/:?#]+)')),
+                   '^www\.', ''
+               ) AS domain
+               FROM tyto.urls
+           )
+           SELECT domain, COUNT(*)::int8 AS links, COALESCE(SUM(counts.clicks), 0)::int8 AS clicks
+           FROM domains LEFT JOIN counts ON counts.url_id = domains.id
+           WHERE domain IS NOT NULL
+           GROUP BY domain
+           ORDER BY clicks DESC, links DESC, domain
+           LIMIT $4"#,
+        CLICKS
+    );
+    let top_domains = sqlx::query_as::<_, (String, i64, i64)>(&query)
+        .bind(from)
+        .bind(to)
+        .bind(rolled_until)
+        .bind(limit)
+        .fetch_all(db_connection)
+        .await?
+        .into_iter()
+        .map(|(domain, links, clicks)| TopDomain {
+            domain,
+            links,
+            clicks,
+        })
+        .collect();
+
+    let deliveries = sqlx::query!(
+        r#"SELECT COUNT(*) FILTER (WHERE status <> 'pending') AS "total!",
+                  COUNT(*) FILTER (WHERE status = 'failed') AS "failed!"
+           FROM tyto.webhook_deliveries WHERE created_at >= $1 AND created_at < $2"#,
+        from,
+        to,
+    )
+    .fetch_one(db_connection)
+    .await?;
+
+    Ok(AdminOverview {
+        from,
+        to,
+        users: growth(users.total, users.current, users.previous),
+        links: growth(links.total, links.current, links.previous),
+        clicks: growth(links.clicks, current_clicks, previous_clicks),
+        top_links,
+        top_domains,
+        signup_funnel: SignupFunnel {
+            created: users.created,
+            activated: users.activated,
+            activation_rate: ratio(users.activated, users.created),
+        },
+        errors: ErrorRates {
+            webhook_deliveries: ErrorRate {
+                total: deliveries.total,
+                failed: deliveries.failed,
+                rate: ratio(deliveries.failed, deliveries.total),
+            },
+        },
+    })
+}
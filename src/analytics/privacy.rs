@@ -0,0 +1,175 @@
+use crate::analytics::hash_ip;
+use crate::config::IpStorage;
+use crate::constants;
+use crate::core::traits::Job;
+use crate::error::Error;
+use crate::state::State;
+use crate::utils::generate_token;
+use actix_web::{web, HttpRequest};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::RwLock;
+
+/// Returns true when a client asks not to be tracked with DNT or Sec-GPC header.
+pub fn opted_out(req: &HttpRequest) -> bool {
+    ["DNT", "Sec-GPC"].iter().any(|name| {
+        req.headers()
+            .get(*name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim() == "1")
+            .unwrap_or(false)
+    })
+}
+
+/// Returns network of an IP address with its host part zeroed, /24 for IPv4 and /48 for IPv6.
+/// IPv4 addresses mapped to IPv6 are truncated as IPv4, otherwise they would all share a
+/// network.
+fn truncate(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return truncate(&IpAddr::V4(mapped));
+            }
+            let segments = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(
+                segments[0],
+                segments[1],
+                segments[2],
+                0,
+                0,
+                0,
+                0,
+                0,
+            ))
+        }
+    }
+}
+
+/// Turns client IP addresses into identifiers stored with visits as configured.
+/// How does it work:
+/// 1. An address is kept whole, truncated to its network or not stored at all.
+/// 2. The address is hashed with the configured salt. When salt rotation is on, a random salt
+///    of the UTC day of a visit is mixed in too.
+/// 3. Daily salts are generated a day ahead and kept in the IP salt repository, so all the
+///    server processes sharing it use the same one. They are deleted once their day has
+///    passed, after which hashes can not be linked back to addresses by trying all of them.
+pub struct IpAnonymizer {
+    state: web::Data<State>,
+    /// Salts of today and tomorrow by UTC day when salt rotation is on.
+    salts: RwLock<HashMap<NaiveDate, String>>,
+}
+
+impl IpAnonymizer {
+    /// Creates a new instance of [IpAnonymizer] and loads daily salts when salt rotation is on.
+    pub async fn new(state: web::Data<State>) -> Result<Self, Error> {
+        let anonymizer = IpAnonymizer {
+            state,
+            salts: RwLock::new(HashMap::new()),
+        };
+        if anonymizer.state.config.analytics.rotate_ip_salt {
+            anonymizer.rotate().await?;
+        }
+        Ok(anonymizer)
+    }
+
+    /// Returns identifier of a client IP address to be stored with a visit made at a time.
+    /// Nothing is stored when IP storage is off, or when salt of the day is not loaded yet.
+    pub fn anonymize(&self, ip: &IpAddr, at: DateTime<Utc>) -> Option<String> {
+        let cfg = &self.state.config.analytics;
+        let ip = match cfg.ip_storage {
+            IpStorage::Hash => *ip,
+            IpStorage::Truncate => truncate(ip),
+            IpStorage::None => return None,
+        };
+        if !cfg.rotate_ip_salt {
+            return Some(hash_ip(&ip, &cfg.ip_hash_salt));
+        }
+        let salts = self.salts.read().unwrap();
+        let daily_salt = salts.get(&at.date_naive())?;
+        Some(hash_ip(&ip, &format!("{}{}", cfg.ip_hash_salt, daily_salt)))
+    }
+
+    /// Makes sure salts of today and tomorrow exist, deletes salts of past days and loads the
+    /// rest.
+    pub async fn rotate(&self) -> Result<(), Error> {
+        let today = Utc::now().date_naive();
+        let salts: Vec<(NaiveDate, String)> = [today, today + Duration::days(1)]
+            .iter()
+            .map(|day| (*day, generate_token(32)))
+            .collect();
+        let salts = self.state.ip_salts.rotate(&salts, today).await?;
+        *self.salts.write().unwrap() = salts.into_iter().collect();
+        Ok(())
+    }
+}
+
+/// A [Job] that rotates daily salts of client IP addresses.
+pub struct IpSaltRotationJob {
+    anonymizer: web::Data<IpAnonymizer>,
+}
+
+impl IpSaltRotationJob {
+    /// Creates a new instance of [IpSaltRotationJob]
+    pub fn new(anonymizer: web::Data<IpAnonymizer>) -> Self {
+        IpSaltRotationJob { anonymizer }
+    }
+}
+
+#[async_trait()]
+impl Job for IpSaltRotationJob {
+    fn name(&self) -> &'static str {
+        "ip_salt_rotation"
+    }
+
+    fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(constants::analytics::IP_SALT_ROTATION_CHECK_SECONDS)
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        self.anonymizer.rotate().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truncated(ip: &str) -> String {
+        truncate(&ip.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn truncates_ipv4_to_24() {
+        assert_eq!(truncated("203.0.113.77"), "203.0.113.0");
+        assert_eq!(truncated("203.0.113.0"), "203.0.113.0");
+        assert_eq!(truncated("255.255.255.255"), "255.255.255.0");
+        assert_eq!(truncated("10.1.2.3"), truncated("10.1.2.254"));
+        assert_ne!(truncated("10.1.2.3"), truncated("10.1.3.3"));
+    }
+
+    #[test]
+    fn truncates_ipv6_to_48() {
+        assert_eq!(
+            truncated("2001:db8:85a3:8d3:1319:8a2e:370:7348"),
+            "2001:db8:85a3::"
+        );
+        assert_eq!(truncated("2001:db8:85a3:ffff::1"), "2001:db8:85a3::");
+        assert_eq!(truncated("::1"), "::");
+        assert_ne!(truncated("2001:db8:85a3::1"), truncated("2001:db8:85a4::1"));
+    }
+
+    #[test]
+    fn truncates_mapped_ipv4_as_ipv4() {
+        assert_eq!(truncated("::ffff:203.0.113.77"), "203.0.113.0");
+        assert_ne!(
+            truncated("::ffff:203.0.113.77"),
+            truncated("::ffff:198.51.100.77")
+        );
+    }
+}
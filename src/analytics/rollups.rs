@@ -0,0 +1,179 @@
+use crate::analytics::Dimension;
+use crate::config::AnalyticsConfig;
+use crate::constants::analytics::{PURGE_BATCH_SIZE, ROLLUP_GRACE_DAYS};
+use crate::core::traits::Job;
+use crate::error::Error;
+use crate::state::State;
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use sqlx::{Pool, Postgres};
+use std::time::UNIX_EPOCH;
+
+/// Returns start of the UTC day visits are rolled up before. Statistics read rollups before it
+/// and raw visits from it on. Unix epoch is returned when visits are not rolled up yet.
+pub async fn rolled_until(db_connection: &Pool<Postgres>) -> Result<DateTime<Utc>, Error> {
+    let state = sqlx::query!(r#"SELECT rolled_until FROM tyto.visit_rollup_state"#)
+        .fetch_optional(db_connection)
+        .await?;
+    Ok(state
+        .and_then(|state| state.rolled_until)
+        .map(start_of)
+        .unwrap_or_else(|| DateTime::from(UNIX_EPOCH)))
+}
+
+/// Returns start of a UTC day.
+fn start_of(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+}
+
+/// A [Job] that rolls visits up into hourly and daily aggregates and purges visits older than
+/// the configured retention period.
+/// How does it work:
+/// 1. Visits of every UTC day that has ended since the last rollup are counted per URL and
+///    hour, and per URL, day and breakdown dimension. Days rolled up in the last run are
+///    counted again to include visits flushed after it. Nothing is done until a day ends.
+/// 2. Rollups of those days are replaced and the end of the last day is stored as the point
+///    statistics switch from rollups to raw visits, in a single transaction.
+/// 3. When a retention period is configured, visits older than it are deleted in batches.
+///    Visits of days that would be rolled up again are kept regardless.
+pub struct RollupJob {
+    state: web::Data<State>,
+    interval: std::time::Duration,
+    retention_days: Option<u32>,
+}
+
+impl RollupJob {
+    /// Creates a new instance of [RollupJob]
+    pub fn new(state: web::Data<State>, cfg: &AnalyticsConfig) -> Self {
+        RollupJob {
+            state,
+            interval: std::time::Duration::from_secs(cfg.rollup_interval_seconds),
+            retention_days: cfg.retention_days,
+        }
+    }
+
+    /// Rolls up visits of days ended since the last rollup.
+    async fn rollup(&self) -> Result<(), Error> {
+        let mut transaction = self.state.db_connection.begin().await?;
+        // Locking the state makes rollups of several server processes run one after another.
+        let state = sqlx::query!(r#"SELECT rolled_until FROM tyto.visit_rollup_state FOR UPDATE"#)
+            .fetch_one(&mut transaction)
+            .await?;
+        let today = Utc::now().date_naive();
+        if state.rolled_until == Some(today) {
+            return Ok(());
+        }
+        let from = state
+            .rolled_until
+            .map(|day| start_of(day) - Duration::days(ROLLUP_GRACE_DAYS))
+            .unwrap_or_else(|| DateTime::from(UNIX_EPOCH));
+        let to = start_of(today);
+
+        sqlx::query!(
+            r#"DELETE FROM tyto.visit_rollups_hourly WHERE "hour" >= $1 AND "hour" < $2"#,
+            from,
+            to,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO tyto.visit_rollups_hourly (url_id, "hour", clicks, bot_clicks, unique_visitors)
+               SELECT url_id, date_trunc('hour', created_at),
+                      COUNT(*) FILTER (WHERE NOT is_bot),
+                      COUNT(*) FILTER (WHERE is_bot),
+                      COUNT(DISTINCT ip_hash) FILTER (WHERE NOT is_bot)
+               FROM tyto.visits WHERE created_at >= $1 AND created_at < $2
+               GROUP BY 1, 2"#,
+            from,
+            to,
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"DELETE FROM tyto.visit_rollups_daily WHERE "day" >= $1 AND "day" < $2"#,
+            from.date_naive(),
+            today,
+        )
+        .execute(&mut transaction)
+        .await?;
+        for dimension in Dimension::ALL.iter() {
+            // Column and fallback come from fixed lists, so it is safe to format them in.
+            let query = format!(
+                r#"INSERT INTO tyto.visit_rollups_daily (url_id, "day", dimension, "name", clicks)
+                   SELECT url_id, (created_at AT TIME ZONE 'UTC')::date, $3,
+                          left(COALESCE({}, '{}'), 1024), COUNT(*)
+                   FROM tyto.visits WHERE created_at >= $1 AND created_at < $2 AND NOT is_bot
+                   GROUP BY 1, 2, 4"#,
+                dimension.column(),
+                dimension.fallback(),
+            );
+            sqlx::query(&query)
+                .bind(from)
+                .bind(to)
+                .bind(dimension.name())
+                .execute(&mut transaction)
+                .await?;
+        }
+
+        sqlx::query!(
+            r#"UPDATE tyto.visit_rollup_state SET rolled_until=$1"#,
+            today
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Deletes visits older than the retention period that are rolled up for good.
+    async fn purge(&self, retention_days: u32) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        let retained = Utc::now() - Duration::days(retention_days as i64);
+        let rolled = rolled_until(db_connection).await? - Duration::days(ROLLUP_GRACE_DAYS);
+        let before = retained.min(rolled);
+
+        let mut purged = 0;
+        loop {
+            let deleted = sqlx::query!(
+                r#"DELETE FROM tyto.visits WHERE id IN (
+                       SELECT id FROM tyto.visits WHERE created_at < $1 LIMIT $2
+                   )"#,
+                before,
+                PURGE_BATCH_SIZE,
+            )
+            .execute(db_connection)
+            .await?
+            .rows_affected();
+            purged += deleted;
+            if deleted < PURGE_BATCH_SIZE as u64 {
+                break;
+            }
+        }
+        if purged > 0 {
+            // TODO: Use log here
+            println!("Purged {} visits recorded before {}", purged, before);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait()]
+impl Job for RollupJob {
+    fn name(&self) -> &'static str {
+        "visit_rollup"
+    }
+
+    fn interval(&self) -> std::time::Duration {
+        self.interval
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        self.rollup().await?;
+        if let Some(retention_days) = self.retention_days {
+            self.purge(retention_days).await?;
+        }
+        Ok(())
+    }
+}
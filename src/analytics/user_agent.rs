@@ -0,0 +1,65 @@
+use woothee::parser::Parser;
+
+/// Families a User-Agent header is reduced to. Only these are shown to owners of links,
+/// never a raw header.
+#[derive(Debug, PartialEq)]
+pub struct UserAgent {
+    /// Browser family: chrome, edge, firefox, ie, opera, safari or other.
+    pub browser: &'static str,
+    /// Operating system family: android, ios, linux, macos, windows or other.
+    pub os: &'static str,
+    /// Device class: desktop, mobile, tablet, bot or other.
+    pub device: &'static str,
+}
+
+/// Parses a User-Agent header into browser family, OS family and device class.
+/// Families follow the br_* and os_* columns of legacy visits table, anything else is other.
+pub fn parse(user_agent: &str) -> UserAgent {
+    let result = match Parser::new().parse(user_agent) {
+        Some(result) => result,
+        None => {
+            return UserAgent {
+                browser: "other",
+                os: "other",
+                device: "other",
+            }
+        }
+    };
+
+    let browser = match result.name {
+        "Chrome" => "chrome",
+        "Edge" => "edge",
+        "Firefox" => "firefox",
+        "Internet Explorer" => "ie",
+        "Opera" => "opera",
+        "Safari" => "safari",
+        _ => "other",
+    };
+
+    let os = match result.os {
+        "Android" => "android",
+        "iPhone" | "iPad" | "iPod" | "iOS" => "ios",
+        "Linux" => "linux",
+        "Mac OSX" => "macos",
+        os if os.starts_with("Windows") => "windows",
+        _ => "other",
+    };
+
+    // Woothee has no tablet category, so tablets are told apart from phones by their UA.
+    let is_tablet = result.os == "iPad"
+        || user_agent.contains("Tablet")
+        || (result.os == "Android" && !user_agent.contains("Mobile"));
+    let device = match result.category {
+        "crawler" => "bot",
+        "smartphone" | "mobilephone" if is_tablet => "tablet",
+        "smartphone" | "mobilephone" => "mobile",
+        "pc" => "desktop",
+        _ => "other",
+    };
+
+    UserAgent {
+        browser,
+        os,
+        device,
+    }
+}
@@ -0,0 +1,261 @@
+use crate::config::CacheConfig;
+use crate::constants::store::REDIRECT_PREFIX;
+use crate::core::traits::{Store, UrlStore};
+use crate::error::Error;
+use crate::types::CacheMetrics;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a redirect needs to know about a short link.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Redirect {
+    pub id: i64,
+    pub user_id: i64,
+    pub target: String,
+    pub track_visits: bool,
+    pub track_conversions: bool,
+    /// Time the link stops redirecting at. Cached along with the link, so an expired link is
+    /// refused even before its entry expires.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Redirect {
+    /// Returns true when the link has expired.
+    pub fn expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
+
+/// Keeps short links resolved by redirects in a [Store], so hot links are redirected without
+/// a database round trip.
+/// How does it work:
+/// 1. A short link is looked up in the store first. Entries past their TTL are absent.
+/// 2. On a miss, the link is read from [UrlStore] and stored as JSON. Unknown and banned links
+///    are stored as `null` with a shorter TTL, so repeated requests for them do not reach the
+///    repository either.
+/// 3. Memory store evicts the least recently used link when it is full.
+/// 4. A link is invalidated when it is created, updated, banned or deleted. With memory store
+///    the change applies to the next redirect served by this process, and other processes
+///    pick it up when their entry expires. With Redis store it applies to every process.
+/// 5. Errors of the store are logged and the link is read from [UrlStore], so an outage of
+///    the store slows redirects down without breaking them.
+pub struct RedirectCache {
+    store: Option<Arc<dyn Store>>,
+    ttl: Duration,
+    negative_ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl RedirectCache {
+    /// Creates a new instance of [RedirectCache]. Nothing is cached when store is absent.
+    pub fn new(store: Option<Arc<dyn Store>>, cfg: &CacheConfig) -> Self {
+        RedirectCache {
+            store,
+            ttl: Duration::from_secs(cfg.redirect_ttl_seconds),
+            negative_ttl: Duration::from_secs(cfg.redirect_negative_ttl_seconds),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a short link that is not banned, from the cache when it is there.
+    pub async fn resolve(
+        &self,
+        urls: &dyn UrlStore,
+        address: &str,
+    ) -> Result<Option<Redirect>, Error> {
+        if let Some(redirect) = self.get(address).await {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(redirect);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let redirect = urls.find_redirect(address).await?;
+        self.insert(address, &redirect).await;
+        Ok(redirect)
+    }
+
+    /// Returns a cached lookup of a short link that has not expired.
+    async fn get(&self, address: &str) -> Option<Option<Redirect>> {
+        let store = self.store.as_ref()?;
+        let key = format!("{}{}", REDIRECT_PREFIX, address);
+        match store.get(&key).await {
+            Ok(value) => value.and_then(|value| serde_json::from_str(&value).ok()),
+            Err(e) => {
+                // TODO: Use log here
+                println!("Error in reading a cached redirect: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Caches a lookup of a short link.
+    async fn insert(&self, address: &str, redirect: &Option<Redirect>) {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return,
+        };
+        let ttl = if redirect.is_some() {
+            self.ttl
+        } else {
+            self.negative_ttl
+        };
+        let key = format!("{}{}", REDIRECT_PREFIX, address);
+        let value = serde_json::to_string(redirect).unwrap();
+        if let Err(e) = store.set(&key, &value, ttl).await {
+            // TODO: Use log here
+            println!("Error in caching a redirect: {:?}", e);
+        }
+    }
+
+    /// Drops a short link from the cache, so the next redirect reads it from database.
+    pub async fn invalidate(&self, address: &str) {
+        if let Some(store) = &self.store {
+            let key = format!("{}{}", REDIRECT_PREFIX, address);
+            if let Err(e) = store.delete(&key).await {
+                // TODO: Use log here
+                println!("Error in invalidating a cached redirect: {:?}", e);
+            }
+        }
+    }
+
+    /// Returns hit and miss counts of this process since the server started along with the
+    /// size of the cache when it is kept in memory.
+    pub fn metrics(&self) -> CacheMetrics {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheMetrics {
+            backend: self.store.as_ref().map(|store| store.backend()),
+            hits,
+            misses,
+            hit_rate: if lookups > 0 {
+                hits as f64 / lookups as f64
+            } else {
+                0.0
+            },
+            entries: self.store.as_ref().and_then(|store| store.entries()),
+            capacity: self.store.as_ref().and_then(|store| store.capacity()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::memory::MemoryUrlStore;
+    use crate::store::memory::MemoryStore;
+    use crate::types::{CreateURLRequest, UpdateURLRequest};
+    use std::num::NonZeroUsize;
+
+    fn cache(capacity: usize) -> RedirectCache {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::new(NonZeroUsize::new(capacity)));
+        RedirectCache::new(Some(store), &CacheConfig::default())
+    }
+
+    fn link(target: &str) -> CreateURLRequest {
+        CreateURLRequest {
+            target: target.to_string(),
+            description: None,
+            banned: false,
+            user_id: 1,
+            track_visits: true,
+            public_stats: false,
+            track_conversions: false,
+            expires_at: None,
+        }
+    }
+
+    fn retarget(target: &str) -> UpdateURLRequest {
+        UpdateURLRequest {
+            target: Some(target.to_string()),
+            description: None,
+            banned: None,
+            track_visits: None,
+            public_stats: None,
+            track_conversions: None,
+            expires_at: None,
+        }
+    }
+
+    async fn target(cache: &RedirectCache, urls: &MemoryUrlStore, address: &str) -> Option<String> {
+        let redirect = cache.resolve(urls, address).await.unwrap();
+        redirect.map(|redirect| redirect.target)
+    }
+
+    #[tokio::test]
+    async fn links_are_served_from_cache_until_invalidated() {
+        let urls = MemoryUrlStore::new();
+        let cache = cache(10);
+        let id = urls
+            .create("abc", &link("https://a.example"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            target(&cache, &urls, "abc").await.as_deref(),
+            Some("https://a.example")
+        );
+        urls.update(id, &retarget("https://b.example"))
+            .await
+            .unwrap();
+        // The cached link is served until it is invalidated.
+        assert_eq!(
+            target(&cache, &urls, "abc").await.as_deref(),
+            Some("https://a.example")
+        );
+        cache.invalidate("abc").await;
+        assert_eq!(
+            target(&cache, &urls, "abc").await.as_deref(),
+            Some("https://b.example")
+        );
+
+        let metrics = cache.metrics();
+        assert_eq!((metrics.hits, metrics.misses), (1, 2));
+        assert_eq!(metrics.entries, Some(1));
+    }
+
+    #[tokio::test]
+    async fn unknown_links_are_cached_as_not_found() {
+        let urls = MemoryUrlStore::new();
+        let cache = cache(10);
+        assert_eq!(target(&cache, &urls, "abc").await, None);
+
+        urls.create("abc", &link("https://a.example"))
+            .await
+            .unwrap();
+        assert_eq!(target(&cache, &urls, "abc").await, None);
+        cache.invalidate("abc").await;
+        assert_eq!(
+            target(&cache, &urls, "abc").await.as_deref(),
+            Some("https://a.example")
+        );
+    }
+
+    #[tokio::test]
+    async fn nothing_is_cached_without_store() {
+        let urls = MemoryUrlStore::new();
+        let cache = RedirectCache::new(None, &CacheConfig::default());
+        let id = urls
+            .create("abc", &link("https://a.example"))
+            .await
+            .unwrap();
+        assert_eq!(
+            target(&cache, &urls, "abc").await.as_deref(),
+            Some("https://a.example")
+        );
+        urls.update(id, &retarget("https://b.example"))
+            .await
+            .unwrap();
+        assert_eq!(
+            target(&cache, &urls, "abc").await.as_deref(),
+            Some("https://b.example")
+        );
+        assert_eq!(cache.metrics().backend, None);
+    }
+}
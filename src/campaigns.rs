@@ -0,0 +1,211 @@
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{Campaign, CreateCampaignRequest, UpdateCampaignRequest, Url};
+use crate::utils::is_unique_violation;
+use actix_web::web;
+use chrono::{DateTime, Utc};
+
+/// Manages campaigns, groups of links of a user whose statistics are compared together.
+/// A link belongs to one campaign at most. Deleting a campaign keeps its links.
+pub struct CampaignManager {
+    state: web::Data<State>,
+}
+
+/// Makes sure a campaign name is between 1 and 255 characters.
+fn validate_name(name: &str) -> Result<(), Error> {
+    let length = name.trim().chars().count();
+    if length == 0 || length > 255 {
+        return Err(Error::InvalidCampaignName);
+    }
+    Ok(())
+}
+
+/// Makes sure a campaign starts before it ends.
+fn validate_range(
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+) -> Result<(), Error> {
+    match (starts_at, ends_at) {
+        (Some(starts_at), Some(ends_at)) if starts_at >= ends_at => Err(Error::InvalidTimeRange),
+        _ => Ok(()),
+    }
+}
+
+impl CampaignManager {
+    /// Creates a new instance of [CampaignManager]
+    pub fn new(state: web::Data<State>) -> Self {
+        CampaignManager { state }
+    }
+
+    /// Creates a new campaign for a user.
+    pub async fn create(
+        &self,
+        user_id: i64,
+        request: CreateCampaignRequest,
+    ) -> Result<Campaign, Error> {
+        validate_name(&request.name)?;
+        validate_range(request.starts_at, request.ends_at)?;
+
+        let db_connection = &self.state.db_connection;
+        let campaign = sqlx::query_as!(
+            Campaign,
+            r#"INSERT INTO tyto.campaigns (user_id, "name", description, starts_at, ends_at) VALUES ($1,$2,$3,$4,$5)
+               ON CONFLICT (user_id, "name") DO NOTHING
+               RETURNING id, "name", description, starts_at, ends_at, 0::int8 AS "url_count!", created_at, updated_at"#,
+            user_id,
+            request.name.trim(),
+            request.description,
+            request.starts_at,
+            request.ends_at,
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::DuplicateCampaign)?;
+        Ok(campaign)
+    }
+
+    /// Returns all the campaigns of a user.
+    pub async fn get_all(&self, user_id: i64) -> Result<Vec<Campaign>, Error> {
+        let db_connection = &self.state.read_connection;
+        let campaigns = sqlx::query_as!(
+            Campaign,
+            r#"SELECT id, "name", description, starts_at, ends_at,
+                      (SELECT COUNT(*) FROM tyto.urls WHERE campaign_id=campaigns.id) AS "url_count!",
+                      created_at, updated_at
+               FROM tyto.campaigns WHERE user_id=$1 ORDER BY created_at ASC"#,
+            user_id
+        )
+        .fetch_all(db_connection)
+        .await?;
+        Ok(campaigns)
+    }
+
+    /// Returns a campaign of a user. Campaigns of other users are reported as not found.
+    pub async fn find(&self, user_id: i64, campaign_id: i64) -> Result<Campaign, Error> {
+        let db_connection = &self.state.db_connection;
+        let campaign = sqlx::query_as!(
+            Campaign,
+            r#"SELECT id, "name", description, starts_at, ends_at,
+                      (SELECT COUNT(*) FROM tyto.urls WHERE campaign_id=campaigns.id) AS "url_count!",
+                      created_at, updated_at
+               FROM tyto.campaigns WHERE id=$1 AND user_id=$2"#,
+            campaign_id,
+            user_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::CampaignNotFound)?;
+        Ok(campaign)
+    }
+
+    /// Updates name, description or date range of a campaign.
+    pub async fn update(
+        &self,
+        user_id: i64,
+        campaign_id: i64,
+        request: UpdateCampaignRequest,
+    ) -> Result<Campaign, Error> {
+        if let Some(name) = &request.name {
+            validate_name(name)?;
+        }
+        // Range is validated as a whole, since only one of its ends may change.
+        let campaign = self.find(user_id, campaign_id).await?;
+        validate_range(
+            request.starts_at.or(campaign.starts_at),
+            request.ends_at.or(campaign.ends_at),
+        )?;
+
+        let db_connection = &self.state.db_connection;
+        let updated = sqlx::query_as!(
+            Campaign,
+            r#"UPDATE tyto.campaigns
+               SET "name"=COALESCE($3, "name"), description=COALESCE($4, description),
+                   starts_at=COALESCE($5, starts_at), ends_at=COALESCE($6, ends_at), updated_at=now()
+               WHERE id=$1 AND user_id=$2
+               RETURNING id, "name", description, starts_at, ends_at,
+                         (SELECT COUNT(*) FROM tyto.urls WHERE campaign_id=campaigns.id) AS "url_count!",
+                         created_at, updated_at"#,
+            campaign_id,
+            user_id,
+            request.name.as_deref().map(str::trim),
+            request.description,
+            request.starts_at,
+            request.ends_at,
+        )
+        .fetch_optional(db_connection)
+        .await;
+        match updated {
+            Ok(updated) => updated.ok_or(Error::CampaignNotFound),
+            Err(e) if is_unique_violation(&e) => Err(Error::DuplicateCampaign),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Deletes a campaign. Its links are kept and no longer belong to a campaign.
+    pub async fn delete(&self, user_id: i64, campaign_id: i64) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        sqlx::query!(
+            r#"DELETE FROM tyto.campaigns WHERE id=$1 AND user_id=$2 RETURNING id"#,
+            campaign_id,
+            user_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::CampaignNotFound)?;
+        Ok(())
+    }
+
+    /// Returns the links of a campaign, oldest first.
+    pub async fn urls(&self, user_id: i64, campaign_id: i64) -> Result<Vec<Url>, Error> {
+        self.find(user_id, campaign_id).await?;
+
+        let db_connection = &self.state.read_connection;
+        let urls = sqlx::query_as!(
+            Url,
+            r#"SELECT id, user_id, address, description, banned, target, visit_count, bot_visit_count, track_visits, public_stats, track_conversions, expires_at, created_at, updated_at
+               FROM tyto.urls WHERE campaign_id=$1 ORDER BY created_at ASC, id ASC"#,
+            campaign_id
+        )
+        .fetch_all(db_connection)
+        .await?;
+        Ok(urls)
+    }
+
+    /// Adds a link of a user to a campaign, moving it out of the campaign it was in.
+    pub async fn add_url(&self, user_id: i64, campaign_id: i64, url_id: i64) -> Result<(), Error> {
+        self.find(user_id, campaign_id).await?;
+
+        let db_connection = &self.state.db_connection;
+        sqlx::query!(
+            r#"UPDATE tyto.urls SET campaign_id=$1, updated_at=now() WHERE id=$2 AND user_id=$3 RETURNING id"#,
+            campaign_id,
+            url_id,
+            user_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::UrlNotFound)?;
+        Ok(())
+    }
+
+    /// Removes a link from a campaign.
+    pub async fn remove_url(
+        &self,
+        user_id: i64,
+        campaign_id: i64,
+        url_id: i64,
+    ) -> Result<(), Error> {
+        self.find(user_id, campaign_id).await?;
+
+        let db_connection = &self.state.db_connection;
+        sqlx::query!(
+            r#"UPDATE tyto.urls SET campaign_id=NULL, updated_at=now() WHERE id=$1 AND campaign_id=$2 RETURNING id"#,
+            url_id,
+            campaign_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::UrlNotFound)?;
+        Ok(())
+    }
+}
@@ -1,3 +1,102 @@
 pub mod user {
     pub const ACTIVATION_CODE_LENGTH: usize = 32;
 }
+
+pub mod webhook {
+    /// Number of random bytes in a generated webhook secret.
+    pub const SECRET_BYTES: usize = 32;
+    /// Number of characters of a webhook response body kept in delivery log.
+    pub const RESPONSE_BODY_LIMIT: usize = 1024;
+    /// Number of latest deliveries returned for a webhook.
+    pub const DELIVERY_LOG_LIMIT: i64 = 100;
+}
+
+pub mod digest {
+    /// Number of random bytes in an unsubscribe token.
+    pub const UNSUBSCRIBE_TOKEN_BYTES: usize = 24;
+}
+
+pub mod analytics {
+    /// Width in characters of the short link part of a stored visit.
+    pub const ADDRESS_WIDTH: usize = 255;
+    /// Width in characters of a stored Referer header.
+    pub const REFERRER_WIDTH: usize = 2040;
+    /// Width in characters of a stored User-Agent header.
+    pub const USER_AGENT_WIDTH: usize = 1024;
+    /// Width in characters of other stored details of a visit, like UTM parameters or a city.
+    pub const DETAIL_WIDTH: usize = 255;
+    /// Salt of the sample configuration. It is public, so IP hashes made with it alone can be
+    /// reversed by hashing every address.
+    pub const SAMPLE_IP_HASH_SALT: &str = "change-me";
+    /// Number of records in a page when not requested otherwise.
+    pub const DEFAULT_PAGE_SIZE: i64 = 50;
+    /// Maximum number of records in a page.
+    pub const MAX_PAGE_SIZE: i64 = 500;
+    /// Number of values in a top-N response when not requested otherwise.
+    pub const DEFAULT_TOP_LIMIT: i64 = 10;
+    /// Maximum number of values in a top-N response.
+    pub const MAX_TOP_LIMIT: i64 = 100;
+    /// Number of days covered by statistics when start of a range is not requested.
+    pub const DEFAULT_STATS_DAYS: i64 = 30;
+    /// Maximum number of buckets in a statistics response.
+    pub const MAX_STATS_BUCKETS: i64 = 1000;
+    /// Number of rolled up days rolled up again, so visits flushed late are counted too.
+    pub const ROLLUP_GRACE_DAYS: i64 = 1;
+    /// Maximum number of visits deleted in one statement while purging.
+    pub const PURGE_BATCH_SIZE: i64 = 10_000;
+    /// Seconds to wait between two checks for daily IP salts to be generated or deleted.
+    pub const IP_SALT_ROTATION_CHECK_SECONDS: u64 = 600;
+    /// Number of live clicks kept for subscribers that fall behind before they miss some.
+    pub const LIVE_CHANNEL_CAPACITY: usize = 1024;
+    /// Seconds between two keep-alive comments sent on an idle live click stream.
+    pub const LIVE_KEEPALIVE_SECONDS: u64 = 15;
+    /// Seconds a live token can be used to open a live click stream for.
+    pub const LIVE_TOKEN_SECONDS: u64 = 60;
+    /// Seconds a live token is still accepted after it expires, for clocks of server processes
+    /// that are slightly apart.
+    pub const LIVE_TOKEN_TOLERANCE_SECONDS: u64 = 5;
+    /// Number of encoded export chunks waiting for a slow client before reading from database
+    /// pauses.
+    pub const EXPORT_CHUNKS_IN_FLIGHT: usize = 4;
+    /// Size in bytes of encoded export rows collected before they are sent as a chunk.
+    pub const EXPORT_CHUNK_SIZE: usize = 16 * 1024;
+    /// Number of days covered by the daily chart of public statistics.
+    pub const PUBLIC_STATS_DAYS: i64 = 30;
+    /// Number of countries listed in public statistics.
+    pub const PUBLIC_STATS_COUNTRIES: i64 = 10;
+}
+
+pub mod conversion {
+    /// Query string parameter carrying a click identifier appended to targets on redirect.
+    pub const CLICK_ID_PARAMETER: &str = "tyto_click_id";
+    /// Number of random bytes in a click identifier.
+    pub const CLICK_ID_BYTES: usize = 16;
+    /// Number of random bytes in a key identifying a conversion goal.
+    pub const GOAL_KEY_BYTES: usize = 16;
+    /// Seconds to wait between two purges of click identifiers past the conversion window.
+    pub const CLICK_PURGE_INTERVAL_SECONDS: u64 = 3600;
+    /// A transparent 1x1 GIF returned by the tracking pixel.
+    pub const PIXEL: &[u8] = &[
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+    ];
+}
+
+pub mod admin {
+    /// Number of days of the period of an overview when not requested otherwise.
+    pub const DEFAULT_OVERVIEW_DAYS: i64 = 30;
+    /// Maximum number of days of the period of an overview.
+    pub const MAX_OVERVIEW_DAYS: i64 = 366;
+}
+
+pub mod store {
+    /// Minimum number of writes to memory store between two sweeps of its expired entries.
+    pub const SWEEP_MIN_WRITES: usize = 1024;
+    /// Key prefix of short links kept by the redirect cache.
+    pub const REDIRECT_PREFIX: &str = "redirect:";
+    /// Key prefix of rate limit counters.
+    pub const RATE_LIMIT_PREFIX: &str = "ratelimit:";
+    /// Key prefix of revoked tokens.
+    pub const REVOKED_TOKEN_PREFIX: &str = "revoked:";
+}
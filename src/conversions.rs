@@ -0,0 +1,289 @@
+use crate::analytics::{self, Scope};
+use crate::constants::conversion::{CLICK_ID_BYTES, CLICK_PURGE_INTERVAL_SECONDS, GOAL_KEY_BYTES};
+use crate::core::traits::Job;
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{
+    ConversionGoal, ConversionStats, CreateGoalRequest, GoalConversions, UpdateGoalRequest,
+    UrlConversions,
+};
+use crate::utils::{generate_token, is_unique_violation};
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// Returns a new identifier of a click, which is appended to the target so the site it leads
+/// to can report conversions of the click. Clicks are recorded by
+/// [crate::analytics::buffer::ClickBuffer] along with their visits.
+pub fn click_id() -> String {
+    generate_token(CLICK_ID_BYTES)
+}
+
+/// Makes sure a goal name is between 1 and 255 characters.
+fn validate_name(name: &str) -> Result<(), Error> {
+    let length = name.trim().chars().count();
+    if length == 0 || length > 255 {
+        return Err(Error::InvalidGoalName);
+    }
+    Ok(())
+}
+
+/// Returns conversions of every goal out of conversions per goal and the clicks leading to
+/// them.
+fn goal_conversions(
+    goals: &[ConversionGoal],
+    conversions: &HashMap<i64, i64>,
+    clicks: i64,
+) -> Vec<GoalConversions> {
+    goals
+        .iter()
+        .map(|goal| {
+            let count = conversions.get(&goal.id).copied().unwrap_or(0);
+            GoalConversions {
+                goal_id: goal.id,
+                name: goal.name.clone(),
+                conversions: count,
+                rate: if clicks > 0 {
+                    count as f64 / clicks as f64
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect()
+}
+
+/// Manages conversion goals of users and conversions reported for clicks of their links.
+/// How does it work:
+/// 1. Redirects of links tracking conversions record a click with a random identifier and
+///    append it to the target as `tyto_click_id` query string parameter.
+/// 2. When a visitor reaches a goal, the site reports the goal key along with the click
+///    identifier it received, either from the browser with a tracking pixel or from its
+///    server with a postback.
+/// 3. A conversion is recorded once per goal and click, when the click is made on a link of
+///    the owner of the goal within the conversion window.
+/// 4. Click identifiers past the conversion window are deleted periodically. Conversions are
+///    kept.
+pub struct ConversionManager {
+    state: web::Data<State>,
+}
+
+impl ConversionManager {
+    /// Creates a new instance of [ConversionManager]
+    pub fn new(state: web::Data<State>) -> Self {
+        ConversionManager { state }
+    }
+
+    /// Creates a new goal for a user with a random key.
+    pub async fn create_goal(
+        &self,
+        user_id: i64,
+        request: CreateGoalRequest,
+    ) -> Result<ConversionGoal, Error> {
+        validate_name(&request.name)?;
+
+        let db_connection = &self.state.db_connection;
+        let goal = sqlx::query_as!(
+            ConversionGoal,
+            r#"INSERT INTO tyto.conversion_goals (user_id, "name", "key") VALUES ($1,$2,$3)
+               ON CONFLICT (user_id, "name") DO NOTHING
+               RETURNING id, "name", "key", created_at, updated_at"#,
+            user_id,
+            request.name.trim(),
+            generate_token(GOAL_KEY_BYTES),
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::DuplicateGoal)?;
+        Ok(goal)
+    }
+
+    /// Returns all the goals of a user.
+    pub async fn get_goals(&self, user_id: i64) -> Result<Vec<ConversionGoal>, Error> {
+        let db_connection = &self.state.db_connection;
+        let goals = sqlx::query_as!(
+            ConversionGoal,
+            r#"SELECT id, "name", "key", created_at, updated_at FROM tyto.conversion_goals
+               WHERE user_id=$1 ORDER BY created_at ASC, id ASC"#,
+            user_id
+        )
+        .fetch_all(db_connection)
+        .await?;
+        Ok(goals)
+    }
+
+    /// Renames a goal.
+    pub async fn update_goal(
+        &self,
+        user_id: i64,
+        goal_id: i64,
+        request: UpdateGoalRequest,
+    ) -> Result<ConversionGoal, Error> {
+        if let Some(name) = &request.name {
+            validate_name(name)?;
+        }
+
+        let db_connection = &self.state.db_connection;
+        let updated = sqlx::query_as!(
+            ConversionGoal,
+            r#"UPDATE tyto.conversion_goals SET "name"=COALESCE($3, "name"), updated_at=now()
+               WHERE id=$1 AND user_id=$2
+               RETURNING id, "name", "key", created_at, updated_at"#,
+            goal_id,
+            user_id,
+            request.name.as_deref().map(str::trim),
+        )
+        .fetch_optional(db_connection)
+        .await;
+        match updated {
+            Ok(updated) => updated.ok_or(Error::GoalNotFound),
+            Err(e) if is_unique_violation(&e) => Err(Error::DuplicateGoal),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Deletes a goal along with its conversions.
+    pub async fn delete_goal(&self, user_id: i64, goal_id: i64) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        sqlx::query!(
+            r#"DELETE FROM tyto.conversion_goals WHERE id=$1 AND user_id=$2 RETURNING id"#,
+            goal_id,
+            user_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::GoalNotFound)?;
+        Ok(())
+    }
+
+    /// Records a conversion of a click for a goal identified by its key. Reporting a
+    /// conversion again is not an error, but it is counted once.
+    pub async fn record(&self, goal_key: &str, click_id: &str) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        let goal = sqlx::query!(
+            r#"SELECT id, user_id FROM tyto.conversion_goals WHERE "key"=$1"#,
+            goal_key
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::GoalNotFound)?;
+
+        let window = self.state.config.analytics.conversion_window_days;
+        let click = sqlx::query!(
+            r#"SELECT clicks.url_id FROM tyto.clicks JOIN tyto.urls ON urls.id = clicks.url_id
+               WHERE clicks.id=$1 AND urls.user_id=$2 AND clicks.created_at >= $3"#,
+            click_id,
+            goal.user_id,
+            Utc::now() - Duration::days(window as i64),
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::ClickNotFound)?;
+
+        sqlx::query!(
+            r#"INSERT INTO tyto.conversions (goal_id, url_id, click_id) VALUES ($1,$2,$3)
+               ON CONFLICT (goal_id, click_id) DO NOTHING"#,
+            goal.id,
+            click.url_id,
+            click_id,
+        )
+        .execute(db_connection)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns clicks and conversions of every goal of a user within a scope over [from, to).
+    /// Links are listed one by one unless the scope is a single URL.
+    pub async fn stats(
+        &self,
+        user_id: i64,
+        scope: Scope,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<ConversionStats, Error> {
+        if from >= to {
+            return Err(Error::InvalidTimeRange);
+        }
+        let db_connection = &self.state.read_connection;
+        let goals = self.get_goals(user_id).await?;
+        let clicks = analytics::clicks_per_url(db_connection, scope, from, to).await?;
+        let conversions = analytics::conversions_per_url(db_connection, scope, from, to).await?;
+
+        let mut total_conversions: HashMap<i64, i64> = HashMap::new();
+        let mut url_conversions: HashMap<i64, HashMap<i64, i64>> = HashMap::new();
+        for (url_id, goal_id, count) in conversions {
+            *total_conversions.entry(goal_id).or_default() += count;
+            url_conversions
+                .entry(url_id)
+                .or_default()
+                .insert(goal_id, count);
+        }
+        let total_clicks = clicks.iter().map(|url| url.clicks).sum();
+
+        let urls = match scope {
+            Scope::Url(_) => None,
+            _ => Some(
+                clicks
+                    .into_iter()
+                    .map(|url| UrlConversions {
+                        goals: goal_conversions(
+                            &goals,
+                            &url_conversions.remove(&url.url_id).unwrap_or_default(),
+                            url.clicks,
+                        ),
+                        url_id: url.url_id,
+                        address: url.address,
+                        clicks: url.clicks,
+                    })
+                    .collect(),
+            ),
+        };
+        Ok(ConversionStats {
+            from,
+            to,
+            clicks: total_clicks,
+            goals: goal_conversions(&goals, &total_conversions, total_clicks),
+            urls,
+        })
+    }
+
+    /// Deletes click identifiers past the conversion window.
+    async fn purge_clicks(&self) -> Result<(), Error> {
+        let window = self.state.config.analytics.conversion_window_days;
+        sqlx::query!(
+            r#"DELETE FROM tyto.clicks WHERE created_at < $1"#,
+            Utc::now() - Duration::days(window as i64),
+        )
+        .execute(&self.state.db_connection)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A [Job] that deletes click identifiers past the conversion window.
+pub struct ClickPurgeJob {
+    conversions: web::Data<ConversionManager>,
+}
+
+impl ClickPurgeJob {
+    /// Creates a new instance of [ClickPurgeJob]
+    pub fn new(conversions: web::Data<ConversionManager>) -> Self {
+        ClickPurgeJob { conversions }
+    }
+}
+
+#[async_trait()]
+impl Job for ClickPurgeJob {
+    fn name(&self) -> &'static str {
+        "click_purge"
+    }
+
+    fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(CLICK_PURGE_INTERVAL_SECONDS)
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        self.conversions.purge_clicks().await
+    }
+}
@@ -1,6 +1,16 @@
-use crate::types::{CreateUserRequest, LoginRequest};
+use crate::analytics::buffer::VisitBatch;
+use crate::cache::Redirect;
+use crate::types::{
+    BotSignature, CreateURLRequest, CreateUserRequest, DeliveryAttempt, LoginRequest, Notification,
+    NotificationChannel, NotificationPreference, UpdateURLRequest, Url, Webhook, WebhookDelivery,
+    WebhookTarget,
+};
 use crate::{error, types::User};
 use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde_json::value;
+use std::collections::HashMap;
+use std::time::Duration;
 
 #[async_trait()]
 pub trait UserManager {
@@ -8,15 +18,237 @@ pub trait UserManager {
     async fn get(&self, user_id: i64) -> Result<User, error::Error>;
     async fn get_all(&self) -> Result<Vec<User>, error::Error>;
     async fn delete(&self, user_id: i64) -> Result<(), error::Error>;
-    async fn activate(&self, activation_code: String) -> Result<(), error::Error>;
+    async fn activate(&self, activation_code: String) -> Result<i64, error::Error>;
     async fn login(&self, login_request: LoginRequest) -> Result<String, error::Error>;
     async fn logout(&self, token: String) -> Result<String, error::Error>;
+    async fn set_language(&self, user_id: i64, language: String) -> Result<(), error::Error>;
+}
+
+/// A trait that must be implemented by all the repositories of user accounts. [UserManager]
+/// implementations keep business rules like activation codes and tokens to themselves and
+/// reach accounts only through it.
+#[async_trait()]
+pub trait UserStore: Send + Sync {
+    /// Stores a new user along with its activation code and returns its id.
+    async fn create(
+        &self,
+        user: &CreateUserRequest,
+        activation_code: &str,
+    ) -> Result<i64, error::Error>;
+
+    /// Returns a user.
+    async fn get(&self, user_id: i64) -> Result<User, error::Error>;
+
+    /// Returns all the users, oldest first.
+    async fn get_all(&self) -> Result<Vec<User>, error::Error>;
+
+    /// Deletes a user.
+    async fn delete(&self, user_id: i64) -> Result<(), error::Error>;
+
+    /// Activates a user with an activation code and returns its id.
+    async fn activate(&self, activation_code: &str) -> Result<i64, error::Error>;
+
+    /// Returns id and role of a user with matching credentials.
+    async fn login(&self, login_request: &LoginRequest) -> Result<(i64, String), error::Error>;
+
+    /// Sets preferred language of a user.
+    async fn set_language(&self, user_id: i64, language: &str) -> Result<(), error::Error>;
+
+    /// Returns notification preferences of a user in the order they are set.
+    async fn preferences(&self, user_id: i64) -> Result<Vec<NotificationPreference>, error::Error>;
+
+    /// Replaces all the notification preferences of a user.
+    async fn set_preferences(
+        &self,
+        user_id: i64,
+        preferences: &[NotificationPreference],
+    ) -> Result<(), error::Error>;
+}
+
+/// A trait that must be implemented by all the repositories of short links. Handlers of
+/// [crate::endpoints::urls] and redirects reach short links only through it.
+#[async_trait()]
+pub trait UrlStore: Send + Sync {
+    /// Stores a new short link at an address and returns its id.
+    async fn create(&self, address: &str, request: &CreateURLRequest) -> Result<i64, error::Error>;
+
+    /// Returns a short link.
+    async fn get(&self, url_id: i64) -> Result<Url, error::Error>;
+
+    /// Returns all the short links, oldest first.
+    async fn get_all(&self) -> Result<Vec<Url>, error::Error>;
+
+    /// Changes fields of a short link present in a request and returns the updated link.
+    async fn update(&self, url_id: i64, request: &UpdateURLRequest) -> Result<Url, error::Error>;
+
+    /// Deletes a short link and returns it as it was, or nothing when there is no such link.
+    async fn delete(&self, url_id: i64) -> Result<Option<Url>, error::Error>;
+
+    /// Returns what a redirect needs to know about a short link that is not banned.
+    async fn find_redirect(&self, address: &str) -> Result<Option<Redirect>, error::Error>;
+}
+
+/// A trait that must be implemented by all the repositories of visits. Visits buffered by
+/// [crate::analytics::buffer::ClickBuffer] reach them in batches.
+#[async_trait()]
+pub trait VisitStore: Send + Sync {
+    /// Adds visit counts of a batch to its URLs and keeps what the repository keeps of its
+    /// visits, all or nothing. Visits of URLs that do not exist are skipped. Returns visit
+    /// counts of the rest after the batch by URL ID.
+    async fn record(&self, batch: &VisitBatch<'_>) -> Result<HashMap<i64, i32>, error::Error>;
+}
+
+/// A trait that must be implemented by all the repositories of bot signatures.
+#[async_trait()]
+pub trait BotSignatureStore: Send + Sync {
+    /// Returns all the signatures ordered by pattern.
+    async fn get_all(&self) -> Result<Vec<BotSignature>, error::Error>;
+
+    /// Stores a signature. Fails with [error::Error::DuplicateBotSignature] when its pattern
+    /// is stored already.
+    async fn create(
+        &self,
+        pattern: &str,
+        description: Option<&str>,
+    ) -> Result<BotSignature, error::Error>;
+
+    /// Deletes a signature.
+    async fn delete(&self, id: i64) -> Result<(), error::Error>;
+}
+
+/// A trait that must be implemented by all the repositories of webhooks and their delivery
+/// logs. Webhooks are always reached through the user owning them.
+#[async_trait()]
+pub trait WebhookStore: Send + Sync {
+    /// Stores a new active webhook of a user and returns it without its secret.
+    async fn create(
+        &self,
+        user_id: i64,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<Webhook, error::Error>;
+
+    /// Returns all the webhooks of a user, oldest first and without their secrets.
+    async fn get_all(&self, user_id: i64) -> Result<Vec<Webhook>, error::Error>;
+
+    /// Changes URL, events or state of a webhook when they are given and returns it.
+    async fn update(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+        url: Option<&str>,
+        events: Option<&[String]>,
+        active: Option<bool>,
+    ) -> Result<Webhook, error::Error>;
+
+    /// Deletes a webhook along with its delivery log.
+    async fn delete(&self, user_id: i64, webhook_id: i64) -> Result<(), error::Error>;
+
+    /// Returns where deliveries to a webhook are made.
+    async fn find(&self, user_id: i64, webhook_id: i64) -> Result<WebhookTarget, error::Error>;
+
+    /// Returns where deliveries are made for active webhooks of a user subscribed to an event.
+    async fn subscribers(
+        &self,
+        user_id: i64,
+        event: &str,
+    ) -> Result<Vec<WebhookTarget>, error::Error>;
+
+    /// Returns up to limit latest deliveries of a webhook, newest first.
+    async fn deliveries(
+        &self,
+        webhook_id: i64,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, error::Error>;
+
+    /// Returns event and payload of a delivery of a webhook.
+    async fn delivery(
+        &self,
+        webhook_id: i64,
+        delivery_id: i64,
+    ) -> Result<(String, value::Value), error::Error>;
+
+    /// Records a pending delivery and returns its id.
+    async fn create_delivery(
+        &self,
+        webhook_id: i64,
+        event: &str,
+        payload: &value::Value,
+    ) -> Result<i64, error::Error>;
+
+    /// Records the outcome of an attempt to make a delivery.
+    async fn record_attempt(
+        &self,
+        delivery_id: i64,
+        attempt: &DeliveryAttempt,
+    ) -> Result<(), error::Error>;
+}
+
+/// A trait that must be implemented by all the repositories of daily salts of client IP
+/// addresses. Server processes sharing a repository use the same salts.
+#[async_trait()]
+pub trait IpSaltStore: Send + Sync {
+    /// Stores salts of days that have none yet, deletes salts of days before `oldest` and
+    /// returns the salts kept.
+    async fn rotate(
+        &self,
+        salts: &[(NaiveDate, String)],
+        oldest: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, String)>, error::Error>;
 }
 
 /// A trait that must be implemented by all the concrete types used to notify people
-/// in some way.
+/// in some way. Notifiers are created once at startup and shared, so they should hold on to
+/// expensive resources like connections instead of creating them per message.
 #[async_trait()]
-pub trait Notifier {
-    /// Sends an email
-    async fn send(&self) -> Result<(), error::Error>;
+pub trait Notifier: Send + Sync {
+    /// Returns the channel this notifier delivers through.
+    fn channel(&self) -> NotificationChannel;
+
+    /// Sends a notification to a channel specific recipient like an email address or a URL.
+    async fn send(&self, recipient: &str, notification: &Notification) -> Result<(), error::Error>;
+}
+
+/// A trait that must be implemented by all the background jobs run periodically by
+/// [crate::scheduler::Scheduler] inside the server process.
+#[async_trait()]
+pub trait Job: Send + Sync {
+    /// Returns name of the job used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Returns time to wait between two runs of the job.
+    fn interval(&self) -> Duration;
+
+    /// Runs the job once.
+    async fn run(&self) -> Result<(), error::Error>;
+}
+
+/// A trait that must be implemented by all the key-value stores keeping short-lived data
+/// shared by requests, like cached redirects, rate limit counters and revoked tokens. Every
+/// entry expires, so stores never need to be cleaned up by their users.
+#[async_trait()]
+pub trait Store: Send + Sync {
+    /// Returns name of the backend used in metrics.
+    fn backend(&self) -> &'static str;
+
+    /// Returns value of a key that has not expired.
+    async fn get(&self, key: &str) -> Result<Option<String>, error::Error>;
+
+    /// Sets value of a key that expires after ttl.
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), error::Error>;
+
+    /// Deletes a key. Deleting an absent key is not an error.
+    async fn delete(&self, key: &str) -> Result<(), error::Error>;
+
+    /// Increments a counter and returns its new value. A counter starts from zero and expires
+    /// ttl after it is created, however many times it is incremented.
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<u64, error::Error>;
+
+    /// Returns number of entries kept, when the store keeps them in the server process.
+    fn entries(&self) -> Option<usize>;
+
+    /// Returns number of entries kept before evicting the least recently used ones, when the
+    /// store keeps them in the server process and is bounded.
+    fn capacity(&self) -> Option<usize>;
 }
@@ -1,13 +1,23 @@
+use std::str::FromStr;
 use std::time::Duration;
 
-use crate::config;
+use crate::config::{self, DatabaseConfig, SslMode};
 use crate::error::Error;
-use sqlx::{self, postgres::PgPoolOptions, Pool, Postgres};
+use sqlx::{
+    self,
+    postgres::{PgConnectOptions, PgPoolOptions, PgSslMode},
+    Pool, Postgres,
+};
 
-/// Returns database connection strings using parameters received from config
+/// Returns database connection strings using parameters received from config. `database_url`
+/// is returned as it is when it is set.
 pub async fn get_db_conn_string(cfg: &config::Config) -> String {
     // postgresql://[user[:password]@][netloc][:port][/dbname][?param1=value1&...]
 
+    if let Some(database_url) = &cfg.database_url {
+        return database_url.clone();
+    }
+
     // let database_urll = "postgres://tyto@localhost:5432/tyto";
     if !cfg.db_password.is_empty() {
         format!(
@@ -22,11 +32,83 @@ pub async fn get_db_conn_string(cfg: &config::Config) -> String {
     }
 }
 
-pub async fn get_database_connection(conn_string: &str) -> Result<Pool<Postgres>, Error> {
-    let pool = PgPoolOptions::new()
-        .max_connections(20)
-        .max_lifetime(Duration::from_secs(30 * 60)) // 30 mins
-        .connect(conn_string)
+impl From<SslMode> for PgSslMode {
+    fn from(mode: SslMode) -> PgSslMode {
+        match mode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Allow => PgSslMode::Allow,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        }
+    }
+}
+
+/// Returns connection options of a connection string with SSL settings and statement timeout
+/// of `[database]` section applied over it. Anything else, like application_name, is taken
+/// from query string of the connection string.
+fn connect_options(conn_string: &str, cfg: &DatabaseConfig) -> Result<PgConnectOptions, Error> {
+    let mut options = PgConnectOptions::from_str(conn_string)?;
+    if let Some(ssl_mode) = cfg.ssl_mode {
+        options = options.ssl_mode(ssl_mode.into());
+    }
+    if let Some(ssl_root_cert) = &cfg.ssl_root_cert {
+        options = options.ssl_root_cert(ssl_root_cert);
+    }
+    if cfg.statement_timeout_ms > 0 {
+        options = options.options([("statement_timeout", cfg.statement_timeout_ms)]);
+    }
+    Ok(options)
+}
+
+/// Returns a seconds setting as a duration, absent when it is zero.
+fn optional_seconds(seconds: u64) -> Option<Duration> {
+    if seconds > 0 {
+        Some(Duration::from_secs(seconds))
+    } else {
+        None
+    }
+}
+
+/// Returns options of a connection pool as configured in `[database]` section.
+fn pool_options(cfg: &DatabaseConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(cfg.max_connections)
+        .min_connections(cfg.min_connections)
+        .connect_timeout(Duration::from_secs(cfg.connect_timeout_seconds))
+        .idle_timeout(optional_seconds(cfg.idle_timeout_seconds))
+        .max_lifetime(optional_seconds(cfg.max_lifetime_seconds))
+}
+
+/// Opens a connection pool to a database as configured in `[database]` section.
+pub async fn get_database_connection(
+    conn_string: &str,
+    cfg: &DatabaseConfig,
+) -> Result<Pool<Postgres>, Error> {
+    let pool = pool_options(cfg)
+        .connect_with(connect_options(conn_string, cfg)?)
         .await?;
     Ok(pool)
 }
+
+/// Creates a connection pool that connects on first use, so a server keeping links and users
+/// in memory starts without a reachable database. Features that still need one fail when used.
+pub fn get_lazy_database_connection(
+    conn_string: &str,
+    cfg: &DatabaseConfig,
+) -> Result<Pool<Postgres>, Error> {
+    Ok(pool_options(cfg).connect_lazy_with(connect_options(conn_string, cfg)?))
+}
+
+/// Opens a connection pool to the read replica when one is configured. Otherwise the pool of
+/// the primary is returned, so readers need not care whether there is a replica.
+pub async fn get_read_connection(
+    primary: &Pool<Postgres>,
+    cfg: &DatabaseConfig,
+) -> Result<Pool<Postgres>, Error> {
+    match &cfg.replica_url {
+        Some(replica_url) => get_database_connection(replica_url, cfg).await,
+        None => Ok(primary.clone()),
+    }
+}
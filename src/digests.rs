@@ -0,0 +1,378 @@
+use crate::analytics::{self, Dimension, Scope};
+use crate::constants;
+use crate::core::traits::Job;
+use crate::error::Error;
+use crate::i18n;
+use crate::notifiers::NotificationDispatcher;
+use crate::state::State;
+use crate::types::{DigestFrequency, DigestSubscription, Notification, NotificationEvent};
+use crate::utils::{generate_token, parse_timezone};
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Manages users' subscriptions to link performance digests.
+pub struct DigestManager {
+    state: web::Data<State>,
+}
+
+/// Returns the start of the current digest period. It is the latest preferred hour of a day for
+/// daily digests and of a Monday for weekly digests in user's time zone, which is not in the
+/// future. Returns [None] when the preferred hour does not exist on that day due to DST change.
+fn period_start(
+    now: DateTime<Utc>,
+    frequency: DigestFrequency,
+    timezone: Tz,
+    hour: u32,
+) -> Option<DateTime<Utc>> {
+    let local_now = now.with_timezone(&timezone);
+    let period_days = match frequency {
+        DigestFrequency::Daily => 1,
+        DigestFrequency::Weekly => 7,
+    };
+
+    let mut date = local_now.date_naive();
+    if frequency == DigestFrequency::Weekly {
+        date -= ChronoDuration::days(local_now.weekday().num_days_from_monday() as i64);
+    }
+
+    let mut start = timezone
+        .from_local_datetime(&date.and_hms_opt(hour, 0, 0)?)
+        .earliest()?;
+    if start > local_now {
+        date -= ChronoDuration::days(period_days);
+        start = timezone
+            .from_local_datetime(&date.and_hms_opt(hour, 0, 0)?)
+            .earliest()?;
+    }
+    Some(start.with_timezone(&Utc))
+}
+
+impl DigestManager {
+    /// Creates a new instance of [DigestManager]
+    pub fn new(state: web::Data<State>) -> Self {
+        DigestManager { state }
+    }
+
+    /// Returns digest subscription of a user.
+    pub async fn get(&self, user_id: i64) -> Result<DigestSubscription, Error> {
+        let db_connection = &self.state.db_connection;
+        let rec = sqlx::query!(
+            r#"SELECT frequency, timezone, hour FROM tyto.digest_subscriptions WHERE user_id=$1"#,
+            user_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::DigestSubscriptionNotFound)?;
+
+        Ok(DigestSubscription {
+            frequency: rec
+                .frequency
+                .parse()
+                .map_err(|_| Error::DigestSubscriptionNotFound)?,
+            timezone: rec.timezone,
+            hour: rec.hour,
+        })
+    }
+
+    /// Subscribes a user to digests or updates an existing subscription.
+    /// How does it work:
+    /// 1. Validate time zone and hour.
+    /// 2. Take a snapshot of current visit counts, so the first digest only covers clicks made
+    ///    after subscribing.
+    /// 3. Store the subscription. Snapshot and unsubscribe token of an existing subscription are
+    ///    kept as they are.
+    pub async fn subscribe(
+        &self,
+        user_id: i64,
+        subscription: DigestSubscription,
+    ) -> Result<(), Error> {
+        parse_timezone(&subscription.timezone)?;
+        if !(0..24).contains(&subscription.hour) {
+            return Err(Error::InvalidDigestHour);
+        }
+
+        let db_connection = &self.state.db_connection;
+        let visit_counts = self.visit_counts(user_id).await?;
+        let token = generate_token(constants::digest::UNSUBSCRIBE_TOKEN_BYTES);
+
+        sqlx::query!(
+            r#"INSERT INTO tyto.digest_subscriptions (user_id, frequency, timezone, hour, unsubscribe_token, visit_counts)
+               VALUES ($1,$2,$3,$4,$5,$6)
+               ON CONFLICT (user_id) DO UPDATE
+               SET frequency=EXCLUDED.frequency, timezone=EXCLUDED.timezone, hour=EXCLUDED.hour, updated_at=now()"#,
+            user_id,
+            subscription.frequency.as_str(),
+            subscription.timezone,
+            subscription.hour,
+            token,
+            visit_counts,
+        )
+        .execute(db_connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unsubscribes a user from digests.
+    pub async fn unsubscribe(&self, user_id: i64) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        sqlx::query!(
+            r#"DELETE FROM tyto.digest_subscriptions WHERE user_id=$1 RETURNING user_id"#,
+            user_id
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::DigestSubscriptionNotFound)?;
+        Ok(())
+    }
+
+    /// Unsubscribes a user with the token from a digest email. It does not require login.
+    pub async fn unsubscribe_by_token(&self, token: &str) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        sqlx::query!(
+            r#"DELETE FROM tyto.digest_subscriptions WHERE unsubscribe_token=$1 RETURNING user_id"#,
+            token
+        )
+        .fetch_optional(db_connection)
+        .await?
+        .ok_or(Error::DigestSubscriptionNotFound)?;
+        Ok(())
+    }
+
+    /// Returns current visit count of every link of a user keyed by link id.
+    async fn visit_counts(&self, user_id: i64) -> Result<value::Value, Error> {
+        let db_connection = &self.state.db_connection;
+        let urls = sqlx::query!(
+            r#"SELECT id, visit_count FROM tyto.urls WHERE user_id=$1"#,
+            user_id
+        )
+        .fetch_all(db_connection)
+        .await?;
+
+        let counts: HashMap<String, i32> = urls
+            .into_iter()
+            .map(|url| (url.id.to_string(), url.visit_count))
+            .collect();
+        Ok(serde_json::to_value(counts).unwrap())
+    }
+}
+
+/// A [Job] that sends link performance digests to subscribed users when they are due.
+pub struct DigestJob {
+    state: web::Data<State>,
+    dispatcher: web::Data<NotificationDispatcher>,
+}
+
+#[async_trait()]
+impl Job for DigestJob {
+    fn name(&self) -> &'static str {
+        "digest"
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.state.config.digests.check_interval_minutes * 60)
+    }
+
+    /// Sends digests to users who have not received one since the start of the current period.
+    async fn run(&self) -> Result<(), Error> {
+        let db_connection = &self.state.db_connection;
+        let subscriptions = sqlx::query!(
+            r#"SELECT user_id, frequency, timezone, hour, unsubscribe_token, visit_counts, last_sent_at
+               FROM tyto.digest_subscriptions"#
+        )
+        .fetch_all(db_connection)
+        .await?;
+
+        let now = Utc::now();
+        for subscription in subscriptions {
+            let frequency: DigestFrequency = match subscription.frequency.parse() {
+                Ok(frequency) => frequency,
+                Err(_) => continue,
+            };
+            let timezone = match parse_timezone(&subscription.timezone) {
+                Ok(timezone) => timezone,
+                Err(_) => continue,
+            };
+            let due = match period_start(now, frequency, timezone, subscription.hour as u32) {
+                Some(start) => subscription.last_sent_at < start,
+                None => false,
+            };
+            if !due {
+                continue;
+            }
+
+            let previous_counts: HashMap<String, i32> =
+                serde_json::from_value(subscription.visit_counts).unwrap_or_default();
+            // A digest that can not be sent is tried again on the next run, without holding
+            // back digests of other users.
+            if let Err(e) = self
+                .send(
+                    subscription.user_id,
+                    frequency,
+                    &subscription.unsubscribe_token,
+                    subscription.last_sent_at.with_timezone(&timezone),
+                    previous_counts,
+                )
+                .await
+            {
+                // TODO: Use log here
+                println!(
+                    "Error sending digest to user {}: {:?}",
+                    subscription.user_id, e
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DigestJob {
+    /// Creates a new instance of [DigestJob]
+    pub fn new(state: web::Data<State>, dispatcher: web::Data<NotificationDispatcher>) -> Self {
+        DigestJob { state, dispatcher }
+    }
+
+    /// Prepares and sends a digest to a user, then stores current visit counts for the next one.
+    async fn send(
+        &self,
+        user_id: i64,
+        frequency: DigestFrequency,
+        unsubscribe_token: &str,
+        since: DateTime<Tz>,
+        previous_counts: HashMap<String, i32>,
+    ) -> Result<(), Error> {
+        let cfg = &self.state.config;
+        let db_connection = &self.state.db_connection;
+        let urls = sqlx::query!(
+            r#"SELECT id, address, target, visit_count, expires_at FROM tyto.urls WHERE user_id=$1"#,
+            user_id
+        )
+        .fetch_all(db_connection)
+        .await?;
+
+        // Clicks made since last digest. Links created after it count all their clicks.
+        let mut clicks: Vec<(i32, String, String)> = urls
+            .iter()
+            .map(|url| {
+                let previous = previous_counts
+                    .get(&url.id.to_string())
+                    .copied()
+                    .unwrap_or(0);
+                (
+                    (url.visit_count - previous).max(0),
+                    url.address.clone(),
+                    url.target.clone(),
+                )
+            })
+            .collect();
+        clicks.sort_by_key(|(count, _, _)| std::cmp::Reverse(*count));
+        let total_clicks: i32 = clicks.iter().map(|(count, _, _)| count).sum();
+
+        let locale = self.dispatcher.locale(user_id).await?;
+        let mut top_links: Vec<String> = clicks
+            .iter()
+            .filter(|(count, _, _)| *count > 0)
+            .take(cfg.digests.top_links)
+            .map(|(count, address, target)| {
+                let args = [
+                    ("url", format!("{}/{}", cfg.domain_name, address)),
+                    ("target", target.to_string()),
+                    ("clicks", count.to_string()),
+                ];
+                i18n::translate(locale, "email.link_digest.line", &args)
+            })
+            .collect();
+        if top_links.is_empty() {
+            top_links.push(i18n::translate(locale, "email.link_digest.no_clicks", &[]));
+        }
+
+        // Referrers are counted from daily rollups once raw visits are purged.
+        let referrers = analytics::breakdown(
+            db_connection,
+            Scope::User(user_id),
+            Dimension::Referrer,
+            Some(since.with_timezone(&Utc)),
+            Some(cfg.digests.top_referrers as i64),
+        )
+        .await?;
+        let mut top_referrers: Vec<String> = referrers
+            .items
+            .iter()
+            .map(|referrer| {
+                let args = [
+                    ("referrer", referrer.name.clone()),
+                    ("clicks", referrer.count.to_string()),
+                ];
+                i18n::translate(locale, "email.link_digest.referrer_line", &args)
+            })
+            .collect();
+        if top_referrers.is_empty() {
+            top_referrers.push(i18n::translate(locale, "email.link_digest.no_clicks", &[]));
+        }
+
+        // Links that stopped redirecting since last digest.
+        let now = Utc::now();
+        let mut expired_links: Vec<String> = urls
+            .iter()
+            .filter(|url| {
+                url.expires_at
+                    .is_some_and(|expires_at| expires_at > since && expires_at <= now)
+            })
+            .map(|url| {
+                let args = [
+                    ("url", format!("{}/{}", cfg.domain_name, url.address)),
+                    ("target", url.target.clone()),
+                ];
+                i18n::translate(locale, "email.link_digest.expired_line", &args)
+            })
+            .collect();
+        if expired_links.is_empty() {
+            expired_links.push(i18n::translate(locale, "email.link_digest.no_expired", &[]));
+        }
+
+        let args = [
+            ("clicks", total_clicks.to_string()),
+            ("since", since.format("%Y-%m-%d %H:%M %Z").to_string()),
+            ("top_links", top_links.join("\n")),
+            ("top_referrers", top_referrers.join("\n")),
+            ("expired_links", expired_links.join("\n")),
+            (
+                "unsubscribe_url",
+                format!("{}/{}", cfg.digests.unsubscribe_url, unsubscribe_token),
+            ),
+        ];
+        let body = i18n::translate(locale, "email.link_digest.body", &args);
+        let subject_key = match frequency {
+            DigestFrequency::Daily => "email.link_digest.subject_daily",
+            DigestFrequency::Weekly => "email.link_digest.subject_weekly",
+        };
+        let subject = i18n::translate(locale, subject_key, &[]);
+
+        let notification = Notification {
+            event: NotificationEvent::LinkDigest,
+            subject,
+            body,
+        };
+        self.dispatcher.dispatch(user_id, notification).await?;
+
+        let visit_counts: HashMap<String, i32> = urls
+            .iter()
+            .map(|url| (url.id.to_string(), url.visit_count))
+            .collect();
+        sqlx::query!(
+            r#"UPDATE tyto.digest_subscriptions SET visit_counts=$2, last_sent_at=now() WHERE user_id=$1"#,
+            user_id,
+            serde_json::to_value(visit_counts).unwrap(),
+        )
+        .execute(db_connection)
+        .await?;
+
+        Ok(())
+    }
+}
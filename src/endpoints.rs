@@ -1,3 +1,9 @@
+pub mod admin;
+pub mod analytics;
+pub mod campaigns;
+pub mod conversions;
+pub mod digests;
 pub mod health;
 pub mod urls;
 pub mod users;
+pub mod webhooks;
@@ -0,0 +1,100 @@
+use crate::analytics::{bots::BotFilter, overview};
+use crate::constants;
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{self, CreateBotSignatureRequest, OverviewQuery};
+use crate::utils::authorize_admin;
+use actix_web::{
+    http::StatusCode,
+    web::{self, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use serde_json;
+
+/// Web handler - Returns all the bot signatures
+pub async fn get_bot_signatures(
+    req: HttpRequest,
+    state: web::Data<State>,
+    bots: web::Data<BotFilter>,
+) -> Result<HttpResponse, Error> {
+    authorize_admin(&req, &state).await?;
+    let signatures = bots.get_all().await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(signatures).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Adds a bot signature. It applies to visits made after it.
+pub async fn create_bot_signature(
+    req: HttpRequest,
+    input: web::Json<CreateBotSignatureRequest>,
+    state: web::Data<State>,
+    bots: web::Data<BotFilter>,
+) -> Result<HttpResponse, Error> {
+    authorize_admin(&req, &state).await?;
+    let signature = bots.create(input.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(signature).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::CREATED).json(response))
+}
+
+/// Web handler - Deletes a bot signature associated with {id}
+pub async fn delete_bot_signature(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    bots: web::Data<BotFilter>,
+) -> Result<HttpResponse, Error> {
+    authorize_admin(&req, &state).await?;
+    bots.delete(id.into_inner()).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Returns totals and growth of users, links and clicks, top links, top target
+/// domains, signup funnel and error rates over the last days
+pub async fn get_overview(
+    req: HttpRequest,
+    query: Query<OverviewQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    authorize_admin(&req, &state).await?;
+    let days = query
+        .days
+        .unwrap_or(constants::admin::DEFAULT_OVERVIEW_DAYS)
+        .clamp(1, constants::admin::MAX_OVERVIEW_DAYS);
+    let limit = query
+        .limit
+        .unwrap_or(constants::analytics::DEFAULT_TOP_LIMIT)
+        .clamp(1, constants::analytics::MAX_TOP_LIMIT);
+    let output = overview::overview(&state.read_connection, days, limit).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Returns hit and miss counts of the redirect cache of the server process
+/// serving the request
+pub async fn get_cache_metrics(
+    req: HttpRequest,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    authorize_admin(&req, &state).await?;
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(state.redirect_cache.metrics()).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
@@ -0,0 +1,836 @@
+use crate::analytics::{self, export, live::LiveClicks, Dimension, Scope};
+use crate::constants;
+use crate::error::Error;
+use crate::i18n::{self, Locale};
+use crate::state::State;
+use crate::types::{
+    self, ExportFormat, ExportQuery, GeoLevel, GeographyQuery, LiveClaim, LiveQuery, Page,
+    PageQuery, PublicStats, PublicStatsDay, StatsInterval, StatsQuery, TopQuery, UserClaim,
+    UtmParameter, Visit,
+};
+use crate::utils::authenticate;
+use actix_web::{
+    http::{header, StatusCode},
+    web::{self, Bytes, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use jwt_simple::prelude::{Claims, Duration, HS256Key, MACLike, VerificationOptions};
+use serde_json::json;
+use std::time::UNIX_EPOCH;
+
+/// Makes sure a URL belongs to the logged in user and returns the claim of the user. URLs of
+/// other users are reported as not found, so their existence is not revealed.
+async fn authorize_owner(
+    req: &HttpRequest,
+    state: &State,
+    url_id: i64,
+) -> Result<UserClaim, Error> {
+    let claim = authenticate(req, state).await?;
+    let url = state.urls.get(url_id).await?;
+    if url.user_id != claim.id {
+        return Err(Error::UrlNotFound);
+    }
+    Ok(claim)
+}
+
+/// Makes sure the logged in user is the user associated with {id} and returns the claim of
+/// the user.
+pub async fn authorize_user(
+    req: &HttpRequest,
+    state: &State,
+    user_id: i64,
+) -> Result<UserClaim, Error> {
+    let claim = authenticate(req, state).await?;
+    if claim.id != user_id {
+        return Err(Error::UserNotFound);
+    }
+    Ok(claim)
+}
+
+/// Makes sure the logged in user owns visits within a scope and returns the claim of the
+/// user.
+pub async fn authorize_scope(
+    req: &HttpRequest,
+    state: &State,
+    scope: Scope,
+) -> Result<UserClaim, Error> {
+    match scope {
+        Scope::Url(url_id) => authorize_owner(req, state, url_id).await,
+        Scope::User(user_id) => authorize_user(req, state, user_id).await,
+        Scope::Campaign(campaign_id) => {
+            let claim = authenticate(req, state).await?;
+            let db_connection = &state.db_connection;
+            sqlx::query!(
+                r#"SELECT id FROM tyto.campaigns WHERE id=$1 AND user_id=$2"#,
+                campaign_id,
+                claim.id
+            )
+            .fetch_optional(db_connection)
+            .await?
+            .ok_or(Error::CampaignNotFound)?;
+            Ok(claim)
+        }
+    }
+}
+
+/// Returns requested number of values for a top-N endpoint within allowed bounds.
+fn top_limit(limit: Option<i64>) -> i64 {
+    limit
+        .unwrap_or(constants::analytics::DEFAULT_TOP_LIMIT)
+        .clamp(1, constants::analytics::MAX_TOP_LIMIT)
+}
+
+/// Web handler - Returns visits of a URL associated with {id}, latest first
+pub async fn get_visits(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<PageQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let url_id = id.into_inner();
+    authorize_owner(&req, &state, url_id).await?;
+
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(constants::analytics::DEFAULT_PAGE_SIZE)
+        .clamp(1, constants::analytics::MAX_PAGE_SIZE);
+
+    let db_connection = &state.read_connection;
+    let total = sqlx::query!(
+        r#"SELECT COUNT(*) AS "total!" FROM tyto.visits WHERE url_id=$1"#,
+        url_id
+    )
+    .fetch_one(db_connection)
+    .await?
+    .total;
+
+    let visits = sqlx::query_as!(
+        Visit,
+        r#"SELECT id, url_id, address, referrer, referrer_domain, utm_source, utm_medium, utm_campaign, browser, os, device, ip_hash, country, region, city, is_bot, created_at
+           FROM tyto.visits WHERE url_id=$1 ORDER BY created_at DESC, id DESC LIMIT $2 OFFSET $3"#,
+        url_id,
+        per_page,
+        (page - 1) * per_page,
+    )
+    .fetch_all(db_connection)
+    .await?;
+
+    let output = Page {
+        items: visits,
+        page,
+        per_page,
+        total,
+    };
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Returns breakdown of visits by a dimension after making sure the caller owns them.
+async fn get_breakdown(
+    req: HttpRequest,
+    scope: Scope,
+    state: web::Data<State>,
+    dimension: Dimension,
+    limit: Option<i64>,
+) -> Result<HttpResponse, Error> {
+    authorize_scope(&req, &state, scope).await?;
+    let output =
+        analytics::breakdown(&state.read_connection, scope, dimension, None, limit).await?;
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Returns visits of a URL associated with {id} per browser family
+pub async fn get_browsers(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    get_breakdown(
+        req,
+        Scope::Url(id.into_inner()),
+        state,
+        Dimension::Browser,
+        None,
+    )
+    .await
+}
+
+/// Web handler - Returns visits of a URL associated with {id} per operating system family
+pub async fn get_operating_systems(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    get_breakdown(req, Scope::Url(id.into_inner()), state, Dimension::Os, None).await
+}
+
+/// Web handler - Returns visits of a URL associated with {id} per device class
+pub async fn get_devices(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    get_breakdown(
+        req,
+        Scope::Url(id.into_inner()),
+        state,
+        Dimension::Device,
+        None,
+    )
+    .await
+}
+
+/// Web handler - Returns click statistics of a URL associated with {id} over a time range
+pub async fn get_stats(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<StatsQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let url_id = id.into_inner();
+    authorize_owner(&req, &state, url_id).await?;
+    let output = analytics::stats(
+        &state.read_connection,
+        Scope::Url(url_id),
+        query.into_inner(),
+    )
+    .await?;
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Returns top referring domains of a URL associated with {id}
+pub async fn get_referrers(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<TopQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let limit = top_limit(query.limit);
+    let scope = Scope::Url(id.into_inner());
+    get_breakdown(req, scope, state, Dimension::Referrer, Some(limit)).await
+}
+
+/// Web handler - Returns top values of a UTM parameter of a URL associated with {id}
+pub async fn get_utm(
+    req: HttpRequest,
+    path: Path<(i64, UtmParameter)>,
+    query: Query<TopQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let (url_id, parameter) = path.into_inner();
+    let limit = top_limit(query.limit);
+    get_breakdown(
+        req,
+        Scope::Url(url_id),
+        state,
+        parameter.into(),
+        Some(limit),
+    )
+    .await
+}
+
+/// Web handler - Returns top referring domains across all URLs of a user associated with {id}
+pub async fn get_user_referrers(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<TopQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let limit = top_limit(query.limit);
+    let scope = Scope::User(id.into_inner());
+    get_breakdown(req, scope, state, Dimension::Referrer, Some(limit)).await
+}
+
+/// Web handler - Returns top values of a UTM parameter across all URLs of a user associated
+/// with {id}
+pub async fn get_user_utm(
+    req: HttpRequest,
+    path: Path<(i64, UtmParameter)>,
+    query: Query<TopQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let (user_id, parameter) = path.into_inner();
+    let limit = top_limit(query.limit);
+    get_breakdown(
+        req,
+        Scope::User(user_id),
+        state,
+        parameter.into(),
+        Some(limit),
+    )
+    .await
+}
+
+/// Web handler - Returns top countries, regions or cities of visitors of a URL associated with
+/// {id}
+pub async fn get_geography(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<GeographyQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let limit = top_limit(query.limit);
+    let dimension = query.by.unwrap_or(GeoLevel::Country).into();
+    get_breakdown(
+        req,
+        Scope::Url(id.into_inner()),
+        state,
+        dimension,
+        Some(limit),
+    )
+    .await
+}
+
+/// Web handler - Streams clicks of a URL associated with {id} as Server-Sent Events as they
+/// happen
+pub async fn get_live(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<LiveQuery>,
+    state: web::Data<State>,
+    live: web::Data<LiveClicks>,
+) -> Result<HttpResponse, Error> {
+    let scope = Scope::Url(id.into_inner());
+    authorize_live(&req, &state, &query, scope).await?;
+    live_stream(&live, scope)
+}
+
+/// Web handler - Streams clicks of all URLs of a user associated with {id} as Server-Sent
+/// Events as they happen
+pub async fn get_user_live(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<LiveQuery>,
+    state: web::Data<State>,
+    live: web::Data<LiveClicks>,
+) -> Result<HttpResponse, Error> {
+    let scope = Scope::User(id.into_inner());
+    authorize_live(&req, &state, &query, scope).await?;
+    live_stream(&live, scope)
+}
+
+/// Web handler - Returns a live token for streaming clicks of a URL associated with {id}
+pub async fn post_live_token(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let scope = Scope::Url(id.into_inner());
+    authorize_scope(&req, &state, scope).await?;
+    live_token(&state, scope)
+}
+
+/// Web handler - Returns a live token for streaming clicks of all URLs of a user associated
+/// with {id}
+pub async fn post_user_live_token(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let scope = Scope::User(id.into_inner());
+    authorize_scope(&req, &state, scope).await?;
+    live_token(&state, scope)
+}
+
+/// Returns the claim of a live token for a scope.
+fn live_claim(scope: Scope) -> LiveClaim {
+    let (live, id) = match scope {
+        Scope::Url(url_id) => ("url", url_id),
+        Scope::User(user_id) => ("user", user_id),
+        Scope::Campaign(campaign_id) => ("campaign", campaign_id),
+    };
+    LiveClaim {
+        live: live.to_string(),
+        id,
+    }
+}
+
+/// Returns a response carrying a live token for a scope, which expires in
+/// [constants::analytics::LIVE_TOKEN_SECONDS].
+fn live_token(state: &State, scope: Scope) -> Result<HttpResponse, Error> {
+    let expires_in = constants::analytics::LIVE_TOKEN_SECONDS;
+    let claim = Claims::with_custom_claims(live_claim(scope), Duration::from_secs(expires_in));
+    let token = state.jwt_key.authenticate(claim)?;
+
+    let data = json!({
+        "token": token,
+        "expires_in": expires_in,
+    });
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data,
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Makes sure a request may stream clicks within a scope. A live token in `token` query
+/// parameter is checked when there is one, since EventSource of browsers can not send
+/// Authorization header. Otherwise the logged in user must own the scope. A live token of
+/// another scope is refused like a scope of another user.
+async fn authorize_live(
+    req: &HttpRequest,
+    state: &State,
+    query: &LiveQuery,
+    scope: Scope,
+) -> Result<(), Error> {
+    match &query.token {
+        Some(token) => verify_live_token(&state.jwt_key, token, scope),
+        None => authorize_scope(req, state, scope).await.map(|_| ()),
+    }
+}
+
+/// Makes sure a live token is valid for a scope. Tokens are issued and checked by tyto
+/// itself, so only the clock difference of its processes is tolerated past their expiry,
+/// [constants::analytics::LIVE_TOKEN_TOLERANCE_SECONDS].
+fn verify_live_token(key: &HS256Key, token: &str, scope: Scope) -> Result<(), Error> {
+    let options = VerificationOptions {
+        time_tolerance: Some(Duration::from_secs(
+            constants::analytics::LIVE_TOKEN_TOLERANCE_SECONDS,
+        )),
+        ..VerificationOptions::default()
+    };
+    let claims = key.verify_token::<LiveClaim>(token, Some(options))?;
+    if claims.custom == live_claim(scope) {
+        return Ok(());
+    }
+    match scope {
+        Scope::Url(_) => Err(Error::UrlNotFound),
+        Scope::User(_) => Err(Error::UserNotFound),
+        Scope::Campaign(_) => Err(Error::CampaignNotFound),
+    }
+}
+
+/// Returns a response streaming clicks within a scope.
+fn live_stream(live: &LiveClicks, scope: Scope) -> Result<HttpResponse, Error> {
+    let stream = live.subscribe(scope)?;
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Tells nginx not to buffer the stream.
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(stream))
+}
+
+/// Returns a response streaming an export as a file download.
+fn export_response(
+    name: &str,
+    scope: Scope,
+    format: ExportFormat,
+    body: impl Stream<Item = Result<Bytes, Error>> + 'static,
+) -> HttpResponse {
+    let (kind, id) = match scope {
+        Scope::Url(url_id) => ("url", url_id),
+        Scope::User(user_id) => ("user", user_id),
+        Scope::Campaign(campaign_id) => ("campaign", campaign_id),
+    };
+    let disposition = format!(
+        "attachment; filename=\"{}-{}-{}.{}\"",
+        name,
+        kind,
+        id,
+        format.extension()
+    );
+    HttpResponse::build(StatusCode::OK)
+        .content_type(format.content_type())
+        .insert_header((header::CONTENT_DISPOSITION, disposition))
+        .streaming(body)
+}
+
+/// Returns range of an export within [from, to). It covers all the visits by default.
+fn export_range(query: &ExportQuery) -> Result<(DateTime<Utc>, DateTime<Utc>), Error> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or_else(|| DateTime::from(UNIX_EPOCH));
+    if from >= to {
+        return Err(Error::InvalidTimeRange);
+    }
+    Ok((from, to))
+}
+
+/// Exports visits within a scope after making sure the caller owns them.
+async fn export_visits(
+    req: HttpRequest,
+    scope: Scope,
+    query: ExportQuery,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    authorize_scope(&req, &state, scope).await?;
+    let (from, to) = export_range(&query)?;
+    let format = query.format.unwrap_or_default();
+    let body = export::visits(state.read_connection.clone(), scope, from, to, format);
+    Ok(export_response("visits", scope, format, body))
+}
+
+/// Exports click statistics within a scope after making sure the caller owns them.
+async fn export_stats(
+    req: HttpRequest,
+    scope: Scope,
+    query: ExportQuery,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    authorize_scope(&req, &state, scope).await?;
+    let (from, to) = export_range(&query)?;
+    let format = query.format.unwrap_or_default();
+    let interval = query.interval.unwrap_or(StatsInterval::Day);
+    let body = export::stats(
+        state.read_connection.clone(),
+        scope,
+        from,
+        to,
+        interval,
+        format,
+    );
+    Ok(export_response("stats", scope, format, body))
+}
+
+/// Web handler - Streams visits of a URL associated with {id} as CSV or NDJSON
+pub async fn get_visits_export(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ExportQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    export_visits(req, Scope::Url(id.into_inner()), query.into_inner(), state).await
+}
+
+/// Web handler - Streams click statistics of a URL associated with {id} as CSV or NDJSON
+pub async fn get_stats_export(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ExportQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    export_stats(req, Scope::Url(id.into_inner()), query.into_inner(), state).await
+}
+
+/// Web handler - Streams visits of all URLs of a user associated with {id} as CSV or NDJSON
+pub async fn get_user_visits_export(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ExportQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    export_visits(req, Scope::User(id.into_inner()), query.into_inner(), state).await
+}
+
+/// Web handler - Streams click statistics of every URL of a user associated with {id} as CSV
+/// or NDJSON
+pub async fn get_user_stats_export(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ExportQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    export_stats(req, Scope::User(id.into_inner()), query.into_inner(), state).await
+}
+
+/// Web handler - Streams visits of all links of a campaign associated with {id} as CSV or
+/// NDJSON
+pub async fn get_campaign_visits_export(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ExportQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    export_visits(
+        req,
+        Scope::Campaign(id.into_inner()),
+        query.into_inner(),
+        state,
+    )
+    .await
+}
+
+/// Web handler - Streams click statistics of every link of a campaign associated with {id} as
+/// CSV or NDJSON
+pub async fn get_campaign_stats_export(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ExportQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    export_stats(
+        req,
+        Scope::Campaign(id.into_inner()),
+        query.into_inner(),
+        state,
+    )
+    .await
+}
+
+/// Returns text with HTML special characters escaped.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Returns a simple HTML page of public statistics with a bar chart of daily clicks.
+fn public_stats_page(stats: &PublicStats, url: &str, locale: Locale) -> String {
+    let t = |key: &str, args: &[(&str, String)]| escape_html(&i18n::translate(locale, key, args));
+    let max = stats
+        .daily
+        .iter()
+        .map(|day| day.clicks)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let bars: String = stats
+        .daily
+        .iter()
+        .enumerate()
+        .map(|(i, day)| {
+            let height = day.clicks * 100 / max;
+            format!(
+                r#"<rect x="{}" y="{}" width="10" height="{}" fill="steelblue"><title>{}: {}</title></rect>"#,
+                i * 12,
+                100 - height,
+                height,
+                day.date,
+                day.clicks
+            )
+        })
+        .collect();
+    let days: String = stats
+        .daily
+        .iter()
+        .rev()
+        .map(|day| format!("<tr><td>{}</td><td>{}</td></tr>", day.date, day.clicks))
+        .collect();
+    let countries: String = stats
+        .countries
+        .iter()
+        .map(|country| {
+            format!(
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape_html(&country.name),
+                country.count
+            )
+        })
+        .collect();
+    let title = t("page.public_stats_title", &[("url", url.to_string())]);
+    // Only web targets are linked, so a javascript: target can not run on the page.
+    let target = escape_html(&stats.target);
+    let target = if stats.target.starts_with("http://") || stats.target.starts_with("https://") {
+        format!(r#"<a href="{0}" rel="nofollow noopener">{0}</a>"#, target)
+    } else {
+        target
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<h1>{title}</h1>
+<p>{target}</p>
+<p>{total}</p>
+<h2>{daily}</h2>
+<svg width="{width}" height="100" role="img" aria-label="{daily}">{bars}</svg>
+<table><tr><th>{day}</th><th>{clicks}</th></tr>{days}</table>
+<h2>{countries_title}</h2>
+<table><tr><th>{country}</th><th>{clicks}</th></tr>{countries}</table>
+</body>
+</html>
+"#,
+        lang = locale.as_str(),
+        title = title,
+        target = target,
+        total = t(
+            "page.public_stats_total",
+            &[("clicks", stats.clicks.to_string())]
+        ),
+        daily = t("page.public_stats_daily", &[]),
+        width = stats.daily.len() * 12,
+        bars = bars,
+        day = t("page.public_stats_day", &[]),
+        clicks = t("page.public_stats_clicks", &[]),
+        days = days,
+        countries_title = t("page.public_stats_countries", &[]),
+        country = t("page.public_stats_country", &[]),
+        countries = countries,
+    )
+}
+
+/// Web handler - Returns public statistics of a URL at its {address} followed by + without
+/// login, when its owner opts in. Browsers get an HTML page and other clients get JSON.
+/// How does it work:
+/// 1. Find a URL with public statistics that is not banned. Return 404 if there is none, so
+///    private links can not be told apart from missing ones.
+/// 2. Count clicks per UTC day of the last days and find top countries of visitors.
+/// 3. Respond with HTML when Accept header asks for it and with JSON otherwise. Nothing
+///    about the owner of the URL is included.
+pub async fn get_public_stats(
+    req: HttpRequest,
+    address: Path<String>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let db_connection = &state.read_connection;
+    let url = sqlx::query!(
+        r#"SELECT id, address, target, visit_count, created_at FROM tyto.urls
+           WHERE address=$1 AND public_stats AND NOT banned"#,
+        address.into_inner()
+    )
+    .fetch_optional(db_connection)
+    .await?
+    .ok_or(Error::UrlNotFound)?;
+
+    let now = Utc::now();
+    let first_day =
+        now.date_naive() - chrono::Duration::days(constants::analytics::PUBLIC_STATS_DAYS - 1);
+    let query = StatsQuery {
+        from: Some(DateTime::from_naive_utc_and_offset(
+            first_day.and_hms_opt(0, 0, 0).unwrap(),
+            Utc,
+        )),
+        to: Some(now),
+        interval: Some(StatsInterval::Day),
+        tz: None,
+    };
+    let daily = analytics::stats(db_connection, Scope::Url(url.id), query)
+        .await?
+        .buckets
+        .into_iter()
+        .filter_map(|bucket| {
+            let date = DateTime::parse_from_rfc3339(&bucket.start)
+                .ok()?
+                .date_naive();
+            Some(PublicStatsDay {
+                date,
+                clicks: bucket.clicks,
+            })
+        })
+        .collect();
+    let countries = analytics::breakdown(
+        db_connection,
+        Scope::Url(url.id),
+        Dimension::Country,
+        None,
+        Some(constants::analytics::PUBLIC_STATS_COUNTRIES),
+    )
+    .await?
+    .items;
+
+    let output = PublicStats {
+        address: url.address,
+        target: url.target,
+        created_at: url.created_at,
+        clicks: url.visit_count as i64,
+        daily,
+        countries,
+    };
+
+    let mut response = HttpResponse::build(StatusCode::OK);
+    response.insert_header((header::CACHE_CONTROL, "public, max-age=60"));
+    let wants_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|accept| accept.contains("text/html"))
+        .unwrap_or(false);
+    if wants_html {
+        let locale = i18n::negotiate(&req, i18n::default_locale(&state.config));
+        let url = format!("{}/{}", state.config.domain_name, output.address);
+        return Ok(response
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CONTENT_LANGUAGE, locale.as_str()))
+            .body(public_stats_page(&output, &url, locale)));
+    }
+    Ok(response.json(types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jwt_simple::prelude::Clock;
+
+    /// Returns a live token for a scope that expired a number of seconds ago, or expires in
+    /// that many when it is negative.
+    fn token(key: &HS256Key, scope: Scope, expired_for: i64) -> String {
+        let mut claims = Claims::with_custom_claims(live_claim(scope), Duration::from_secs(60));
+        let now = Clock::now_since_epoch();
+        claims.expires_at = Some(if expired_for >= 0 {
+            now - Duration::from_secs(expired_for as u64)
+        } else {
+            now + Duration::from_secs((-expired_for) as u64)
+        });
+        key.authenticate(claims).unwrap()
+    }
+
+    #[test]
+    fn live_tokens_open_their_scope_only() {
+        let key = HS256Key::generate();
+        let url_token = token(&key, Scope::Url(1), -60);
+        let user_token = token(&key, Scope::User(1), -60);
+
+        assert!(verify_live_token(&key, &url_token, Scope::Url(1)).is_ok());
+        assert!(verify_live_token(&key, &user_token, Scope::User(1)).is_ok());
+        assert!(matches!(
+            verify_live_token(&key, &url_token, Scope::Url(2)),
+            Err(Error::UrlNotFound)
+        ));
+        assert!(matches!(
+            verify_live_token(&key, &url_token, Scope::User(1)),
+            Err(Error::UserNotFound)
+        ));
+        assert!(matches!(
+            verify_live_token(&key, &user_token, Scope::Url(1)),
+            Err(Error::UrlNotFound)
+        ));
+        // Tokens signed with another key are refused.
+        let other_key = HS256Key::generate();
+        assert!(matches!(
+            verify_live_token(&other_key, &url_token, Scope::Url(1)),
+            Err(Error::InvalidToken { .. })
+        ));
+    }
+
+    #[test]
+    fn expired_live_tokens_are_refused() {
+        let key = HS256Key::generate();
+        let tolerance = constants::analytics::LIVE_TOKEN_TOLERANCE_SECONDS as i64;
+        let token = token(&key, Scope::Url(1), tolerance + 30);
+        assert!(matches!(
+            verify_live_token(&key, &token, Scope::Url(1)),
+            Err(Error::InvalidToken { .. })
+        ));
+    }
+
+    #[test]
+    fn login_tokens_are_not_live_tokens() {
+        let key = HS256Key::generate();
+        let claim = UserClaim {
+            id: 1,
+            email: "user@example.com".to_string(),
+            role: "regular".to_string(),
+        };
+        let claims = Claims::with_custom_claims(claim, Duration::from_secs(60));
+        let token = key.authenticate(claims).unwrap();
+        assert!(matches!(
+            verify_live_token(&key, &token, Scope::User(1)),
+            Err(Error::InvalidToken { .. })
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::analytics::{self, Scope};
+use crate::campaigns::CampaignManager;
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{self, CampaignStats, CreateCampaignRequest, StatsQuery, UpdateCampaignRequest};
+use crate::utils::authenticate;
+use actix_web::{
+    http::StatusCode,
+    web::{self, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use serde_json;
+
+/// Web handler - Creates a new campaign for the logged in user
+pub async fn create_campaign(
+    req: HttpRequest,
+    input: web::Json<CreateCampaignRequest>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let campaign = campaigns.create(claim.id, input.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(campaign).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::CREATED).json(response))
+}
+
+/// Web handler - Returns all the campaigns of the logged in user
+pub async fn get_campaigns(
+    req: HttpRequest,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let found_campaigns = campaigns.get_all(claim.id).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(found_campaigns).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Returns a campaign associated with {id}
+pub async fn get_campaign(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let campaign = campaigns.find(claim.id, id.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(campaign).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Updates name, description or date range of a campaign
+pub async fn update_campaign(
+    req: HttpRequest,
+    id: Path<i64>,
+    input: web::Json<UpdateCampaignRequest>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let campaign = campaigns
+        .update(claim.id, id.into_inner(), input.into_inner())
+        .await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(campaign).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Deletes a campaign. Its links are kept.
+pub async fn delete_campaign(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    campaigns.delete(claim.id, id.into_inner()).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Returns links of a campaign associated with {id}
+pub async fn get_campaign_urls(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let urls = campaigns.urls(claim.id, id.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(urls).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Adds a link associated with {url_id} to a campaign
+pub async fn add_campaign_url(
+    req: HttpRequest,
+    path: Path<(i64, i64)>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let (campaign_id, url_id) = path.into_inner();
+    campaigns.add_url(claim.id, campaign_id, url_id).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Removes a link associated with {url_id} from a campaign
+pub async fn remove_campaign_url(
+    req: HttpRequest,
+    path: Path<(i64, i64)>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let (campaign_id, url_id) = path.into_inner();
+    campaigns.remove_url(claim.id, campaign_id, url_id).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Returns click statistics of all the links of a campaign associated with {id}
+/// added up, along with clicks of every link.
+/// How does it work:
+/// 1. Make sure the campaign belongs to the logged in user.
+/// 2. Fill the time range in from the date range of the campaign, up to now, when it is not
+///    supplied. Otherwise defaults of URL statistics apply.
+/// 3. Compute statistics over visits of all the links of the campaign, like for a single URL.
+/// 4. Count clicks of every link over the same range, so links can be compared.
+pub async fn get_campaign_stats(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<StatsQuery>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let campaign = campaigns.find(claim.id, id.into_inner()).await?;
+
+    let mut query = query.into_inner();
+    query.from = query.from.or(campaign.starts_at);
+    query.to = query
+        .to
+        .or_else(|| campaign.ends_at.map(|ends_at| ends_at.min(Utc::now())));
+    let scope = Scope::Campaign(campaign.id);
+    let stats = analytics::stats(&state.read_connection, scope, query).await?;
+    let urls =
+        analytics::clicks_per_url(&state.read_connection, scope, stats.from, stats.to).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(CampaignStats { stats, urls }).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
@@ -0,0 +1,177 @@
+use crate::analytics::Scope;
+use crate::campaigns::CampaignManager;
+use crate::constants;
+use crate::conversions::ConversionManager;
+use crate::endpoints::analytics::authorize_scope;
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{
+    self, ConversionQuery, ConversionRequest, CreateGoalRequest, UpdateGoalRequest,
+};
+use crate::utils::authenticate;
+use actix_web::{
+    http::{header, StatusCode},
+    web::{self, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use chrono::{Duration, Utc};
+use serde_json;
+
+/// Web handler - Creates a new conversion goal for the logged in user
+pub async fn create_goal(
+    req: HttpRequest,
+    input: web::Json<CreateGoalRequest>,
+    state: web::Data<State>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let goal = conversions
+        .create_goal(claim.id, input.into_inner())
+        .await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(goal).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::CREATED).json(response))
+}
+
+/// Web handler - Returns all the conversion goals of the logged in user
+pub async fn get_goals(
+    req: HttpRequest,
+    state: web::Data<State>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let goals = conversions.get_goals(claim.id).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(goals).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Renames a conversion goal
+pub async fn update_goal(
+    req: HttpRequest,
+    id: Path<i64>,
+    input: web::Json<UpdateGoalRequest>,
+    state: web::Data<State>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let goal = conversions
+        .update_goal(claim.id, id.into_inner(), input.into_inner())
+        .await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(goal).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Deletes a conversion goal along with its conversions
+pub async fn delete_goal(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    conversions.delete_goal(claim.id, id.into_inner()).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Records a conversion reported by a server, called a postback
+pub async fn post_conversion(
+    input: web::Json<ConversionRequest>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    conversions.record(&input.goal, &input.click_id).await?;
+    Ok(HttpResponse::build(StatusCode::CREATED).finish())
+}
+
+/// Web handler - Records a conversion reported by a browser loading a tracking pixel
+/// How does it work:
+/// 1. Record a conversion of the click for the goal in query string.
+/// 2. Respond with a transparent 1x1 GIF regardless of the outcome, so pages showing the
+///    pixel never show a broken image. Errors are logged instead.
+/// 3. Ask browsers and proxies not to cache the pixel, so every load reaches tyto.
+pub async fn get_pixel(
+    query: Query<ConversionRequest>,
+    conversions: web::Data<ConversionManager>,
+) -> HttpResponse {
+    if let Err(e) = conversions.record(&query.goal, &query.click_id).await {
+        // TODO: Use log here
+        println!("Error in recording a conversion: {:?}", e);
+    }
+    HttpResponse::build(StatusCode::OK)
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(constants::conversion::PIXEL)
+}
+
+/// Web handler - Returns clicks, conversions and conversion rates of every goal for a URL
+/// associated with {id}
+pub async fn get_url_conversions(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ConversionQuery>,
+    state: web::Data<State>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    let scope = Scope::Url(id.into_inner());
+    let claim = authorize_scope(&req, &state, scope).await?;
+
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query
+        .from
+        .unwrap_or_else(|| to - Duration::days(constants::analytics::DEFAULT_STATS_DAYS));
+    let output = conversions.stats(claim.id, scope, from, to).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Returns clicks, conversions and conversion rates of every goal for all the
+/// links of a campaign associated with {id}, along with those of every link. The range covers
+/// the campaign up to now when it is not supplied, like campaign statistics.
+pub async fn get_campaign_conversions(
+    req: HttpRequest,
+    id: Path<i64>,
+    query: Query<ConversionQuery>,
+    state: web::Data<State>,
+    campaigns: web::Data<CampaignManager>,
+    conversions: web::Data<ConversionManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let campaign = campaigns.find(claim.id, id.into_inner()).await?;
+
+    let to = query
+        .to
+        .or_else(|| campaign.ends_at.map(|ends_at| ends_at.min(Utc::now())))
+        .unwrap_or_else(Utc::now);
+    let from = query
+        .from
+        .or(campaign.starts_at)
+        .unwrap_or_else(|| to - Duration::days(constants::analytics::DEFAULT_STATS_DAYS));
+    let output = conversions
+        .stats(claim.id, Scope::Campaign(campaign.id), from, to)
+        .await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(output).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
@@ -0,0 +1,68 @@
+use crate::digests::DigestManager;
+use crate::error::Error;
+use crate::i18n;
+use crate::state::State;
+use crate::types::{self, DigestSubscription};
+use crate::utils::authenticate;
+use actix_web::{
+    http::{header, StatusCode},
+    web::{self, Path},
+    HttpRequest, HttpResponse,
+};
+
+/// Web handler - Returns digest subscription of the logged in user
+pub async fn get_digest(
+    req: HttpRequest,
+    state: web::Data<State>,
+    digests: web::Data<DigestManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let subscription = digests.get(claim.id).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(subscription).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Subscribes the logged in user to digests or updates the subscription
+pub async fn set_digest(
+    req: HttpRequest,
+    input: web::Json<DigestSubscription>,
+    state: web::Data<State>,
+    digests: web::Data<DigestManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    digests.subscribe(claim.id, input.into_inner()).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Unsubscribes the logged in user from digests
+pub async fn delete_digest(
+    req: HttpRequest,
+    state: web::Data<State>,
+    digests: web::Data<DigestManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    digests.unsubscribe(claim.id).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Unsubscribes a user with the token from a digest email. It is opened from
+/// email clients, so it does not require login and responds with plain text.
+pub async fn unsubscribe(
+    req: HttpRequest,
+    token: Path<String>,
+    state: web::Data<State>,
+    digests: web::Data<DigestManager>,
+) -> Result<HttpResponse, Error> {
+    digests.unsubscribe_by_token(&token.into_inner()).await?;
+
+    let locale = i18n::negotiate(&req, i18n::default_locale(&state.config));
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type("text/plain; charset=utf-8")
+        .insert_header((header::CONTENT_LANGUAGE, locale.as_str()))
+        .body(i18n::translate(locale, "page.digest_unsubscribed", &[])))
+}
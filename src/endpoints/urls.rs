@@ -1,23 +1,65 @@
+use crate::analytics::{
+    self,
+    bots::BotFilter,
+    buffer::{BufferedVisit, ClickBuffer},
+    geoip::GeoIp,
+    privacy::{self, IpAnonymizer},
+    VisitEvent,
+};
+use crate::constants;
+use crate::conversions;
 use crate::error::Error;
+use crate::notifiers::NotificationDispatcher;
 use crate::state::State;
-use crate::types::{self, CreateURLRequest, Url};
+use crate::types::{self, CreateURLRequest, NotificationEvent, UpdateURLRequest, WebhookEvent};
+use crate::utils::authenticate;
+use crate::webhooks::WebhookManager;
 use actix_web::{
-    http::StatusCode,
+    http::{header, StatusCode},
     web::{self, Path},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
-use serde::Serialize;
 use serde_json::{self, json};
 
 /// Web handler - Deletes a URL record associated with {id}
-pub async fn delete_url(id: Path<i64>, state: web::Data<State>) -> Result<HttpResponse, Error> {
+pub async fn delete_url(
+    id: Path<i64>,
+    state: web::Data<State>,
+    dispatcher: web::Data<NotificationDispatcher>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
     let state = state.clone();
-    let db_connection = &state.db_connection;
     let id = id.into_inner();
 
-    let _ = sqlx::query!(r#"DELETE FROM tyto.urls WHERE id=$1"#, id)
-        .execute(db_connection)
-        .await?;
+    let deleted = state.urls.delete(id).await?;
+
+    if let Some(deleted) = deleted {
+        state.redirect_cache.invalidate(&deleted.address).await;
+        let url = format!("{}/{}", &state.config.domain_name, deleted.address);
+        // TODO: Use log here
+        if let Err(e) = dispatcher
+            .notify(
+                deleted.user_id,
+                NotificationEvent::LinkDeleted,
+                &[("url", url)],
+            )
+            .await
+        {
+            println!("Error: {:?}", e);
+        }
+
+        let data = json!({
+            "id": id,
+            "user_id": deleted.user_id,
+            "address": deleted.address,
+        });
+        if let Err(e) = webhooks
+            .emit(deleted.user_id, WebhookEvent::LinkDeleted, data)
+            .await
+        {
+            println!("Error: {:?}", e);
+        }
+    }
 
     let response = types::Response {
         status: types::Status::Success,
@@ -36,24 +78,8 @@ pub async fn get_shortened_url(
     // Record { id: 1, address: "0a137b375cc3881a70e186ce2172c8d1", description: None, banned: false, target: "www.google.com", visit_count: 0, created_at: 2022-02-26T15:01:42.112443Z, updated_at: 2022-02-26T15:01:42.112443Z }
 
     let state = state.clone();
-    let db_connection = &state.db_connection;
-    let id = id.into_inner();
-
-    let url_data = sqlx::query!(r#"SELECT * FROM tyto.urls WHERE id=$1"#, id)
-        .fetch_one(db_connection)
-        .await?;
+    let found_url = state.urls.get(id.into_inner()).await?;
 
-    let found_url = Url {
-        id: url_data.id,
-        user_id: url_data.user_id,
-        address: url_data.address,
-        description: url_data.description,
-        banned: url_data.banned,
-        target: url_data.target,
-        visit_count: url_data.visit_count,
-        created_at: url_data.created_at,
-        updated_at: url_data.updated_at,
-    };
     // Prepare response
     let response = types::Response {
         status: types::Status::Success,
@@ -63,35 +89,71 @@ pub async fn get_shortened_url(
     Ok(HttpResponse::build(StatusCode::OK).json(response))
 }
 
-/// Web handler - Creates a new shortened URL for a supplied longer URL
+/// Web handler - Creates a new shortened URL for a supplied longer URL. Creations are rate limited
+/// per logged in user, or per client IP address when the request carries no valid token, since
+/// user_id of the request itself is not verified.
 pub async fn post_url(
+    req: HttpRequest,
     input: web::Json<CreateURLRequest>,
     state: web::Data<State>,
+    dispatcher: web::Data<NotificationDispatcher>,
+    webhooks: web::Data<WebhookManager>,
 ) -> Result<HttpResponse, Error> {
-    #[derive(Serialize)]
-    struct OutputData {
-        pub url: String,
-    }
-
     let state = state.clone();
-    let db_connection = &state.db_connection;
+    let creator = match authenticate(&req, &state).await {
+        Ok(claim) => format!("user:{}", claim.id),
+        Err(_) => format!(
+            "ip:{}",
+            analytics::client_ip(&req, &state.config.trusted_proxies)
+                .map(|ip| ip.to_string())
+                .unwrap_or_default()
+        ),
+    };
+    state
+        .rate_limiter
+        .check(
+            "url_creation",
+            &creator,
+            state.config.rate_limit.url_creations,
+        )
+        .await?;
     let short_url = shorten_url_md5(input.target.clone()).await;
+    let id = state.urls.create(&short_url, &input).await?;
+    // The address may be cached as unknown.
+    state.redirect_cache.invalidate(&short_url).await;
+
+    let url = format!("{}/{}", &state.config.domain_name, short_url);
+
+    // TODO: Use log here
+    let args = [("url", url.clone()), ("target", input.target.clone())];
+    if let Err(e) = dispatcher
+        .notify(input.user_id, NotificationEvent::LinkCreated, &args)
+        .await
+    {
+        println!("Error: {:?}", e);
+    }
 
-    // IMP NOTE: DATABASE_URL env var must be set for this to work.
-    //           export DATABASE_URL="postgres://tyto@localhost/tyto"
-    let _rec = sqlx::query!(
-        r#"INSERT INTO tyto.urls (address,target,description,banned,user_id) VALUES ($1,$2,$3,$4,$5) RETURNING id"#,
-        short_url.clone(),
-        input.target.clone(),
-        input.description,
-        input.banned,
-        input.user_id,
-    )
-    .fetch_one(db_connection)
-    .await?;
+    let data = json!({
+        "id": id,
+        "user_id": input.user_id,
+        "address": short_url,
+        "target": input.target,
+        "description": input.description,
+        "banned": input.banned,
+        "track_visits": input.track_visits,
+        "public_stats": input.public_stats,
+        "track_conversions": input.track_conversions,
+        "expires_at": input.expires_at,
+    });
+    if let Err(e) = webhooks
+        .emit(input.user_id, WebhookEvent::LinkCreated, data)
+        .await
+    {
+        println!("Error: {:?}", e);
+    }
 
     let output = json!({
-        "url": format!("{}/{}", &state.config.domain_name, short_url),
+        "url": url,
     });
 
     let response = types::Response {
@@ -103,34 +165,145 @@ pub async fn post_url(
     Ok(HttpResponse::build(StatusCode::CREATED).json(response))
 }
 
-/// Returns a shortened version of a URL
-pub async fn shorten_url_md5(long_url: String) -> String {
-    format!("{:?}", md5::compute(long_url))
+/// Web handler - Updates target, description, banned flag, visit tracking, public statistics,
+/// conversion tracking or expiry of a URL record associated with {id}
+pub async fn update_url(
+    id: Path<i64>,
+    input: web::Json<UpdateURLRequest>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let state = state.clone();
+    let id = id.into_inner();
+
+    // Banned flag before the update tells if the URL is being banned now.
+    let existing = state.urls.get(id).await?;
+    let updated_url = state.urls.update(id, &input).await?;
+    state.redirect_cache.invalidate(&updated_url.address).await;
+
+    // TODO: Use log here
+    let data = serde_json::to_value(&updated_url).unwrap();
+    if let Err(e) = webhooks
+        .emit(updated_url.user_id, WebhookEvent::LinkUpdated, data.clone())
+        .await
+    {
+        println!("Error: {:?}", e);
+    }
+    if !existing.banned && updated_url.banned {
+        if let Err(e) = webhooks
+            .emit(updated_url.user_id, WebhookEvent::LinkBanned, data)
+            .await
+        {
+            println!("Error: {:?}", e);
+        }
+    }
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(updated_url).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
 }
 
-/// Web handler - Returns all the URL records from database
-pub async fn get_urls(state: web::Data<State>) -> Result<HttpResponse, Error> {
+/// Web handler - Redirects to the target of a shortened URL {address}
+/// How does it work:
+/// 1. Find a URL that is not banned, from [crate::cache::RedirectCache] when it is cached.
+///    Return 404 if there is none, or 410 if it has expired.
+/// 2. Capture the visit and tell if it is made by a bot.
+/// 3. Tell if details of the visit are stored. They are not when storage is turned off
+///    globally or for the URL, or when the client opts out with DNT or Sec-GPC header.
+/// 4. When the URL tracks conversions and details of the visit are stored, give the click an
+///    identifier and append it to the target.
+/// 5. Buffer the visit, which publishes it to live click streams too. [ClickBuffer] writes
+///    visits, clicks and visit counts in batches, so they lag behind by up to a flush interval.
+/// 6. Redirect to the target.
+pub async fn redirect(
+    req: HttpRequest,
+    address: Path<String>,
+    state: web::Data<State>,
+    geoip: web::Data<GeoIp>,
+    bots: web::Data<BotFilter>,
+    ips: web::Data<IpAnonymizer>,
+    clicks: web::Data<ClickBuffer>,
+) -> Result<HttpResponse, Error> {
     let state = state.clone();
-    let db_connection = &state.db_connection;
+    let address = address.into_inner();
 
-    let urls = sqlx::query!(r#"SELECT * FROM tyto.urls ORDER BY created_at ASC"#,)
-        .fetch_all(db_connection)
-        .await?;
+    let url = state
+        .redirect_cache
+        .resolve(state.urls.as_ref(), &address)
+        .await?
+        .ok_or(Error::UrlNotFound)?;
+    if url.expired() {
+        return Err(Error::UrlExpired);
+    }
 
-    let mut output = Vec::new();
-    for url in urls {
-        output.push(Url {
-            id: url.id,
-            user_id: url.user_id,
-            address: url.address,
-            description: url.description,
-            banned: url.banned,
-            target: url.target,
-            visit_count: url.visit_count,
-            created_at: url.created_at,
-            updated_at: url.updated_at,
-        });
+    let cfg = &state.config.analytics;
+    let visit = VisitEvent::from_request(
+        &req,
+        cfg,
+        &state.config.trusted_proxies,
+        &geoip,
+        &bots,
+        &ips,
+        &address,
+    );
+    let stored = cfg.store_visits
+        && url.track_visits
+        && !(cfg.honour_do_not_track && privacy::opted_out(&req));
+
+    let mut location = absolute_target(&url.target);
+    let click_id = (url.track_conversions && stored).then(conversions::click_id);
+    if let Some(click_id) = &click_id {
+        location = with_click_id(location, click_id);
+    }
+
+    clicks.push(BufferedVisit {
+        url_id: url.id,
+        user_id: url.user_id,
+        visit,
+        stored,
+        click_id,
+    });
+
+    Ok(HttpResponse::build(StatusCode::FOUND)
+        .insert_header((header::LOCATION, location))
+        .finish())
+}
+
+/// Returns a target with a click identifier appended to its query string. Targets that can
+/// not be parsed are returned as they are.
+fn with_click_id(target: String, click_id: &str) -> String {
+    match reqwest::Url::parse(&target) {
+        Ok(mut parsed) => {
+            parsed
+                .query_pairs_mut()
+                .append_pair(constants::conversion::CLICK_ID_PARAMETER, click_id);
+            parsed.to_string()
+        }
+        Err(_) => target,
+    }
+}
+
+/// Returns target of a URL with http scheme added when it has none, like www.google.com, so
+/// browsers do not treat it as a relative location.
+fn absolute_target(target: &str) -> String {
+    if target.contains("://") {
+        target.to_string()
+    } else {
+        format!("http://{}", target)
     }
+}
+
+/// Returns a shortened version of a URL
+pub async fn shorten_url_md5(long_url: String) -> String {
+    format!("{:?}", md5::compute(long_url))
+}
+
+/// Web handler - Returns all the URL records
+pub async fn get_urls(state: web::Data<State>) -> Result<HttpResponse, Error> {
+    let output = state.urls.get_all().await?;
 
     // Prepare response
     let response = types::Response {
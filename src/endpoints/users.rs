@@ -1,8 +1,16 @@
-use crate::core::traits::{Notifier, UserManager};
-use crate::emailer::EmailNotifier;
+use crate::analytics;
+use crate::core::traits::UserManager;
+use crate::endpoints::analytics::authorize_user;
 use crate::error::Error;
-use crate::types::{self, CreateUserRequest, LoginRequest, Response, Status};
+use crate::i18n::{self, Locale};
+use crate::notifiers::NotificationDispatcher;
+use crate::state::State;
+use crate::types::{
+    self, CreateUserRequest, LoginRequest, NotificationEvent, NotificationPreference, Response,
+    SetLanguageRequest, Status, WebhookEvent,
+};
 use crate::user_management::TytoUserManager;
+use crate::webhooks::WebhookManager;
 use crate::Config;
 use actix_web::http::header::HeaderValue;
 use actix_web::HttpRequest;
@@ -17,13 +25,15 @@ use validator::validate_email;
 /// Web handler - Creates a new user
 /// How does it work:
 /// 1. Validate email
-/// 2. Read configuration
-/// 3. Prepare body for activation email
-/// 4. Send an email
+/// 2. Pick preferred language. Negotiate it from Accept-Language header when not supplied.
+/// 3. Read configuration
+/// 4. Notify the user with activation email in preferred language
 /// 5. Prepare and send response
 pub async fn create_user(
+    req: HttpRequest,
     new_user: web::Json<CreateUserRequest>,
     user_manager: web::Data<TytoUserManager>,
+    dispatcher: web::Data<NotificationDispatcher>,
     cfg: web::Data<Config>,
 ) -> Result<HttpResponse, Error> {
     // Extract and validate email
@@ -34,47 +44,37 @@ pub async fn create_user(
         return Err(Error::InvalidEmail);
     }
 
+    let mut new_user = new_user.into_inner();
+    let locale = match &new_user.language {
+        Some(language) => Locale::from_tag(language).ok_or(Error::UnsupportedLanguage {
+            language: language.to_string(),
+        })?,
+        None => i18n::negotiate(&req, i18n::default_locale(&cfg)),
+    };
+    new_user.language = Some(locale.as_str().to_string());
+
     // Read configurations
-    let sender = cfg.email.sender.to_owned();
     let activation_url = cfg.activation_url.to_owned();
 
-    let (user_id, activation_code) = user_manager.create(new_user.into_inner()).await?;
+    let (user_id, activation_code) = user_manager.create(new_user).await?;
 
     let output = json!({
         "id": user_id,
     });
 
     // Notify a user about her newly created account.
-    // TODO: Use some template crate for email body.
-    let subject = String::from("Welcome to Tyto!");
     // Endpoint: www.localhost:8442/api/v1/users/{code}/activate
-    let mut body = String::from(
-        r#"Hi there,
-                
-           Your accouant in Tyto is successfully created.
-                  
-           Please visit {activation_url}/{code}.
-           
-           Regards,
-           Tyto Team"#,
-    );
-
-    // Replace placeholders with actual activation code
-    body = body.replace("{code}", &activation_code);
-    body = body.replace("{activation_url}", &activation_url);
-
-    /* |--IMP--|: As an alternative to creating new instance of EmailNotifier every time here, we can
-     * have a single instance wrapped in Arc<Mutex>. In current scenario where email sending is
-     * not performed in separate task/thread (it is sent in separate thread but it still takes time
-     * so it has to be sent in separate tokio task), it can slow down the whole process. But this is
-     * inevitable in future when the system matures.
-     */
-    let emailer = EmailNotifier::new(cfg, sender, email, subject, body);
-
+    // Notifications are delivered on separate tokio tasks, so this does not wait for SMTP server.
+    let args = [
+        ("activation_url", activation_url),
+        ("code", activation_code),
+    ];
     // TODO: Use log here
-    match emailer.send().await {
-        Ok(_) => println!("Email sent sucessfully"),
-        Err(e) => println!("Error: {:?}", e),
+    if let Err(e) = dispatcher
+        .notify(user_id, NotificationEvent::AccountCreated, &args)
+        .await
+    {
+        println!("Error: {:?}", e);
     }
 
     let response = Response {
@@ -90,9 +90,28 @@ pub async fn create_user(
 pub async fn activate(
     activation_code: web::Path<String>,
     user_manager: web::Data<TytoUserManager>,
+    dispatcher: web::Data<NotificationDispatcher>,
+    webhooks: web::Data<WebhookManager>,
 ) -> Result<HttpResponse, Error> {
     let activation_code = activation_code.into_inner();
-    user_manager.activate(activation_code).await?;
+    let user_id = user_manager.activate(activation_code).await?;
+
+    // TODO: Use log here
+    let data = json!({ "id": user_id });
+    if let Err(e) = webhooks
+        .emit(user_id, WebhookEvent::UserActivated, data)
+        .await
+    {
+        println!("Error: {:?}", e);
+    }
+
+    // TODO: Use log here
+    if let Err(e) = dispatcher
+        .notify(user_id, NotificationEvent::AccountActivated, &[])
+        .await
+    {
+        println!("Error: {:?}", e);
+    }
 
     Ok(HttpResponse::build(StatusCode::OK).finish())
 }
@@ -122,12 +141,21 @@ pub async fn delete_user(
     Ok(HttpResponse::build(StatusCode::OK).finish())
 }
 
-/// Web handler - User login
+/// Web handler - User login. Attempts are rate limited per client IP address.
 pub async fn login(
+    req: HttpRequest,
     login_request: web::Json<LoginRequest>,
+    state: web::Data<State>,
     user_manager: web::Data<TytoUserManager>,
 ) -> Result<HttpResponse, Error> {
-    // TODO: Return 404 code. Currently it returns 500 because it is difficult to have disctinction between different database errors.
+    let client_ip = analytics::client_ip(&req, &state.config.trusted_proxies)
+        .map(|ip| ip.to_string())
+        .unwrap_or_default();
+    state
+        .rate_limiter
+        .check("login", &client_ip, state.config.rate_limit.login_attempts)
+        .await?;
+
     let login_request = login_request.into_inner();
     let token = user_manager.login(login_request).await?;
 
@@ -182,3 +210,58 @@ pub async fn logout(
 
     Ok(HttpResponse::build(StatusCode::OK).json(response))
 }
+
+/// Web handler - Returns notification preferences of the logged in user associated with {id}
+pub async fn get_notification_preferences(
+    req: HttpRequest,
+    user_id: web::Path<i64>,
+    state: web::Data<State>,
+    dispatcher: web::Data<NotificationDispatcher>,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id.into_inner();
+    authorize_user(&req, &state, user_id).await?;
+    let preferences = dispatcher.preferences(user_id).await?;
+
+    // Prepare response
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(preferences).unwrap(),
+    };
+
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Replaces notification preferences of the logged in user associated with {id}
+/// Events without any preference are delivered through their default channels, and events
+/// with only a preference without channel are not delivered at all.
+pub async fn set_notification_preferences(
+    req: HttpRequest,
+    user_id: web::Path<i64>,
+    preferences: web::Json<Vec<NotificationPreference>>,
+    state: web::Data<State>,
+    dispatcher: web::Data<NotificationDispatcher>,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id.into_inner();
+    authorize_user(&req, &state, user_id).await?;
+    dispatcher
+        .set_preferences(user_id, preferences.into_inner())
+        .await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Sets preferred language of a user used for emails
+pub async fn set_language(
+    req: HttpRequest,
+    user_id: web::Path<i64>,
+    input: web::Json<SetLanguageRequest>,
+    state: web::Data<State>,
+    user_manager: web::Data<TytoUserManager>,
+) -> Result<HttpResponse, Error> {
+    let user_id = user_id.into_inner();
+    authorize_user(&req, &state, user_id).await?;
+    user_manager
+        .set_language(user_id, input.into_inner().language)
+        .await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
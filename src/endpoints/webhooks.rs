@@ -0,0 +1,136 @@
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{self, CreateWebhookRequest, UpdateWebhookRequest};
+use crate::utils::authenticate;
+use crate::webhooks::WebhookManager;
+use actix_web::{
+    http::StatusCode,
+    web::{self, Path},
+    HttpRequest, HttpResponse,
+};
+use serde_json::{self, json};
+
+/// Web handler - Registers a new webhook for the logged in user
+pub async fn create_webhook(
+    req: HttpRequest,
+    input: web::Json<CreateWebhookRequest>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let webhook = webhooks.create(claim.id, input.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(webhook).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::CREATED).json(response))
+}
+
+/// Web handler - Returns all the webhooks of the logged in user
+pub async fn get_webhooks(
+    req: HttpRequest,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let found_webhooks = webhooks.get_all(claim.id).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(found_webhooks).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Updates URL, events or state of a webhook
+pub async fn update_webhook(
+    req: HttpRequest,
+    id: Path<i64>,
+    input: web::Json<UpdateWebhookRequest>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let webhook = webhooks
+        .update(claim.id, id.into_inner(), input.into_inner())
+        .await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(webhook).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Deletes a webhook
+pub async fn delete_webhook(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    webhooks.delete(claim.id, id.into_inner()).await?;
+    Ok(HttpResponse::build(StatusCode::OK).finish())
+}
+
+/// Web handler - Returns delivery log of a webhook
+pub async fn get_deliveries(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let deliveries = webhooks.deliveries(claim.id, id.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: serde_json::to_value(deliveries).unwrap(),
+    };
+    Ok(HttpResponse::build(StatusCode::OK).json(response))
+}
+
+/// Web handler - Delivers payload of an earlier delivery again
+pub async fn redeliver(
+    req: HttpRequest,
+    path: Path<(i64, i64)>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let (webhook_id, delivery_id) = path.into_inner();
+    let new_delivery_id = webhooks
+        .redeliver(claim.id, webhook_id, delivery_id)
+        .await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: json!({ "delivery_id": new_delivery_id }),
+    };
+    Ok(HttpResponse::build(StatusCode::ACCEPTED).json(response))
+}
+
+/// Web handler - Sends a ping event to a webhook to test the receiver
+pub async fn ping(
+    req: HttpRequest,
+    id: Path<i64>,
+    state: web::Data<State>,
+    webhooks: web::Data<WebhookManager>,
+) -> Result<HttpResponse, Error> {
+    let claim = authenticate(&req, &state).await?;
+    let delivery_id = webhooks.ping(claim.id, id.into_inner()).await?;
+
+    let response = types::Response {
+        status: types::Status::Success,
+        message: None,
+        data: json!({ "delivery_id": delivery_id }),
+    };
+    Ok(HttpResponse::build(StatusCode::ACCEPTED).json(response))
+}
@@ -20,6 +20,28 @@ pub enum Error {
         source: lettre::transport::smtp::Error,
     },
 
+    #[snafu(display("Email file transport Error: {}", source))]
+    EmailFile {
+        source: lettre::transport::file::Error,
+    },
+
+    #[snafu(display("Invalid email message: {}", source))]
+    EmailMessage { source: lettre::error::Error },
+
+    #[snafu(display("Invalid email address: {}", source))]
+    InvalidEmailAddress {
+        source: lettre::address::AddressError,
+    },
+
+    #[snafu(display("Invalid email configuration: {}", message))]
+    InvalidEmailConfig { message: String },
+
+    #[snafu(display("Notification delivery Error: {}", source))]
+    Notification { source: reqwest::Error },
+
+    #[snafu(display("Notification log Error: {}", source))]
+    NotificationLog { source: std::io::Error },
+
     #[snafu(display("Invalid email"))]
     InvalidEmail,
 
@@ -43,6 +65,189 @@ pub enum Error {
 
     #[snafu(display("Token time must be between 1 to 60 minutes"))]
     InvalidTokenExpirationTime,
+
+    #[snafu(display("Authorization token is missing. Please login to obtain a token"))]
+    MissingToken,
+
+    #[snafu(display("URL not found."))]
+    UrlNotFound,
+
+    #[snafu(display("Webhook not found."))]
+    WebhookNotFound,
+
+    #[snafu(display("Webhook delivery not found."))]
+    WebhookDeliveryNotFound,
+
+    #[snafu(display("Webhook URL must be an absolute http or https URL."))]
+    InvalidWebhookUrl,
+
+    #[snafu(display("Unknown time zone {}.", timezone))]
+    InvalidTimezone { timezone: String },
+
+    #[snafu(display("Digest hour must be between 0 and 23."))]
+    InvalidDigestHour,
+
+    #[snafu(display("Digest subscription not found."))]
+    DigestSubscriptionNotFound,
+
+    #[snafu(display("Unsupported language {}.", language))]
+    UnsupportedLanguage { language: String },
+
+    #[snafu(display("Start of a time range must be before its end."))]
+    InvalidTimeRange,
+
+    #[snafu(display(
+        "Time range has more than {} buckets. Use a shorter range or a longer interval.",
+        max
+    ))]
+    TooManyBuckets { max: i64 },
+
+    #[snafu(display("Error in reading GeoIP database: {}", source))]
+    GeoIpDatabase { source: maxminddb::MaxMindDBError },
+
+    #[snafu(display("Administrator access is required."))]
+    AdminRequired,
+
+    #[snafu(display("Bot signature not found."))]
+    BotSignatureNotFound,
+
+    #[snafu(display("Bot signature already exists."))]
+    DuplicateBotSignature,
+
+    #[snafu(display("Bot signature pattern must be between 3 and 255 characters."))]
+    InvalidBotSignature,
+
+    #[snafu(display("Campaign not found."))]
+    CampaignNotFound,
+
+    #[snafu(display("Campaign with this name already exists."))]
+    DuplicateCampaign,
+
+    #[snafu(display("Campaign name must be between 1 and 255 characters."))]
+    InvalidCampaignName,
+
+    #[snafu(display("Conversion goal not found."))]
+    GoalNotFound,
+
+    #[snafu(display("Conversion goal with this name already exists."))]
+    DuplicateGoal,
+
+    #[snafu(display("Conversion goal name must be between 1 and 255 characters."))]
+    InvalidGoalName,
+
+    #[snafu(display("Click not found or too old to be converted."))]
+    ClickNotFound,
+
+    #[snafu(display("Shared store Error: {}", source))]
+    Store { source: redis::RedisError },
+
+    #[snafu(display("Too many requests. Please try again later"))]
+    TooManyRequests,
+
+    #[snafu(display("Token has been revoked. Please login again to obtain new token"))]
+    RevokedToken,
+
+    #[snafu(display("URL is already shortened."))]
+    DuplicateUrl,
+
+    #[snafu(display("User with this email already exists."))]
+    DuplicateUser,
+
+    #[snafu(display("Webhook URL must reach a public address."))]
+    WebhookUrlNotAllowed,
+
+    #[snafu(display(
+        "Set ip_hash_salt of analytics settings to a secret, or turn rotate_ip_salt on."
+    ))]
+    InsecureIpHashSalt,
+
+    #[snafu(display("Clicks are streamed live only for a link or a user."))]
+    UnsupportedLiveScope,
+
+    #[snafu(display("Link has expired."))]
+    UrlExpired,
+}
+
+impl Error {
+    /// Returns the key of the error message in message catalogs. See [crate::i18n].
+    pub fn message_key(&self) -> &'static str {
+        use Error::*;
+        match self {
+            Database { source: _ } => "error.database",
+            ConfigFile { source: _ } => "error.config_file",
+            ConfigRead { source: _ } => "error.config_read",
+            Email { source: _ } => "error.email",
+            EmailFile { source: _ } => "error.email_file",
+            EmailMessage { source: _ } => "error.email_message",
+            InvalidEmailAddress { source: _ } => "error.invalid_email_address",
+            InvalidEmailConfig { message: _ } => "error.invalid_email_config",
+            Notification { source: _ } => "error.notification",
+            NotificationLog { source: _ } => "error.notification_log",
+            InvalidEmail => "error.invalid_email",
+            AccountAlreadyActivated => "error.account_already_activated",
+            InvalidActivationToken => "error.invalid_activation_token",
+            MigrationFailed { source: _ } => "error.migration_failed",
+            UserNotFound => "error.user_not_found",
+            Base64Decode { source: _ } => "error.base64_decode",
+            InvalidToken { source: _ } => "error.invalid_token",
+            InvalidTokenExpirationTime => "error.invalid_token_expiration_time",
+            MissingToken => "error.missing_token",
+            UrlNotFound => "error.url_not_found",
+            WebhookNotFound => "error.webhook_not_found",
+            WebhookDeliveryNotFound => "error.webhook_delivery_not_found",
+            InvalidWebhookUrl => "error.invalid_webhook_url",
+            InvalidTimezone { timezone: _ } => "error.invalid_timezone",
+            InvalidDigestHour => "error.invalid_digest_hour",
+            DigestSubscriptionNotFound => "error.digest_subscription_not_found",
+            UnsupportedLanguage { language: _ } => "error.unsupported_language",
+            InvalidTimeRange => "error.invalid_time_range",
+            TooManyBuckets { max: _ } => "error.too_many_buckets",
+            GeoIpDatabase { source: _ } => "error.geoip_database",
+            AdminRequired => "error.admin_required",
+            BotSignatureNotFound => "error.bot_signature_not_found",
+            DuplicateBotSignature => "error.duplicate_bot_signature",
+            InvalidBotSignature => "error.invalid_bot_signature",
+            CampaignNotFound => "error.campaign_not_found",
+            DuplicateCampaign => "error.duplicate_campaign",
+            InvalidCampaignName => "error.invalid_campaign_name",
+            GoalNotFound => "error.goal_not_found",
+            DuplicateGoal => "error.duplicate_goal",
+            InvalidGoalName => "error.invalid_goal_name",
+            ClickNotFound => "error.click_not_found",
+            Store { source: _ } => "error.store",
+            TooManyRequests => "error.too_many_requests",
+            RevokedToken => "error.revoked_token",
+            DuplicateUrl => "error.duplicate_url",
+            DuplicateUser => "error.duplicate_user",
+            WebhookUrlNotAllowed => "error.webhook_url_not_allowed",
+            InsecureIpHashSalt => "error.insecure_ip_hash_salt",
+            UnsupportedLiveScope => "error.unsupported_live_scope",
+            UrlExpired => "error.url_expired",
+        }
+    }
+
+    /// Returns values of the placeholders in the error message.
+    pub fn message_args(&self) -> Vec<(&'static str, String)> {
+        use Error::*;
+        match self {
+            Database { source } => vec![("source", source.to_string())],
+            ConfigFile { source } => vec![("source", source.to_string())],
+            ConfigRead { source } => vec![("source", source.to_string())],
+            Email { source } => vec![("source", source.to_string())],
+            EmailFile { source } => vec![("source", source.to_string())],
+            EmailMessage { source } => vec![("source", source.to_string())],
+            InvalidEmailAddress { source } => vec![("source", source.to_string())],
+            InvalidEmailConfig { message } => vec![("message", message.to_string())],
+            Notification { source } => vec![("source", source.to_string())],
+            NotificationLog { source } => vec![("source", source.to_string())],
+            InvalidTimezone { timezone } => vec![("timezone", timezone.to_string())],
+            UnsupportedLanguage { language } => vec![("language", language.to_string())],
+            TooManyBuckets { max } => vec![("max", max.to_string())],
+            GeoIpDatabase { source } => vec![("source", source.to_string())],
+            Store { source } => vec![("source", source.to_string())],
+            _ => vec![],
+        }
+    }
 }
 
 impl ResponseError for Error {
@@ -53,6 +258,12 @@ impl ResponseError for Error {
             ConfigFile { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigRead { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Email { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            EmailFile { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            EmailMessage { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            InvalidEmailAddress { source: _ } => StatusCode::BAD_REQUEST,
+            InvalidEmailConfig { message: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            Notification { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            NotificationLog { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             MigrationFailed { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             InvalidEmail => StatusCode::BAD_REQUEST,
             InvalidActivationToken => StatusCode::BAD_REQUEST,
@@ -61,6 +272,38 @@ impl ResponseError for Error {
             Base64Decode { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             InvalidToken { source: _ } => StatusCode::UNAUTHORIZED,
             InvalidTokenExpirationTime => StatusCode::INTERNAL_SERVER_ERROR,
+            MissingToken => StatusCode::UNAUTHORIZED,
+            UrlNotFound => StatusCode::NOT_FOUND,
+            WebhookNotFound => StatusCode::NOT_FOUND,
+            WebhookDeliveryNotFound => StatusCode::NOT_FOUND,
+            InvalidWebhookUrl => StatusCode::BAD_REQUEST,
+            InvalidTimezone { timezone: _ } => StatusCode::BAD_REQUEST,
+            InvalidDigestHour => StatusCode::BAD_REQUEST,
+            DigestSubscriptionNotFound => StatusCode::NOT_FOUND,
+            UnsupportedLanguage { language: _ } => StatusCode::BAD_REQUEST,
+            InvalidTimeRange => StatusCode::BAD_REQUEST,
+            TooManyBuckets { max: _ } => StatusCode::BAD_REQUEST,
+            GeoIpDatabase { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            AdminRequired => StatusCode::FORBIDDEN,
+            BotSignatureNotFound => StatusCode::NOT_FOUND,
+            DuplicateBotSignature => StatusCode::CONFLICT,
+            InvalidBotSignature => StatusCode::BAD_REQUEST,
+            CampaignNotFound => StatusCode::NOT_FOUND,
+            DuplicateCampaign => StatusCode::CONFLICT,
+            InvalidCampaignName => StatusCode::BAD_REQUEST,
+            GoalNotFound => StatusCode::NOT_FOUND,
+            DuplicateGoal => StatusCode::CONFLICT,
+            InvalidGoalName => StatusCode::BAD_REQUEST,
+            ClickNotFound => StatusCode::NOT_FOUND,
+            Store { source: _ } => StatusCode::SERVICE_UNAVAILABLE,
+            TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            RevokedToken => StatusCode::UNAUTHORIZED,
+            DuplicateUrl => StatusCode::CONFLICT,
+            DuplicateUser => StatusCode::CONFLICT,
+            WebhookUrlNotAllowed => StatusCode::BAD_REQUEST,
+            InsecureIpHashSalt => StatusCode::INTERNAL_SERVER_ERROR,
+            UnsupportedLiveScope => StatusCode::BAD_REQUEST,
+            UrlExpired => StatusCode::GONE,
         };
 
         let response = types::Response {
@@ -97,6 +340,30 @@ impl From<lettre::transport::smtp::Error> for Error {
     }
 }
 
+impl From<lettre::transport::file::Error> for Error {
+    fn from(source: lettre::transport::file::Error) -> Error {
+        Error::EmailFile { source }
+    }
+}
+
+impl From<lettre::error::Error> for Error {
+    fn from(source: lettre::error::Error) -> Error {
+        Error::EmailMessage { source }
+    }
+}
+
+impl From<lettre::address::AddressError> for Error {
+    fn from(source: lettre::address::AddressError) -> Error {
+        Error::InvalidEmailAddress { source }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(source: reqwest::Error) -> Error {
+        Error::Notification { source }
+    }
+}
+
 impl From<MigrateError> for Error {
     fn from(source: MigrateError) -> Error {
         Error::MigrationFailed { source }
@@ -114,3 +381,15 @@ impl From<jwt_simple::Error> for Error {
         Error::InvalidToken { source }
     }
 }
+
+impl From<maxminddb::MaxMindDBError> for Error {
+    fn from(source: maxminddb::MaxMindDBError) -> Error {
+        Error::GeoIpDatabase { source }
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(source: redis::RedisError) -> Error {
+        Error::Store { source }
+    }
+}
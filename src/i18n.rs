@@ -0,0 +1,280 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::types;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::ServiceResponse,
+    http::header,
+    HttpRequest, HttpResponse,
+};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Languages tyto has message catalogs for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Locale {
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+impl Locale {
+    /// All the supported locales.
+    pub const ALL: [Locale; 4] = [Locale::En, Locale::De, Locale::Fr, Locale::Es];
+
+    /// Returns the language tag of the locale.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Es => "es",
+        }
+    }
+
+    /// Returns the locale for a language tag. Only the primary language subtag is considered,
+    /// so de-AT and de-CH both fall back to German.
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.trim().split(&['-', '_'][..]).next()?.to_lowercase();
+        Locale::ALL
+            .iter()
+            .copied()
+            .find(|locale| locale.as_str() == language)
+    }
+
+    /// Returns the source of the message catalog of the locale.
+    fn catalog_source(&self) -> &'static str {
+        match self {
+            Locale::En => include_str!("../locales/en.toml"),
+            Locale::De => include_str!("../locales/de.toml"),
+            Locale::Fr => include_str!("../locales/fr.toml"),
+            Locale::Es => include_str!("../locales/es.toml"),
+        }
+    }
+}
+
+/// Returns the configured default locale. English is used if it is not supported, though
+/// configuration validation does not let that happen.
+pub fn default_locale(cfg: &Config) -> Locale {
+    Locale::from_tag(&cfg.default_language).unwrap_or(Locale::En)
+}
+
+/// Flattens nested TOML tables into dotted keys like `email.account_created.subject`.
+fn flatten(prefix: &str, value: &toml::Value, messages: &mut HashMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, messages);
+            }
+        }
+        toml::Value::String(message) => {
+            messages.insert(prefix.to_string(), message.to_string());
+        }
+        _ => {}
+    }
+}
+
+/// Returns message catalogs of all the locales. They are parsed once on first use.
+fn catalogs() -> &'static HashMap<Locale, HashMap<String, String>> {
+    static CATALOGS: OnceLock<HashMap<Locale, HashMap<String, String>>> = OnceLock::new();
+    CATALOGS.get_or_init(|| {
+        Locale::ALL
+            .iter()
+            .map(|locale| {
+                // Catalogs are compiled in, so a broken one is a bug.
+                let value: toml::Value = toml::from_str(locale.catalog_source())
+                    .expect("Failed to parse message catalog");
+                let mut messages = HashMap::new();
+                flatten("", &value, &mut messages);
+                (*locale, messages)
+            })
+            .collect()
+    })
+}
+
+/// Returns the message for a key in a locale with `{name}` placeholders replaced by args.
+/// Messages missing in a catalog fall back to English and then to the key itself.
+pub fn translate(locale: Locale, key: &str, args: &[(&str, String)]) -> String {
+    let catalogs = catalogs();
+    let mut message = catalogs
+        .get(&locale)
+        .and_then(|messages| messages.get(key))
+        .or_else(|| {
+            catalogs
+                .get(&Locale::En)
+                .and_then(|messages| messages.get(key))
+        })
+        .cloned()
+        .unwrap_or_else(|| key.to_string());
+
+    for (name, value) in args {
+        message = message.replace(&format!("{{{}}}", name), value);
+    }
+    message
+}
+
+/// Picks the locale for a request from its Accept-Language header, honouring quality values.
+/// Returns `default` when the header is absent, has no supported language or prefers any
+/// language with `*`.
+pub fn negotiate(req: &HttpRequest, default: Locale) -> Locale {
+    let accept_language = match req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(accept_language) => accept_language,
+        None => return default,
+    };
+
+    let mut languages: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next().unwrap_or("").trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (tag, quality)
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    // Stable sort keeps the header order among equal qualities.
+    languages.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    // A wildcard accepts any language, so the default one is as good as others after it.
+    languages
+        .into_iter()
+        .find_map(|(tag, _)| match tag {
+            "*" => Some(default),
+            tag => Locale::from_tag(tag),
+        })
+        .unwrap_or(default)
+}
+
+/// Replaces the message of an error response produced from [Error] with the one from the
+/// catalog of the locale. Other responses are passed as they are.
+pub fn localize_response<B>(res: ServiceResponse<B>, locale: Locale) -> ServiceResponse<BoxBody>
+where
+    B: MessageBody + 'static,
+{
+    let message = res
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<Error>())
+        .map(|e| translate(locale, e.message_key(), &e.message_args()));
+
+    match message {
+        Some(message) => {
+            let status = res.status();
+            let response = types::Response {
+                status: types::Status::Failure,
+                message: Some(message),
+                data: serde_json::from_str("{}").unwrap(),
+            };
+            let (req, _) = res.into_parts();
+            let localized = HttpResponse::build(status)
+                .insert_header((header::CONTENT_LANGUAGE, locale.as_str()))
+                .json(response);
+            ServiceResponse::new(req, localized)
+        }
+        None => res.map_into_boxed_body(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::collections::BTreeSet;
+
+    fn negotiate_header(accept_language: &str) -> Locale {
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT_LANGUAGE, accept_language))
+            .to_http_request();
+        negotiate(&req, Locale::En)
+    }
+
+    #[test]
+    fn negotiates_by_quality() {
+        assert_eq!(negotiate_header("fr"), Locale::Fr);
+        assert_eq!(negotiate_header("fr;q=0.5, es;q=0.8, de;q=0.1"), Locale::Es);
+        assert_eq!(negotiate_header("ja, es;q=0.3, fr;q=0.2"), Locale::Es);
+        // Header order decides among equal qualities.
+        assert_eq!(negotiate_header("es;q=0.5, fr;q=0.5"), Locale::Es);
+        // Languages with zero quality are not acceptable.
+        assert_eq!(negotiate_header("de;q=0, fr;q=0.1"), Locale::Fr);
+        assert_eq!(negotiate_header("de;q=0"), Locale::En);
+    }
+
+    #[test]
+    fn negotiates_regional_tags() {
+        assert_eq!(negotiate_header("de-AT"), Locale::De);
+        assert_eq!(negotiate_header("pt-BR, es_MX;q=0.9"), Locale::Es);
+        assert_eq!(negotiate_header("FR-ca"), Locale::Fr);
+    }
+
+    #[test]
+    fn negotiates_wildcards() {
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT_LANGUAGE, "*"))
+            .to_http_request();
+        assert_eq!(negotiate(&req, Locale::De), Locale::De);
+        assert_eq!(negotiate_header("*, fr;q=0.5"), Locale::En);
+        assert_eq!(negotiate_header("fr, *;q=0.5"), Locale::Fr);
+        assert_eq!(negotiate_header("ja, *;q=0.1, es;q=0.05"), Locale::En);
+    }
+
+    #[test]
+    fn negotiates_malformed_headers() {
+        assert_eq!(
+            negotiate(&TestRequest::default().to_http_request(), Locale::Fr),
+            Locale::Fr
+        );
+        assert_eq!(negotiate_header(""), Locale::En);
+        assert_eq!(negotiate_header(",;, ;q=1,"), Locale::En);
+        assert_eq!(negotiate_header("de;q=high, fr;q=0.5"), Locale::De);
+        assert_eq!(negotiate_header("de;q=NaN, fr;q=0.5"), Locale::Fr);
+        assert_eq!(negotiate_header("-de, x-fr, es"), Locale::Es);
+    }
+
+    /// Returns `{name}` placeholders of a message.
+    fn placeholders(message: &str) -> BTreeSet<&str> {
+        message
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split('}').next())
+            .collect()
+    }
+
+    #[test]
+    fn catalogs_have_the_same_keys_and_placeholders() {
+        let catalogs = catalogs();
+        let english = &catalogs[&Locale::En];
+        let keys: BTreeSet<&String> = english.keys().collect();
+        for locale in Locale::ALL.iter() {
+            let messages = &catalogs[locale];
+            assert_eq!(
+                messages.keys().collect::<BTreeSet<_>>(),
+                keys,
+                "keys of {} differ from en",
+                locale.as_str()
+            );
+            for (key, message) in messages {
+                assert_eq!(
+                    placeholders(message),
+                    placeholders(&english[key]),
+                    "placeholders of {} in {} differ from en",
+                    key,
+                    locale.as_str()
+                );
+            }
+        }
+    }
+}
@@ -1,25 +1,50 @@
 extern crate base64;
 extern crate serde_json;
 
-use crate::config::Config;
-use actix_web::{web, App, HttpResponse, HttpServer};
+use crate::config::{Config, IpStorage, RepositoryBackend};
+use actix_web::{dev::Service, web, App, HttpResponse, HttpServer};
+use analytics::bots::BotFilter;
+use analytics::buffer::ClickBuffer;
+use analytics::geoip::{GeoIp, GeoIpReloadJob};
+use analytics::live::LiveClicks;
+use analytics::privacy::{IpAnonymizer, IpSaltRotationJob};
+use analytics::rollups::RollupJob;
+use campaigns::CampaignManager;
 use clap::Parser;
+use conversions::{ClickPurgeJob, ConversionManager};
+use digests::{DigestJob, DigestManager};
 use error::Error;
+use notifiers::NotificationDispatcher;
+use scheduler::Scheduler;
 use sqlx::{self};
+use std::sync::Arc;
 use std::{fs, path::Path};
 use user_management::TytoUserManager;
+use webhooks::WebhookManager;
 
+mod analytics;
+mod cache;
+mod campaigns;
 mod config;
 mod constants;
+mod conversions;
 mod core;
 mod db;
-mod emailer;
+mod digests;
 mod endpoints;
 mod error;
+mod i18n;
+mod notifiers;
+mod rate_limit;
+mod repositories;
+mod revocations;
+mod scheduler;
 mod state;
+mod store;
 mod types;
 mod user_management;
 pub mod utils;
+mod webhooks;
 
 /// Tiny URL generator
 #[derive(Parser, Debug)]
@@ -38,29 +63,98 @@ async fn main() -> Result<(), Error> {
     let config_path = fs::canonicalize(args.config)?;
     let cfg = read_config(config_path).await?;
 
-    // Database pool creation
+    // Database pool creation. Links and users kept in memory do not need a database, so the
+    // pool then connects on first use and migrations are left for a server using Postgres.
     let db_connection_string = db::get_db_conn_string(&cfg).await;
-    let db_connection_pool = db::get_database_connection(&db_connection_string).await?;
+    let (db_connection_pool, read_connection_pool) = match cfg.repository.backend {
+        RepositoryBackend::Postgres => {
+            let pool = db::get_database_connection(&db_connection_string, &cfg.database).await?;
+            let read_pool = db::get_read_connection(&pool, &cfg.database).await?;
 
-    // Database migration
-    sqlx::migrate!("./migrations")
-        .run(&db_connection_pool)
-        .await?;
+            // Database migration
+            sqlx::migrate!("./migrations").run(&pool).await?;
+            (pool, read_pool)
+        }
+        RepositoryBackend::Memory => {
+            let pool = db::get_lazy_database_connection(&db_connection_string, &cfg.database)?;
+            (pool.clone(), pool)
+        }
+    };
 
     // Prepare data to be shared. web::Data is Arc, so we can safely share and send it across workers.
-    let state = state::State::new(cfg.clone(), db_connection_pool);
+    let state = state::State::new(cfg.clone(), db_connection_pool, read_connection_pool).await?;
     let shared_state = web::Data::new(state);
     let shared_user_manager = web::Data::new(TytoUserManager::new(shared_state.clone()));
+    let shared_dispatcher = web::Data::new(
+        NotificationDispatcher::new(shared_state.clone(), shared_user_manager.clone()).await?,
+    );
+    let shared_webhooks = web::Data::new(WebhookManager::new(shared_state.clone())?);
+    let shared_digests = web::Data::new(DigestManager::new(shared_state.clone()));
+    let shared_campaigns = web::Data::new(CampaignManager::new(shared_state.clone()));
+    let shared_conversions = web::Data::new(ConversionManager::new(shared_state.clone()));
     let shared_config = web::Data::new(cfg.clone());
+    let shared_geoip = web::Data::new(GeoIp::new(&cfg.analytics));
+    let shared_bots = web::Data::new(BotFilter::new(shared_state.clone()).await?);
+    let shared_ips = web::Data::new(IpAnonymizer::new(shared_state.clone()).await?);
+    let shared_live = web::Data::new(LiveClicks::new());
+    let shared_clicks = web::Data::new(ClickBuffer::new(
+        shared_state.clone(),
+        shared_webhooks.clone(),
+        shared_live.clone(),
+    ));
+    ClickBuffer::start(shared_clicks.clone());
+    let clicks = shared_clicks.clone();
+
+    // Background jobs. Digests, rollups and clicks are kept only in database, so their jobs are
+    // left for a server using Postgres.
+    let mut scheduler = Scheduler::new();
+    if cfg.repository.backend == RepositoryBackend::Postgres {
+        scheduler.register(Arc::new(DigestJob::new(
+            shared_state.clone(),
+            shared_dispatcher.clone(),
+        )));
+        scheduler.register(Arc::new(RollupJob::new(
+            shared_state.clone(),
+            &cfg.analytics,
+        )));
+        scheduler.register(Arc::new(ClickPurgeJob::new(shared_conversions.clone())));
+    }
+    if cfg.analytics.geoip_database.is_some() {
+        scheduler.register(Arc::new(GeoIpReloadJob::new(
+            shared_geoip.clone(),
+            &cfg.analytics,
+        )));
+    }
+    if cfg.analytics.rotate_ip_salt {
+        scheduler.register(Arc::new(IpSaltRotationJob::new(shared_ips.clone())));
+    }
+    scheduler.start();
 
     let ip_port = format!("{}:{}", cfg.ip, cfg.port);
     println!("Starting server at: {}", ip_port);
 
     HttpServer::new(move || {
+        let default_locale = i18n::default_locale(&cfg);
         App::new()
+            // Localize error messages as per Accept-Language header.
+            .wrap_fn(move |req, srv| {
+                let locale = i18n::negotiate(req.request(), default_locale);
+                let fut = srv.call(req);
+                async move { Ok(i18n::localize_response(fut.await?, locale)) }
+            })
             .app_data(shared_state.clone())
             .app_data(shared_user_manager.clone())
+            .app_data(shared_dispatcher.clone())
+            .app_data(shared_webhooks.clone())
+            .app_data(shared_digests.clone())
+            .app_data(shared_campaigns.clone())
+            .app_data(shared_conversions.clone())
             .app_data(shared_config.clone())
+            .app_data(shared_geoip.clone())
+            .app_data(shared_bots.clone())
+            .app_data(shared_ips.clone())
+            .app_data(shared_live.clone())
+            .app_data(shared_clicks.clone())
             .service(
                 web::scope("/api/v1")
                     .service(
@@ -68,13 +162,99 @@ async fn main() -> Result<(), Error> {
                             .route("", web::get().to(endpoints::urls::get_urls))
                             .route("", web::post().to(endpoints::urls::post_url))
                             .route("/{id}", web::delete().to(endpoints::urls::delete_url))
-                            .route("/{id}", web::get().to(endpoints::urls::get_shortened_url)),
+                            .route("/{id}", web::patch().to(endpoints::urls::update_url))
+                            .route("/{id}", web::get().to(endpoints::urls::get_shortened_url))
+                            .route(
+                                "/{id}/visits",
+                                web::get().to(endpoints::analytics::get_visits),
+                            )
+                            .route(
+                                "/{id}/stats",
+                                web::get().to(endpoints::analytics::get_stats),
+                            )
+                            .route("/{id}/live", web::get().to(endpoints::analytics::get_live))
+                            .route(
+                                "/{id}/live/token",
+                                web::post().to(endpoints::analytics::post_live_token),
+                            )
+                            .route(
+                                "/{id}/conversions",
+                                web::get().to(endpoints::conversions::get_url_conversions),
+                            )
+                            .route(
+                                "/{id}/export/visits",
+                                web::get().to(endpoints::analytics::get_visits_export),
+                            )
+                            .route(
+                                "/{id}/export/stats",
+                                web::get().to(endpoints::analytics::get_stats_export),
+                            )
+                            .route(
+                                "/{id}/referrers",
+                                web::get().to(endpoints::analytics::get_referrers),
+                            )
+                            .route(
+                                "/{id}/utm/{parameter}",
+                                web::get().to(endpoints::analytics::get_utm),
+                            )
+                            .route(
+                                "/{id}/geography",
+                                web::get().to(endpoints::analytics::get_geography),
+                            )
+                            .route(
+                                "/{id}/browsers",
+                                web::get().to(endpoints::analytics::get_browsers),
+                            )
+                            .route(
+                                "/{id}/os",
+                                web::get().to(endpoints::analytics::get_operating_systems),
+                            )
+                            .route(
+                                "/{id}/devices",
+                                web::get().to(endpoints::analytics::get_devices),
+                            ),
                     )
                     .service(
                         web::scope("/users")
                             .route("", web::get().to(endpoints::users::get_all_users))
                             .route("", web::post().to(endpoints::users::create_user))
                             .route("/{id}", web::delete().to(endpoints::users::delete_user))
+                            .route(
+                                "/{id}/language",
+                                web::put().to(endpoints::users::set_language),
+                            )
+                            .route(
+                                "/{id}/referrers",
+                                web::get().to(endpoints::analytics::get_user_referrers),
+                            )
+                            .route(
+                                "/{id}/live",
+                                web::get().to(endpoints::analytics::get_user_live),
+                            )
+                            .route(
+                                "/{id}/live/token",
+                                web::post().to(endpoints::analytics::post_user_live_token),
+                            )
+                            .route(
+                                "/{id}/export/visits",
+                                web::get().to(endpoints::analytics::get_user_visits_export),
+                            )
+                            .route(
+                                "/{id}/export/stats",
+                                web::get().to(endpoints::analytics::get_user_stats_export),
+                            )
+                            .route(
+                                "/{id}/utm/{parameter}",
+                                web::get().to(endpoints::analytics::get_user_utm),
+                            )
+                            .route(
+                                "/{id}/notifications",
+                                web::get().to(endpoints::users::get_notification_preferences),
+                            )
+                            .route(
+                                "/{id}/notifications",
+                                web::put().to(endpoints::users::set_notification_preferences),
+                            )
                             .route(
                                 "/activate/{code}",
                                 web::patch().to(endpoints::users::activate),
@@ -82,13 +262,137 @@ async fn main() -> Result<(), Error> {
                             .route("login", web::post().to(endpoints::users::login))
                             .route("logout", web::post().to(endpoints::users::logout)),
                     )
-                    .service(web::scope("admin").route("", web::get().to(HttpResponse::Ok))),
+                    .service(
+                        web::scope("/webhooks")
+                            .route("", web::get().to(endpoints::webhooks::get_webhooks))
+                            .route("", web::post().to(endpoints::webhooks::create_webhook))
+                            .route(
+                                "/{id}",
+                                web::patch().to(endpoints::webhooks::update_webhook),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete().to(endpoints::webhooks::delete_webhook),
+                            )
+                            .route("/{id}/ping", web::post().to(endpoints::webhooks::ping))
+                            .route(
+                                "/{id}/deliveries",
+                                web::get().to(endpoints::webhooks::get_deliveries),
+                            )
+                            .route(
+                                "/{id}/deliveries/{delivery_id}/redeliver",
+                                web::post().to(endpoints::webhooks::redeliver),
+                            ),
+                    )
+                    .service(
+                        web::scope("/campaigns")
+                            .route("", web::get().to(endpoints::campaigns::get_campaigns))
+                            .route("", web::post().to(endpoints::campaigns::create_campaign))
+                            .route("/{id}", web::get().to(endpoints::campaigns::get_campaign))
+                            .route(
+                                "/{id}",
+                                web::patch().to(endpoints::campaigns::update_campaign),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete().to(endpoints::campaigns::delete_campaign),
+                            )
+                            .route(
+                                "/{id}/urls",
+                                web::get().to(endpoints::campaigns::get_campaign_urls),
+                            )
+                            .route(
+                                "/{id}/urls/{url_id}",
+                                web::put().to(endpoints::campaigns::add_campaign_url),
+                            )
+                            .route(
+                                "/{id}/urls/{url_id}",
+                                web::delete().to(endpoints::campaigns::remove_campaign_url),
+                            )
+                            .route(
+                                "/{id}/stats",
+                                web::get().to(endpoints::campaigns::get_campaign_stats),
+                            )
+                            .route(
+                                "/{id}/export/visits",
+                                web::get().to(endpoints::analytics::get_campaign_visits_export),
+                            )
+                            .route(
+                                "/{id}/export/stats",
+                                web::get().to(endpoints::analytics::get_campaign_stats_export),
+                            )
+                            .route(
+                                "/{id}/conversions",
+                                web::get().to(endpoints::conversions::get_campaign_conversions),
+                            ),
+                    )
+                    .service(
+                        web::scope("/goals")
+                            .route("", web::get().to(endpoints::conversions::get_goals))
+                            .route("", web::post().to(endpoints::conversions::create_goal))
+                            .route(
+                                "/{id}",
+                                web::patch().to(endpoints::conversions::update_goal),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete().to(endpoints::conversions::delete_goal),
+                            ),
+                    )
+                    .service(
+                        web::scope("/conversions")
+                            .route("", web::post().to(endpoints::conversions::post_conversion))
+                            .route(
+                                "/pixel.gif",
+                                web::get().to(endpoints::conversions::get_pixel),
+                            ),
+                    )
+                    .service(
+                        web::scope("/digests")
+                            .route("", web::get().to(endpoints::digests::get_digest))
+                            .route("", web::put().to(endpoints::digests::set_digest))
+                            .route("", web::delete().to(endpoints::digests::delete_digest))
+                            .route(
+                                "/unsubscribe/{token}",
+                                web::get().to(endpoints::digests::unsubscribe),
+                            ),
+                    )
+                    .service(
+                        web::scope("admin")
+                            .route("", web::get().to(HttpResponse::Ok))
+                            .route("/overview", web::get().to(endpoints::admin::get_overview))
+                            .route("/cache", web::get().to(endpoints::admin::get_cache_metrics))
+                            .route("/bots", web::get().to(endpoints::admin::get_bot_signatures))
+                            .route(
+                                "/bots",
+                                web::post().to(endpoints::admin::create_bot_signature),
+                            )
+                            .route(
+                                "/bots/{id}",
+                                web::delete().to(endpoints::admin::delete_bot_signature),
+                            ),
+                    ),
+            )
+            .service(
+                web::scope("")
+                    .route("/health", web::get().to(endpoints::health::health))
+                    .route(
+                        "/{address}+",
+                        web::get().to(endpoints::analytics::get_public_stats),
+                    )
+                    .route("/{address}", web::get().to(endpoints::urls::redirect)),
             )
-            .service(web::scope("").route("/health", web::get().to(endpoints::health::health)))
     })
     .bind(ip_port)?
     .run()
     .await?;
+
+    // Server has stopped accepting requests, so no more visits are buffered. Visits that can
+    // not be written now are lost, which is no reason to fail the shutdown.
+    if let Err(e) = clicks.drain().await {
+        // TODO: Use log here
+        println!("Error in flushing visits: {:?}", e);
+    }
     Ok(())
 }
 
@@ -103,10 +407,48 @@ async fn read_config<P: AsRef<Path>>(config_file_path: P) -> Result<Config, Erro
 }
 
 /// Performs various validations on the values from config file. Currently it validates the token
-/// expiration time which must be between 1 and 60 minutes including.
+/// expiration time which must be between 1 and 60 minutes including, the default language
+/// which must have a message catalog and the IP hash salt, which must be a secret when IP
+/// addresses are hashed without daily salts.
 async fn validate_config(c: &Config) -> Result<(), Error> {
     if c.auth.minutes < 1 || c.auth.minutes > 60 {
         return Err(error::Error::InvalidTokenExpirationTime);
     }
+    if i18n::Locale::from_tag(&c.default_language).is_none() {
+        return Err(error::Error::UnsupportedLanguage {
+            language: c.default_language.clone(),
+        });
+    }
+    let salt = c.analytics.ip_hash_salt.trim();
+    if c.analytics.ip_storage != IpStorage::None
+        && !c.analytics.rotate_ip_salt
+        && (salt.is_empty() || salt == constants::analytics::SAMPLE_IP_HASH_SALT)
+    {
+        return Err(error::Error::InsecureIpHashSalt);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sample_config_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml");
+        assert!(read_config(path).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn sample_salt_is_refused_without_rotation() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml");
+        let mut c = read_config(path).await.unwrap();
+        c.analytics.rotate_ip_salt = false;
+        assert!(matches!(
+            validate_config(&c).await,
+            Err(Error::InsecureIpHashSalt)
+        ));
+        c.analytics.ip_storage = IpStorage::None;
+        assert!(validate_config(&c).await.is_ok());
+    }
+}
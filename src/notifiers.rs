@@ -0,0 +1,200 @@
+pub mod email;
+pub mod log;
+pub mod slack;
+pub mod webhook;
+
+use crate::core::traits::{Notifier, UserManager};
+use crate::error::Error;
+use crate::i18n::{self, Locale};
+use crate::state::State;
+use crate::types::{Notification, NotificationChannel, NotificationEvent, NotificationPreference};
+use crate::user_management::TytoUserManager;
+use crate::webhooks;
+use actix_web::web;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Routes notifications to the channels a user has chosen for an event. It owns one long-lived
+/// [Notifier] per channel.
+pub struct NotificationDispatcher {
+    state: web::Data<State>,
+    user_manager: web::Data<TytoUserManager>,
+    notifiers: HashMap<NotificationChannel, Arc<dyn Notifier>>,
+}
+
+impl NotificationDispatcher {
+    /// Creates a new [NotificationDispatcher] with notifiers for all the supported channels.
+    pub async fn new(
+        state: web::Data<State>,
+        user_manager: web::Data<TytoUserManager>,
+    ) -> Result<Self, Error> {
+        let cfg = &state.config;
+        // Webhook and Slack destinations come from users, so they are reached like webhooks.
+        let client = webhooks::client(&cfg.webhooks)?;
+
+        let all_notifiers: Vec<Arc<dyn Notifier>> = vec![
+            Arc::new(email::EmailNotifier::new(cfg)?),
+            Arc::new(webhook::WebhookNotifier::new(client.clone())),
+            Arc::new(slack::SlackNotifier::new(client)),
+            Arc::new(log::LogNotifier::new(cfg.notifications.log_file.as_deref()).await?),
+        ];
+        let notifiers = all_notifiers
+            .into_iter()
+            .map(|notifier| (notifier.channel(), notifier))
+            .collect();
+
+        Ok(NotificationDispatcher {
+            state,
+            user_manager,
+            notifiers,
+        })
+    }
+
+    /// Notifies a user about an event.
+    /// How does it work:
+    /// 1. Find channels the user has chosen for the event. Fall back to the event's default
+    ///    channels when the user has no preferences for it, so opting out leaves no channels.
+    /// 2. Resolve a recipient for every channel.
+    /// 3. Deliver on a separate tokio task per channel so the caller does not wait for slow SMTP
+    ///    servers or webhooks. Webhook and Slack destinations are checked to reach only public
+    ///    addresses again, since their host names may resolve elsewhere by now. Delivery errors
+    ///    are only logged.
+    pub async fn dispatch(&self, user_id: i64, notification: Notification) -> Result<(), Error> {
+        let preferences: Vec<NotificationPreference> = self
+            .preferences(user_id)
+            .await?
+            .into_iter()
+            .filter(|p| p.event == notification.event)
+            .collect();
+        let mut targets: Vec<(NotificationChannel, Option<String>)> = preferences
+            .iter()
+            .filter_map(|p| Some((p.channel?, p.destination.clone())))
+            .collect();
+        if preferences.is_empty() {
+            targets = notification
+                .event
+                .default_channels()
+                .iter()
+                .map(|channel| (*channel, None))
+                .collect();
+        }
+
+        for (channel, destination) in targets {
+            let recipient = match self.recipient(user_id, channel, destination).await? {
+                Some(recipient) => recipient,
+                None => {
+                    // TODO: Use log here
+                    println!(
+                        "No destination for {} notification of user {}",
+                        channel.as_str(),
+                        user_id
+                    );
+                    continue;
+                }
+            };
+            let notifier = match self.notifiers.get(&channel) {
+                Some(notifier) => notifier.clone(),
+                None => continue,
+            };
+            let notification = notification.clone();
+            let state = self.state.clone();
+
+            tokio::spawn(async move {
+                if let NotificationChannel::Webhook | NotificationChannel::Slack = channel {
+                    if let Err(e) = webhooks::validate_url(&recipient, &state.config.webhooks).await
+                    {
+                        // TODO: Use log here
+                        println!("Error: {:?}", e);
+                        return;
+                    }
+                }
+                // TODO: Use log here
+                match notifier.send(&recipient, &notification).await {
+                    Ok(_) => println!("{} notification sent sucessfully", channel.as_str()),
+                    Err(e) => println!("Error: {:?}", e),
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns preferred locale of a user, or the configured default one.
+    pub async fn locale(&self, user_id: i64) -> Result<Locale, Error> {
+        let user = self.user_manager.get(user_id).await?;
+        let locale = user
+            .language
+            .as_deref()
+            .and_then(Locale::from_tag)
+            .unwrap_or_else(|| i18n::default_locale(&self.state.config));
+        Ok(locale)
+    }
+
+    /// Notifies a user about an event in preferred language of the user. Subject and body come
+    /// from `email.{event}` entries of message catalogs with placeholders replaced by args.
+    pub async fn notify(
+        &self,
+        user_id: i64,
+        event: NotificationEvent,
+        args: &[(&str, String)],
+    ) -> Result<(), Error> {
+        let locale = self.locale(user_id).await?;
+        let prefix = format!("email.{}", event.as_str());
+        let notification = Notification {
+            event,
+            subject: i18n::translate(locale, &format!("{}.subject", prefix), args),
+            body: i18n::translate(locale, &format!("{}.body", prefix), args),
+        };
+        self.dispatch(user_id, notification).await
+    }
+
+    /// Returns channel specific recipient of a notification for a user.
+    async fn recipient(
+        &self,
+        user_id: i64,
+        channel: NotificationChannel,
+        destination: Option<String>,
+    ) -> Result<Option<String>, Error> {
+        let notifications_cfg = &self.state.config.notifications;
+        let recipient = match channel {
+            NotificationChannel::Email => Some(self.user_manager.get(user_id).await?.email),
+            NotificationChannel::Webhook => {
+                destination.or_else(|| notifications_cfg.webhook_url.clone())
+            }
+            NotificationChannel::Slack => {
+                destination.or_else(|| notifications_cfg.slack_webhook_url.clone())
+            }
+            NotificationChannel::Log => Some(destination.unwrap_or_else(|| user_id.to_string())),
+        };
+        Ok(recipient)
+    }
+
+    /// Returns notification preferences of a user.
+    pub async fn preferences(&self, user_id: i64) -> Result<Vec<NotificationPreference>, Error> {
+        self.state.users.preferences(user_id).await
+    }
+
+    /// Replaces all the notification preferences of a user. Webhook and Slack destinations
+    /// must reach only public addresses, like webhooks.
+    pub async fn set_preferences(
+        &self,
+        user_id: i64,
+        preferences: Vec<NotificationPreference>,
+    ) -> Result<(), Error> {
+        // Make sure the user exists
+        self.user_manager.get(user_id).await?;
+        for preference in &preferences {
+            if let (
+                Some(NotificationChannel::Webhook | NotificationChannel::Slack),
+                Some(destination),
+            ) = (preference.channel, &preference.destination)
+            {
+                webhooks::validate_url(destination, &self.state.config.webhooks).await?;
+            }
+        }
+        self.state
+            .users
+            .set_preferences(user_id, &preferences)
+            .await
+    }
+}
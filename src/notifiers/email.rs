@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+
+use crate::config::{Config, EmailTransport, SmtpSecurity};
+use crate::core::traits::Notifier;
+use crate::error::{self, Error};
+use crate::types::{Notification, NotificationChannel};
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, PoolConfig},
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+/// Transports an [EmailNotifier] can deliver through
+enum Transport {
+    /// SMTP server. Connections are pooled and reused across emails.
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    /// `.eml` files in a directory
+    File(AsyncFileTransport<Tokio1Executor>),
+    /// Standard output
+    Stdout,
+}
+
+/// Used to send an email notification. It keeps a single transport for all the messages.
+pub struct EmailNotifier {
+    /// Sender's email
+    from: Mailbox,
+    /// Transport shared by all the messages
+    transport: Transport,
+}
+
+#[async_trait()]
+impl Notifier for EmailNotifier {
+    fn channel(&self) -> NotificationChannel {
+        NotificationChannel::Email
+    }
+
+    async fn send(&self, recipient: &str, notification: &Notification) -> Result<(), error::Error> {
+        // Build a message
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(recipient.parse()?)
+            .subject(notification.subject.to_string())
+            .body(notification.body.to_string())?;
+
+        // Send email
+        match &self.transport {
+            Transport::Smtp(mailer) => {
+                mailer.send(email).await?;
+            }
+            Transport::File(mailer) => {
+                mailer.send(email).await?;
+            }
+            Transport::Stdout => {
+                println!("{}", String::from_utf8_lossy(&email.formatted()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl EmailNotifier {
+    /// Creates a new [EmailNotifier] along with the transport described in [Config].
+    pub fn new(cfg: &Config) -> Result<Self, error::Error> {
+        let email_cfg = &cfg.email;
+        let from = email_cfg.sender.parse()?;
+
+        let transport = match email_cfg.transport {
+            EmailTransport::Smtp => Transport::Smtp(EmailNotifier::smtp_transport(cfg)?),
+            EmailTransport::File => {
+                let directory =
+                    email_cfg
+                        .directory
+                        .as_ref()
+                        .ok_or_else(|| Error::InvalidEmailConfig {
+                            message: "directory is required for file transport".to_string(),
+                        })?;
+                Transport::File(AsyncFileTransport::<Tokio1Executor>::new(directory))
+            }
+            EmailTransport::Stdout => Transport::Stdout,
+        };
+
+        Ok(EmailNotifier { from, transport })
+    }
+
+    /// Prepares an SMTP transport as per the server, port, security mode, credentials and pool
+    /// size from [Config].
+    fn smtp_transport(cfg: &Config) -> Result<AsyncSmtpTransport<Tokio1Executor>, error::Error> {
+        let email_cfg = &cfg.email;
+        let server = email_cfg
+            .server
+            .as_deref()
+            .ok_or_else(|| Error::InvalidEmailConfig {
+                message: "server is required for smtp transport".to_string(),
+            })?;
+
+        let mut builder = match email_cfg.security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(server)?,
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(server)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(server),
+        };
+
+        if let Some(port) = email_cfg.port {
+            builder = builder.port(port);
+        }
+
+        // TODO: Think about encrypting password.
+        if let Some(username) = &email_cfg.username {
+            let password = email_cfg.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.to_string(), password));
+        }
+
+        if let Some(pool_size) = email_cfg.pool_size {
+            builder = builder.pool_config(PoolConfig::new().max_size(pool_size));
+        }
+
+        Ok(builder.build())
+    }
+}
@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::core::traits::Notifier;
+use crate::error::{self, Error};
+use crate::types::{Notification, NotificationChannel};
+
+/// Writes notifications to a file, or to stdout when no file is configured. Useful during
+/// development and for keeping an audit trail of what was sent.
+pub struct LogNotifier {
+    /// File notifications are appended to. Writes are serialized through the mutex.
+    file: Option<Mutex<File>>,
+}
+
+#[async_trait()]
+impl Notifier for LogNotifier {
+    fn channel(&self) -> NotificationChannel {
+        NotificationChannel::Log
+    }
+
+    async fn send(&self, recipient: &str, notification: &Notification) -> Result<(), error::Error> {
+        let line = format!(
+            "[{}] event={} to={} subject={:?} body={:?}\n",
+            Utc::now().to_rfc3339(),
+            notification.event.as_str(),
+            recipient,
+            notification.subject,
+            notification.body
+        );
+
+        match &self.file {
+            Some(file) => {
+                let mut file = file.lock().await;
+                file.write_all(line.as_bytes())
+                    .await
+                    .map_err(|source| Error::NotificationLog { source })?;
+                file.flush()
+                    .await
+                    .map_err(|source| Error::NotificationLog { source })?;
+            }
+            None => print!("{}", line),
+        }
+
+        Ok(())
+    }
+}
+
+impl LogNotifier {
+    /// Creates a new [LogNotifier]. The file at `path` is created if it does not exist.
+    pub async fn new(path: Option<&str>) -> Result<Self, error::Error> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(|source| Error::NotificationLog { source })?,
+            )),
+            None => None,
+        };
+        Ok(LogNotifier { file })
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::core::traits::Notifier;
+use crate::error;
+use crate::types::{Notification, NotificationChannel};
+
+/// Posts notifications to a Slack-compatible incoming webhook. Any chat service accepting
+/// `{"text": "..."}` payloads (Slack, Mattermost, Rocket.Chat) works.
+pub struct SlackNotifier {
+    /// HTTP client shared by all the requests
+    client: reqwest::Client,
+}
+
+#[async_trait()]
+impl Notifier for SlackNotifier {
+    fn channel(&self) -> NotificationChannel {
+        NotificationChannel::Slack
+    }
+
+    async fn send(&self, recipient: &str, notification: &Notification) -> Result<(), error::Error> {
+        let payload = json!({
+            "text": format!("*{}*\n{}", notification.subject, notification.body),
+        });
+
+        self.client
+            .post(recipient)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+impl SlackNotifier {
+    /// Creates a new [SlackNotifier] using a shared HTTP client.
+    pub fn new(client: reqwest::Client) -> Self {
+        SlackNotifier { client }
+    }
+}
@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::json;
+
+use crate::core::traits::Notifier;
+use crate::error;
+use crate::types::{Notification, NotificationChannel};
+
+/// Posts notifications as JSON documents to a generic HTTP endpoint.
+pub struct WebhookNotifier {
+    /// HTTP client shared by all the requests
+    client: reqwest::Client,
+}
+
+#[async_trait()]
+impl Notifier for WebhookNotifier {
+    fn channel(&self) -> NotificationChannel {
+        NotificationChannel::Webhook
+    }
+
+    async fn send(&self, recipient: &str, notification: &Notification) -> Result<(), error::Error> {
+        let payload = json!({
+            "event": notification.event,
+            "subject": notification.subject,
+            "body": notification.body,
+            "sent_at": Utc::now(),
+        });
+
+        self.client
+            .post(recipient)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+impl WebhookNotifier {
+    /// Creates a new [WebhookNotifier] using a shared HTTP client.
+    pub fn new(client: reqwest::Client) -> Self {
+        WebhookNotifier { client }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::RateLimitConfig;
+use crate::constants::store::RATE_LIMIT_PREFIX;
+use crate::core::traits::Store;
+use crate::error::Error;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Limits how many times an action is taken within fixed windows. Actions are counted in a
+/// [Store], so with Redis store every process behind a load balancer enforces the same limit.
+/// How does it work:
+/// 1. Every action is counted under a key made of the action, its subject like an IP address
+///    or a user, and the window it is taken in, like `ratelimit:login:203.0.113.7:28512345`.
+/// 2. A counter expires along with its window.
+/// 3. An action is refused with 429 once its counter goes beyond the limit.
+/// 4. Errors of the store are logged and the action is allowed, so an outage of the store
+///    does not lock users out.
+pub struct RateLimiter {
+    store: Arc<dyn Store>,
+    window_seconds: u64,
+}
+
+impl RateLimiter {
+    /// Creates a new instance of [RateLimiter]
+    pub fn new(store: Arc<dyn Store>, cfg: &RateLimitConfig) -> Self {
+        RateLimiter {
+            store,
+            window_seconds: cfg.window_seconds.max(1),
+        }
+    }
+
+    /// Counts an action taken by a subject and fails when it is taken more than limit times
+    /// within the current window. Nothing is counted when limit is zero.
+    pub async fn check(&self, action: &str, subject: &str, limit: u64) -> Result<(), Error> {
+        if limit == 0 {
+            return Ok(());
+        }
+        let now = Utc::now().timestamp().max(0) as u64;
+        let window = now / self.window_seconds;
+        let key = format!("{}{}:{}:{}", RATE_LIMIT_PREFIX, action, subject, window);
+        let ttl = Duration::from_secs((window + 1) * self.window_seconds - now);
+
+        match self.store.increment(&key, ttl).await {
+            Ok(count) if count > limit => Err(Error::TooManyRequests),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // TODO: Use log here
+                println!("Error in counting an action for rate limit: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use async_trait::async_trait;
+
+    /// A [Store] that can not be reached.
+    struct BrokenStore;
+
+    #[async_trait()]
+    impl Store for BrokenStore {
+        fn backend(&self) -> &'static str {
+            "broken"
+        }
+
+        async fn get(&self, _: &str) -> Result<Option<String>, Error> {
+            Err(Error::TooManyRequests)
+        }
+
+        async fn set(&self, _: &str, _: &str, _: Duration) -> Result<(), Error> {
+            Err(Error::TooManyRequests)
+        }
+
+        async fn delete(&self, _: &str) -> Result<(), Error> {
+            Err(Error::TooManyRequests)
+        }
+
+        async fn increment(&self, _: &str, _: Duration) -> Result<u64, Error> {
+            Err(Error::TooManyRequests)
+        }
+
+        fn entries(&self) -> Option<usize> {
+            None
+        }
+
+        fn capacity(&self) -> Option<usize> {
+            None
+        }
+    }
+
+    fn limiter(store: Arc<dyn Store>) -> RateLimiter {
+        let cfg = RateLimitConfig {
+            window_seconds: 60,
+            ..RateLimitConfig::default()
+        };
+        RateLimiter::new(store, &cfg)
+    }
+
+    #[tokio::test]
+    async fn actions_beyond_limit_are_refused() {
+        let limiter = limiter(Arc::new(MemoryStore::new(None)));
+        assert!(limiter.check("login", "203.0.113.7", 2).await.is_ok());
+        assert!(limiter.check("login", "203.0.113.7", 2).await.is_ok());
+        assert!(matches!(
+            limiter.check("login", "203.0.113.7", 2).await,
+            Err(Error::TooManyRequests)
+        ));
+        // Other subjects and actions are counted apart.
+        assert!(limiter.check("login", "203.0.113.8", 2).await.is_ok());
+        assert!(limiter.check("url", "203.0.113.7", 2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn zero_limit_counts_nothing() {
+        let store = Arc::new(MemoryStore::new(None));
+        let limiter = limiter(store.clone());
+        for _ in 0..10 {
+            assert!(limiter.check("login", "203.0.113.7", 0).await.is_ok());
+        }
+        assert_eq!(store.entries(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn actions_are_allowed_when_store_fails() {
+        let limiter = limiter(Arc::new(BrokenStore));
+        assert!(limiter.check("login", "203.0.113.7", 1).await.is_ok());
+        assert!(limiter.check("login", "203.0.113.7", 1).await.is_ok());
+    }
+}
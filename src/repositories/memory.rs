@@ -0,0 +1,660 @@
+use crate::analytics::buffer::VisitBatch;
+use crate::cache::Redirect;
+use crate::core::traits::{
+    BotSignatureStore, IpSaltStore, UrlStore, UserStore, VisitStore, WebhookStore,
+};
+use crate::error::Error;
+use crate::types::{
+    BotSignature, CreateURLRequest, CreateUserRequest, DeliveryAttempt, LoginRequest,
+    NotificationPreference, UpdateURLRequest, Url, User, Webhook, WebhookDelivery, WebhookTarget,
+};
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use serde_json::value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Records of a repository by id along with the id given to the next one.
+struct Records<T> {
+    next_id: i64,
+    by_id: BTreeMap<i64, T>,
+}
+
+impl<T> Records<T> {
+    fn new() -> Self {
+        Records {
+            next_id: 1,
+            by_id: BTreeMap::new(),
+        }
+    }
+
+    /// Stores a record built out of a new id and returns the id.
+    fn insert(&mut self, record: impl FnOnce(i64) -> T) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.by_id.insert(id, record(id));
+        id
+    }
+}
+
+/// A [UrlStore] keeping short links in the server process. Links are lost on restart. It is
+/// also the [VisitStore] of the links, which counts their visits and keeps nothing else of them.
+pub struct MemoryUrlStore {
+    urls: Mutex<Records<Url>>,
+}
+
+impl Default for MemoryUrlStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryUrlStore {
+    /// Creates a new instance of [MemoryUrlStore] without links.
+    pub fn new() -> Self {
+        MemoryUrlStore {
+            urls: Mutex::new(Records::new()),
+        }
+    }
+}
+
+#[async_trait()]
+impl UrlStore for MemoryUrlStore {
+    async fn create(&self, address: &str, request: &CreateURLRequest) -> Result<i64, Error> {
+        let mut urls = self.urls.lock().unwrap();
+        if urls.by_id.values().any(|url| url.address == address) {
+            return Err(Error::DuplicateUrl);
+        }
+        let now = Utc::now();
+        let id = urls.insert(|id| Url {
+            id,
+            user_id: request.user_id,
+            address: address.to_string(),
+            description: request.description.clone(),
+            banned: request.banned,
+            target: request.target.clone(),
+            visit_count: 0,
+            bot_visit_count: 0,
+            track_visits: request.track_visits,
+            public_stats: request.public_stats,
+            track_conversions: request.track_conversions,
+            expires_at: request.expires_at,
+            created_at: now,
+            updated_at: now,
+        });
+        Ok(id)
+    }
+
+    async fn get(&self, url_id: i64) -> Result<Url, Error> {
+        let urls = self.urls.lock().unwrap();
+        urls.by_id.get(&url_id).cloned().ok_or(Error::UrlNotFound)
+    }
+
+    async fn get_all(&self) -> Result<Vec<Url>, Error> {
+        let urls = self.urls.lock().unwrap();
+        Ok(urls.by_id.values().cloned().collect())
+    }
+
+    async fn update(&self, url_id: i64, request: &UpdateURLRequest) -> Result<Url, Error> {
+        let mut urls = self.urls.lock().unwrap();
+        let url = urls.by_id.get_mut(&url_id).ok_or(Error::UrlNotFound)?;
+        if let Some(target) = &request.target {
+            url.target = target.clone();
+        }
+        if let Some(description) = &request.description {
+            url.description = Some(description.clone());
+        }
+        if let Some(banned) = request.banned {
+            url.banned = banned;
+        }
+        if let Some(track_visits) = request.track_visits {
+            url.track_visits = track_visits;
+        }
+        if let Some(public_stats) = request.public_stats {
+            url.public_stats = public_stats;
+        }
+        if let Some(track_conversions) = request.track_conversions {
+            url.track_conversions = track_conversions;
+        }
+        if let Some(expires_at) = request.expires_at {
+            url.expires_at = Some(expires_at);
+        }
+        url.updated_at = Utc::now();
+        Ok(url.clone())
+    }
+
+    async fn delete(&self, url_id: i64) -> Result<Option<Url>, Error> {
+        Ok(self.urls.lock().unwrap().by_id.remove(&url_id))
+    }
+
+    async fn find_redirect(&self, address: &str) -> Result<Option<Redirect>, Error> {
+        let urls = self.urls.lock().unwrap();
+        let redirect = urls
+            .by_id
+            .values()
+            .find(|url| url.address == address && !url.banned)
+            .map(|url| Redirect {
+                id: url.id,
+                user_id: url.user_id,
+                target: url.target.clone(),
+                track_visits: url.track_visits,
+                track_conversions: url.track_conversions,
+                expires_at: url.expires_at,
+            });
+        Ok(redirect)
+    }
+}
+
+#[async_trait()]
+impl VisitStore for MemoryUrlStore {
+    async fn record(&self, batch: &VisitBatch<'_>) -> Result<HashMap<i64, i32>, Error> {
+        let mut urls = self.urls.lock().unwrap();
+        let mut visit_counts = HashMap::new();
+        for (url_id, counts) in batch.counts.iter() {
+            if let Some(url) = urls.by_id.get_mut(url_id) {
+                url.visit_count += counts.visits;
+                url.bot_visit_count += counts.bot_visits;
+                visit_counts.insert(*url_id, url.visit_count);
+            }
+        }
+        Ok(visit_counts)
+    }
+}
+
+/// A user account along with what is kept about it beyond [User].
+struct Account {
+    user: User,
+    activation_code: String,
+    activated: bool,
+    role: String,
+    preferences: Vec<NotificationPreference>,
+}
+
+/// A [UserStore] keeping user accounts in the server process. Accounts are lost on restart.
+pub struct MemoryUserStore {
+    accounts: Mutex<Records<Account>>,
+}
+
+impl Default for MemoryUserStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryUserStore {
+    /// Creates a new instance of [MemoryUserStore] without accounts.
+    pub fn new() -> Self {
+        MemoryUserStore {
+            accounts: Mutex::new(Records::new()),
+        }
+    }
+
+    /// Creates an activated admin account and returns its id.
+    pub fn seed_admin(&self, email: &str, password: &str) -> i64 {
+        let mut accounts = self.accounts.lock().unwrap();
+        let now = Utc::now();
+        accounts.insert(|id| Account {
+            user: User {
+                id: Some(id),
+                apikey: None,
+                banned: false,
+                email: email.to_string(),
+                password: password.to_string(),
+                language: None,
+                created_at: now,
+                updated_at: now,
+            },
+            activation_code: String::new(),
+            activated: true,
+            role: String::from("admin"),
+            preferences: Vec::new(),
+        })
+    }
+}
+
+#[async_trait()]
+impl UserStore for MemoryUserStore {
+    async fn create(&self, user: &CreateUserRequest, activation_code: &str) -> Result<i64, Error> {
+        let mut accounts = self.accounts.lock().unwrap();
+        if accounts
+            .by_id
+            .values()
+            .any(|account| account.user.email == user.email)
+        {
+            return Err(Error::DuplicateUser);
+        }
+        let now = Utc::now();
+        let id = accounts.insert(|id| Account {
+            user: User {
+                id: Some(id),
+                apikey: None,
+                banned: false,
+                email: user.email.clone(),
+                password: user.password.clone(),
+                language: user.language.clone(),
+                created_at: now,
+                updated_at: now,
+            },
+            activation_code: activation_code.to_string(),
+            activated: false,
+            role: String::from("regular"),
+            preferences: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    async fn get(&self, user_id: i64) -> Result<User, Error> {
+        let accounts = self.accounts.lock().unwrap();
+        accounts
+            .by_id
+            .get(&user_id)
+            .map(|account| account.user.clone())
+            .ok_or(Error::UserNotFound)
+    }
+
+    async fn get_all(&self) -> Result<Vec<User>, Error> {
+        let accounts = self.accounts.lock().unwrap();
+        Ok(accounts
+            .by_id
+            .values()
+            .map(|account| account.user.clone())
+            .collect())
+    }
+
+    async fn delete(&self, user_id: i64) -> Result<(), Error> {
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts
+            .by_id
+            .remove(&user_id)
+            .map(|_| ())
+            .ok_or(Error::UserNotFound)
+    }
+
+    async fn activate(&self, activation_code: &str) -> Result<i64, Error> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let account = accounts
+            .by_id
+            .values_mut()
+            .find(|account| account.activation_code == activation_code)
+            .ok_or(Error::InvalidActivationToken)?;
+        if account.activated {
+            return Err(Error::AccountAlreadyActivated);
+        }
+        account.activated = true;
+        Ok(account.user.id.unwrap_or_default())
+    }
+
+    async fn login(&self, login_request: &LoginRequest) -> Result<(i64, String), Error> {
+        let accounts = self.accounts.lock().unwrap();
+        accounts
+            .by_id
+            .iter()
+            .find(|(_, account)| {
+                account.user.email == login_request.email
+                    && account.user.password == login_request.password
+            })
+            .map(|(id, account)| (*id, account.role.clone()))
+            .ok_or(Error::UserNotFound)
+    }
+
+    async fn set_language(&self, user_id: i64, language: &str) -> Result<(), Error> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let account = accounts
+            .by_id
+            .get_mut(&user_id)
+            .ok_or(Error::UserNotFound)?;
+        account.user.language = Some(language.to_string());
+        account.user.updated_at = Utc::now();
+        Ok(())
+    }
+    async fn preferences(&self, user_id: i64) -> Result<Vec<NotificationPreference>, Error> {
+        let accounts = self.accounts.lock().unwrap();
+        accounts
+            .by_id
+            .get(&user_id)
+            .map(|account| account.preferences.clone())
+            .ok_or(Error::UserNotFound)
+    }
+
+    async fn set_preferences(
+        &self,
+        user_id: i64,
+        preferences: &[NotificationPreference],
+    ) -> Result<(), Error> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let account = accounts
+            .by_id
+            .get_mut(&user_id)
+            .ok_or(Error::UserNotFound)?;
+        account.preferences = preferences.to_vec();
+        Ok(())
+    }
+}
+
+/// A [BotSignatureStore] keeping bot signatures in the server process. Signatures are lost on
+/// restart.
+pub struct MemoryBotSignatureStore {
+    signatures: Mutex<Records<BotSignature>>,
+}
+
+impl Default for MemoryBotSignatureStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBotSignatureStore {
+    /// Creates a new instance of [MemoryBotSignatureStore] without signatures.
+    pub fn new() -> Self {
+        MemoryBotSignatureStore {
+            signatures: Mutex::new(Records::new()),
+        }
+    }
+}
+
+#[async_trait()]
+impl BotSignatureStore for MemoryBotSignatureStore {
+    async fn get_all(&self) -> Result<Vec<BotSignature>, Error> {
+        let signatures = self.signatures.lock().unwrap();
+        let mut all: Vec<BotSignature> = signatures.by_id.values().cloned().collect();
+        all.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+        Ok(all)
+    }
+
+    async fn create(
+        &self,
+        pattern: &str,
+        description: Option<&str>,
+    ) -> Result<BotSignature, Error> {
+        let mut signatures = self.signatures.lock().unwrap();
+        if signatures
+            .by_id
+            .values()
+            .any(|signature| signature.pattern == pattern)
+        {
+            return Err(Error::DuplicateBotSignature);
+        }
+        let signature = |id| BotSignature {
+            id,
+            pattern: pattern.to_string(),
+            description: description.map(str::to_string),
+            created_at: Utc::now(),
+        };
+        let id = signatures.insert(signature);
+        Ok(signatures.by_id[&id].clone())
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), Error> {
+        let mut signatures = self.signatures.lock().unwrap();
+        signatures
+            .by_id
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(Error::BotSignatureNotFound)
+    }
+}
+
+/// A webhook along with what is kept about it beyond [Webhook].
+struct Hook {
+    webhook: Webhook,
+    user_id: i64,
+    secret: String,
+}
+
+impl Hook {
+    fn target(&self) -> WebhookTarget {
+        WebhookTarget {
+            id: self.webhook.id,
+            url: self.webhook.url.clone(),
+            secret: self.secret.clone(),
+        }
+    }
+}
+
+/// A [WebhookStore] keeping webhooks and their deliveries in the server process. Only the
+/// latest deliveries of a webhook shown by its delivery log are kept, and all of them are lost
+/// on restart.
+pub struct MemoryWebhookStore {
+    hooks: Mutex<Records<Hook>>,
+    deliveries: Mutex<Records<WebhookDelivery>>,
+    /// Number of deliveries kept per webhook.
+    log_limit: usize,
+}
+
+impl MemoryWebhookStore {
+    /// Creates a new instance of [MemoryWebhookStore] without webhooks, keeping up to
+    /// `log_limit` deliveries per webhook.
+    pub fn new(log_limit: usize) -> Self {
+        MemoryWebhookStore {
+            hooks: Mutex::new(Records::new()),
+            deliveries: Mutex::new(Records::new()),
+            log_limit,
+        }
+    }
+}
+
+#[async_trait()]
+impl WebhookStore for MemoryWebhookStore {
+    async fn create(
+        &self,
+        user_id: i64,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<Webhook, Error> {
+        let mut hooks = self.hooks.lock().unwrap();
+        let now = Utc::now();
+        let id = hooks.insert(|id| Hook {
+            webhook: Webhook {
+                id,
+                url: url.to_string(),
+                events: events.to_vec(),
+                active: true,
+                secret: None,
+                created_at: now,
+                updated_at: now,
+            },
+            user_id,
+            secret: secret.to_string(),
+        });
+        Ok(hooks.by_id[&id].webhook.clone())
+    }
+
+    async fn get_all(&self, user_id: i64) -> Result<Vec<Webhook>, Error> {
+        let hooks = self.hooks.lock().unwrap();
+        Ok(hooks
+            .by_id
+            .values()
+            .filter(|hook| hook.user_id == user_id)
+            .map(|hook| hook.webhook.clone())
+            .collect())
+    }
+
+    async fn update(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+        url: Option<&str>,
+        events: Option<&[String]>,
+        active: Option<bool>,
+    ) -> Result<Webhook, Error> {
+        let mut hooks = self.hooks.lock().unwrap();
+        let hook = hooks
+            .by_id
+            .get_mut(&webhook_id)
+            .filter(|hook| hook.user_id == user_id)
+            .ok_or(Error::WebhookNotFound)?;
+        if let Some(url) = url {
+            hook.webhook.url = url.to_string();
+        }
+        if let Some(events) = events {
+            hook.webhook.events = events.to_vec();
+        }
+        if let Some(active) = active {
+            hook.webhook.active = active;
+        }
+        hook.webhook.updated_at = Utc::now();
+        Ok(hook.webhook.clone())
+    }
+
+    async fn delete(&self, user_id: i64, webhook_id: i64) -> Result<(), Error> {
+        let mut hooks = self.hooks.lock().unwrap();
+        if hooks
+            .by_id
+            .get(&webhook_id)
+            .is_none_or(|hook| hook.user_id != user_id)
+        {
+            return Err(Error::WebhookNotFound);
+        }
+        hooks.by_id.remove(&webhook_id);
+        let mut deliveries = self.deliveries.lock().unwrap();
+        deliveries
+            .by_id
+            .retain(|_, delivery| delivery.webhook_id != webhook_id);
+        Ok(())
+    }
+
+    async fn find(&self, user_id: i64, webhook_id: i64) -> Result<WebhookTarget, Error> {
+        let hooks = self.hooks.lock().unwrap();
+        hooks
+            .by_id
+            .get(&webhook_id)
+            .filter(|hook| hook.user_id == user_id)
+            .map(Hook::target)
+            .ok_or(Error::WebhookNotFound)
+    }
+
+    async fn subscribers(&self, user_id: i64, event: &str) -> Result<Vec<WebhookTarget>, Error> {
+        let hooks = self.hooks.lock().unwrap();
+        Ok(hooks
+            .by_id
+            .values()
+            .filter(|hook| hook.user_id == user_id && hook.webhook.active)
+            .filter(|hook| {
+                hook.webhook.events.is_empty()
+                    || hook.webhook.events.iter().any(|name| name == event)
+            })
+            .map(Hook::target)
+            .collect())
+    }
+
+    async fn deliveries(&self, webhook_id: i64, limit: i64) -> Result<Vec<WebhookDelivery>, Error> {
+        let deliveries = self.deliveries.lock().unwrap();
+        // Ids grow with time, so the newest deliveries come last.
+        Ok(deliveries
+            .by_id
+            .values()
+            .rev()
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn delivery(
+        &self,
+        webhook_id: i64,
+        delivery_id: i64,
+    ) -> Result<(String, value::Value), Error> {
+        let deliveries = self.deliveries.lock().unwrap();
+        deliveries
+            .by_id
+            .get(&delivery_id)
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .map(|delivery| (delivery.event.clone(), delivery.payload.clone()))
+            .ok_or(Error::WebhookDeliveryNotFound)
+    }
+
+    async fn create_delivery(
+        &self,
+        webhook_id: i64,
+        event: &str,
+        payload: &value::Value,
+    ) -> Result<i64, Error> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let id = deliveries.insert(|id| WebhookDelivery {
+            id,
+            webhook_id,
+            event: event.to_string(),
+            payload: payload.clone(),
+            status: String::from("pending"),
+            attempts: 0,
+            response_status: None,
+            response_body: None,
+            error: None,
+            created_at: Utc::now(),
+            delivered_at: None,
+        });
+
+        // Forget deliveries of the webhook beyond its log.
+        let expired: Vec<i64> = deliveries
+            .by_id
+            .values()
+            .rev()
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .skip(self.log_limit)
+            .map(|delivery| delivery.id)
+            .collect();
+        for id in expired {
+            deliveries.by_id.remove(&id);
+        }
+        Ok(id)
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery_id: i64,
+        attempt: &DeliveryAttempt,
+    ) -> Result<(), Error> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        // A delivery forgotten in the meantime is not recorded, as a deleted row is not updated.
+        if let Some(delivery) = deliveries.by_id.get_mut(&delivery_id) {
+            delivery.attempts = attempt.attempts;
+            delivery.status = attempt.status.to_string();
+            delivery.response_status = attempt.response_status;
+            delivery.response_body = attempt.response_body.clone();
+            delivery.error = attempt.error.clone();
+            delivery.delivered_at = (attempt.status == "succeeded").then(Utc::now);
+        }
+        Ok(())
+    }
+}
+
+/// An [IpSaltStore] keeping daily salts in the server process, so they are used by it alone.
+pub struct MemoryIpSaltStore {
+    salts: Mutex<BTreeMap<NaiveDate, String>>,
+}
+
+impl Default for MemoryIpSaltStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryIpSaltStore {
+    /// Creates a new instance of [MemoryIpSaltStore] without salts.
+    pub fn new() -> Self {
+        MemoryIpSaltStore {
+            salts: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+#[async_trait()]
+impl IpSaltStore for MemoryIpSaltStore {
+    async fn rotate(
+        &self,
+        salts: &[(NaiveDate, String)],
+        oldest: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, String)>, Error> {
+        let mut kept = self.salts.lock().unwrap();
+        for (day, salt) in salts {
+            kept.entry(*day).or_insert_with(|| salt.clone());
+        }
+        kept.retain(|day, _| *day >= oldest);
+        Ok(kept
+            .iter()
+            .map(|(day, salt)| (*day, salt.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,751 @@
+use crate::analytics::buffer::{BufferedVisit, VisitBatch};
+use crate::analytics::VisitEvent;
+use crate::cache::Redirect;
+use crate::core::traits::{
+    BotSignatureStore, IpSaltStore, UrlStore, UserStore, VisitStore, WebhookStore,
+};
+use crate::error::Error;
+use crate::types::{
+    BotSignature, CreateURLRequest, CreateUserRequest, DeliveryAttempt, LoginRequest,
+    NotificationPreference, UpdateURLRequest, Url, User, Webhook, WebhookDelivery, WebhookTarget,
+};
+use crate::utils::is_unique_violation;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::value;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+
+/// A [UrlStore] keeping short links in `tyto.urls`. Listing and redirect lookups read from the
+/// read replica when there is one.
+pub struct PgUrlStore {
+    db_connection: Pool<Postgres>,
+    replica: Option<Pool<Postgres>>,
+}
+
+impl PgUrlStore {
+    /// Creates a new instance of [PgUrlStore]
+    pub fn new(db_connection: Pool<Postgres>, replica: Option<Pool<Postgres>>) -> Self {
+        PgUrlStore {
+            db_connection,
+            replica,
+        }
+    }
+
+    /// Returns pool of the read replica, or of the primary when there is none.
+    fn read_connection(&self) -> &Pool<Postgres> {
+        self.replica.as_ref().unwrap_or(&self.db_connection)
+    }
+}
+
+/// Returns a short link that is not banned from database.
+async fn find_redirect(
+    db_connection: &Pool<Postgres>,
+    address: &str,
+) -> Result<Option<Redirect>, Error> {
+    let redirect = sqlx::query_as!(
+        Redirect,
+        r#"SELECT id, user_id, target, track_visits, track_conversions, expires_at FROM tyto.urls WHERE address=$1 AND banned=false"#,
+        address
+    )
+    .fetch_optional(db_connection)
+    .await?;
+    Ok(redirect)
+}
+
+#[async_trait()]
+impl UrlStore for PgUrlStore {
+    async fn create(&self, address: &str, request: &CreateURLRequest) -> Result<i64, Error> {
+        // IMP NOTE: Queries are checked against sqlx-data.json, or against DATABASE_URL when it
+        //           is set. Run `cargo sqlx prepare` after changing them.
+        let rec = sqlx::query!(
+            r#"INSERT INTO tyto.urls (address,target,description,banned,user_id,track_visits,public_stats,track_conversions,expires_at) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9) RETURNING id"#,
+            address,
+            request.target,
+            request.description,
+            request.banned,
+            request.user_id,
+            request.track_visits,
+            request.public_stats,
+            request.track_conversions,
+            request.expires_at,
+        )
+        .fetch_one(&self.db_connection)
+        .await;
+        match rec {
+            Ok(rec) => Ok(rec.id),
+            Err(e) if is_unique_violation(&e) => Err(Error::DuplicateUrl),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get(&self, url_id: i64) -> Result<Url, Error> {
+        let url = sqlx::query_as!(
+            Url,
+            r#"SELECT id, user_id, address, description, banned, target, visit_count, bot_visit_count, track_visits, public_stats, track_conversions, expires_at, created_at, updated_at
+               FROM tyto.urls WHERE id=$1"#,
+            url_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::UrlNotFound)?;
+        Ok(url)
+    }
+
+    async fn get_all(&self) -> Result<Vec<Url>, Error> {
+        let urls = sqlx::query_as!(
+            Url,
+            r#"SELECT id, user_id, address, description, banned, target, visit_count, bot_visit_count, track_visits, public_stats, track_conversions, expires_at, created_at, updated_at
+               FROM tyto.urls ORDER BY created_at ASC"#,
+        )
+        .fetch_all(self.read_connection())
+        .await?;
+        Ok(urls)
+    }
+
+    async fn update(&self, url_id: i64, request: &UpdateURLRequest) -> Result<Url, Error> {
+        let url = sqlx::query_as!(
+            Url,
+            r#"UPDATE tyto.urls
+               SET target=COALESCE($2, target), description=COALESCE($3, description), banned=COALESCE($4, banned),
+                   track_visits=COALESCE($5, track_visits), public_stats=COALESCE($6, public_stats),
+                   track_conversions=COALESCE($7, track_conversions), expires_at=COALESCE($8, expires_at),
+                   updated_at=now()
+               WHERE id=$1
+               RETURNING id, user_id, address, description, banned, target, visit_count, bot_visit_count, track_visits, public_stats, track_conversions, expires_at, created_at, updated_at"#,
+            url_id,
+            request.target,
+            request.description,
+            request.banned,
+            request.track_visits,
+            request.public_stats,
+            request.track_conversions,
+            request.expires_at,
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::UrlNotFound)?;
+        Ok(url)
+    }
+
+    async fn delete(&self, url_id: i64) -> Result<Option<Url>, Error> {
+        let url = sqlx::query_as!(
+            Url,
+            r#"DELETE FROM tyto.urls WHERE id=$1
+               RETURNING id, user_id, address, description, banned, target, visit_count, bot_visit_count, track_visits, public_stats, track_conversions, expires_at, created_at, updated_at"#,
+            url_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?;
+        Ok(url)
+    }
+
+    /// Looks a short link up in the read replica when there is one. A link unknown to the
+    /// replica is looked up in the primary again, so a link created moments ago is not taken
+    /// as unknown because of replication lag.
+    async fn find_redirect(&self, address: &str) -> Result<Option<Redirect>, Error> {
+        let redirect = find_redirect(self.read_connection(), address).await?;
+        if redirect.is_none() && self.replica.is_some() {
+            return find_redirect(&self.db_connection, address).await;
+        }
+        Ok(redirect)
+    }
+}
+
+/// A [UserStore] keeping user accounts in `tyto.users`. Listing reads from the read replica
+/// when there is one.
+pub struct PgUserStore {
+    db_connection: Pool<Postgres>,
+    read_connection: Pool<Postgres>,
+}
+
+impl PgUserStore {
+    /// Creates a new instance of [PgUserStore]
+    pub fn new(db_connection: Pool<Postgres>, read_connection: Pool<Postgres>) -> Self {
+        PgUserStore {
+            db_connection,
+            read_connection,
+        }
+    }
+}
+
+#[async_trait()]
+impl UserStore for PgUserStore {
+    async fn create(&self, user: &CreateUserRequest, activation_code: &str) -> Result<i64, Error> {
+        let rec = sqlx::query!(
+            r#"INSERT INTO tyto.users (email,password, activation_code, language) VALUES ($1,$2,$3,$4) RETURNING id"#,
+            user.email,
+            user.password,
+            activation_code,
+            user.language,
+        )
+        .fetch_one(&self.db_connection)
+        .await;
+        match rec {
+            Ok(rec) => Ok(rec.id),
+            Err(e) if is_unique_violation(&e) => Err(Error::DuplicateUser),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get(&self, user_id: i64) -> Result<User, Error> {
+        // TODO: Return error if a user is deleted.
+        let user = sqlx::query!(r#"SELECT * FROM tyto.users WHERE id=$1"#, user_id)
+            .fetch_optional(&self.db_connection)
+            .await?
+            .ok_or(Error::UserNotFound)?;
+
+        Ok(User {
+            id: Some(user.id),
+            apikey: user.apikey,
+            email: user.email,
+            banned: user.banned,
+            password: user.password,
+            language: user.language,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        })
+    }
+
+    async fn get_all(&self) -> Result<Vec<User>, Error> {
+        let found_users = sqlx::query!(r#"SELECT * FROM tyto.users ORDER BY created_at ASC"#)
+            .fetch_all(&self.read_connection)
+            .await?;
+
+        let users: Vec<User> = found_users
+            .into_iter()
+            .map(|user| User {
+                id: Some(user.id),
+                apikey: user.apikey,
+                email: user.email,
+                banned: user.banned,
+                password: user.password,
+                language: user.language,
+                created_at: user.created_at,
+                updated_at: user.updated_at,
+            })
+            .collect::<Vec<User>>();
+
+        Ok(users)
+    }
+
+    async fn delete(&self, user_id: i64) -> Result<(), Error> {
+        sqlx::query!(
+            r#"DELETE FROM tyto.users where id=$1 RETURNING id"#,
+            user_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+        Ok(())
+    }
+
+    async fn activate(&self, activation_code: &str) -> Result<i64, Error> {
+        let user_record = sqlx::query!(
+            r#"SELECT id, activated from tyto.users WHERE activation_code=$1"#,
+            activation_code
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::InvalidActivationToken)?;
+
+        // Do not activate already activated account.
+        if user_record.activated {
+            return Err(Error::AccountAlreadyActivated);
+        }
+
+        sqlx::query!(
+            r#"UPDATE tyto.users SET activated=true WHERE activation_code=$1"#,
+            activation_code
+        )
+        .execute(&self.db_connection)
+        .await?;
+
+        Ok(user_record.id)
+    }
+
+    async fn login(&self, login_request: &LoginRequest) -> Result<(i64, String), Error> {
+        let user_record = sqlx::query!(
+            r#"SELECT id, role from tyto.users WHERE email=$1 and password=$2"#,
+            login_request.email,
+            login_request.password
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+        Ok((user_record.id, user_record.role))
+    }
+
+    async fn set_language(&self, user_id: i64, language: &str) -> Result<(), Error> {
+        sqlx::query!(
+            r#"UPDATE tyto.users SET language=$2, updated_at=now() WHERE id=$1 RETURNING id"#,
+            user_id,
+            language
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+        Ok(())
+    }
+    async fn preferences(&self, user_id: i64) -> Result<Vec<NotificationPreference>, Error> {
+        let records = sqlx::query!(
+            r#"SELECT event, channel, destination FROM tyto.notification_preferences WHERE user_id=$1 ORDER BY id ASC"#,
+            user_id
+        )
+        .fetch_all(&self.db_connection)
+        .await?;
+
+        // Rows with unknown event or channel names are skipped.
+        let preferences = records
+            .into_iter()
+            .filter_map(|record| {
+                let channel = match record.channel {
+                    Some(channel) => Some(channel.parse().ok()?),
+                    None => None,
+                };
+                Some(NotificationPreference {
+                    event: record.event.parse().ok()?,
+                    channel,
+                    destination: record.destination,
+                })
+            })
+            .collect();
+
+        Ok(preferences)
+    }
+
+    async fn set_preferences(
+        &self,
+        user_id: i64,
+        preferences: &[NotificationPreference],
+    ) -> Result<(), Error> {
+        let mut transaction = self.db_connection.begin().await?;
+        sqlx::query!(
+            r#"DELETE FROM tyto.notification_preferences WHERE user_id=$1"#,
+            user_id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        for preference in preferences {
+            sqlx::query!(
+                r#"INSERT INTO tyto.notification_preferences (user_id, event, channel, destination) VALUES ($1,$2,$3,$4)
+                   ON CONFLICT (user_id, event, channel) DO UPDATE SET destination=EXCLUDED.destination"#,
+                user_id,
+                preference.event.as_str(),
+                preference.channel.map(|channel| channel.as_str()),
+                preference.destination,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// A [BotSignatureStore] keeping bot signatures in `tyto.bot_signatures`.
+pub struct PgBotSignatureStore {
+    db_connection: Pool<Postgres>,
+}
+
+impl PgBotSignatureStore {
+    /// Creates a new instance of [PgBotSignatureStore]
+    pub fn new(db_connection: Pool<Postgres>) -> Self {
+        PgBotSignatureStore { db_connection }
+    }
+}
+
+#[async_trait()]
+impl BotSignatureStore for PgBotSignatureStore {
+    async fn get_all(&self) -> Result<Vec<BotSignature>, Error> {
+        let signatures = sqlx::query_as!(
+            BotSignature,
+            r#"SELECT id, pattern, description, created_at FROM tyto.bot_signatures ORDER BY pattern"#
+        )
+        .fetch_all(&self.db_connection)
+        .await?;
+        Ok(signatures)
+    }
+
+    async fn create(
+        &self,
+        pattern: &str,
+        description: Option<&str>,
+    ) -> Result<BotSignature, Error> {
+        let signature = sqlx::query_as!(
+            BotSignature,
+            r#"INSERT INTO tyto.bot_signatures (pattern, description) VALUES ($1,$2)
+               ON CONFLICT (pattern) DO NOTHING
+               RETURNING id, pattern, description, created_at"#,
+            pattern,
+            description,
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::DuplicateBotSignature)?;
+        Ok(signature)
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), Error> {
+        sqlx::query!(
+            r#"DELETE FROM tyto.bot_signatures WHERE id=$1 RETURNING id"#,
+            id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::BotSignatureNotFound)?;
+        Ok(())
+    }
+}
+
+/// A [WebhookStore] keeping webhooks in `tyto.webhooks` and their deliveries in
+/// `tyto.webhook_deliveries`.
+pub struct PgWebhookStore {
+    db_connection: Pool<Postgres>,
+}
+
+impl PgWebhookStore {
+    /// Creates a new instance of [PgWebhookStore]
+    pub fn new(db_connection: Pool<Postgres>) -> Self {
+        PgWebhookStore { db_connection }
+    }
+}
+
+#[async_trait()]
+impl WebhookStore for PgWebhookStore {
+    async fn create(
+        &self,
+        user_id: i64,
+        url: &str,
+        secret: &str,
+        events: &[String],
+    ) -> Result<Webhook, Error> {
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"INSERT INTO tyto.webhooks (user_id, url, secret, events) VALUES ($1,$2,$3,$4)
+               RETURNING id, url, events, active, NULL::varchar AS secret, created_at, updated_at"#,
+            user_id,
+            url,
+            secret,
+            events,
+        )
+        .fetch_one(&self.db_connection)
+        .await?;
+        Ok(webhook)
+    }
+
+    async fn get_all(&self, user_id: i64) -> Result<Vec<Webhook>, Error> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"SELECT id, url, events, active, NULL::varchar AS secret, created_at, updated_at
+               FROM tyto.webhooks WHERE user_id=$1 ORDER BY created_at ASC"#,
+            user_id
+        )
+        .fetch_all(&self.db_connection)
+        .await?;
+        Ok(webhooks)
+    }
+
+    async fn update(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+        url: Option<&str>,
+        events: Option<&[String]>,
+        active: Option<bool>,
+    ) -> Result<Webhook, Error> {
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"UPDATE tyto.webhooks
+               SET url=COALESCE($3, url), events=COALESCE($4, events), active=COALESCE($5, active), updated_at=now()
+               WHERE id=$1 AND user_id=$2
+               RETURNING id, url, events, active, NULL::varchar AS secret, created_at, updated_at"#,
+            webhook_id,
+            user_id,
+            url,
+            events,
+            active,
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::WebhookNotFound)?;
+        Ok(webhook)
+    }
+
+    async fn delete(&self, user_id: i64, webhook_id: i64) -> Result<(), Error> {
+        sqlx::query!(
+            r#"DELETE FROM tyto.webhooks WHERE id=$1 AND user_id=$2 RETURNING id"#,
+            webhook_id,
+            user_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::WebhookNotFound)?;
+        Ok(())
+    }
+
+    async fn find(&self, user_id: i64, webhook_id: i64) -> Result<WebhookTarget, Error> {
+        let target = sqlx::query_as!(
+            WebhookTarget,
+            r#"SELECT id, url, secret FROM tyto.webhooks WHERE id=$1 AND user_id=$2"#,
+            webhook_id,
+            user_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::WebhookNotFound)?;
+        Ok(target)
+    }
+
+    async fn subscribers(&self, user_id: i64, event: &str) -> Result<Vec<WebhookTarget>, Error> {
+        let targets = sqlx::query_as!(
+            WebhookTarget,
+            r#"SELECT id, url, secret FROM tyto.webhooks
+               WHERE user_id=$1 AND active AND (cardinality(events)=0 OR $2=ANY(events))"#,
+            user_id,
+            event
+        )
+        .fetch_all(&self.db_connection)
+        .await?;
+        Ok(targets)
+    }
+
+    async fn deliveries(&self, webhook_id: i64, limit: i64) -> Result<Vec<WebhookDelivery>, Error> {
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            r#"SELECT id, webhook_id, event, payload, status, attempts, response_status, response_body, error, created_at, delivered_at
+               FROM tyto.webhook_deliveries WHERE webhook_id=$1 ORDER BY created_at DESC, id DESC LIMIT $2"#,
+            webhook_id,
+            limit,
+        )
+        .fetch_all(&self.db_connection)
+        .await?;
+        Ok(deliveries)
+    }
+
+    async fn delivery(
+        &self,
+        webhook_id: i64,
+        delivery_id: i64,
+    ) -> Result<(String, value::Value), Error> {
+        let delivery = sqlx::query!(
+            r#"SELECT event, payload FROM tyto.webhook_deliveries WHERE id=$1 AND webhook_id=$2"#,
+            delivery_id,
+            webhook_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await?
+        .ok_or(Error::WebhookDeliveryNotFound)?;
+        Ok((delivery.event, delivery.payload))
+    }
+
+    async fn create_delivery(
+        &self,
+        webhook_id: i64,
+        event: &str,
+        payload: &value::Value,
+    ) -> Result<i64, Error> {
+        let rec = sqlx::query!(
+            r#"INSERT INTO tyto.webhook_deliveries (webhook_id, event, payload) VALUES ($1,$2,$3) RETURNING id"#,
+            webhook_id,
+            event,
+            payload,
+        )
+        .fetch_one(&self.db_connection)
+        .await?;
+        Ok(rec.id)
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery_id: i64,
+        attempt: &DeliveryAttempt,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"UPDATE tyto.webhook_deliveries
+               SET attempts=$2, status=$3, response_status=$4, response_body=$5, error=$6, updated_at=now(),
+                   delivered_at=CASE WHEN $3::varchar='succeeded' THEN now() ELSE NULL END
+               WHERE id=$1"#,
+            delivery_id,
+            attempt.attempts,
+            attempt.status,
+            attempt.response_status,
+            attempt.response_body,
+            attempt.error,
+        )
+        .execute(&self.db_connection)
+        .await?;
+        Ok(())
+    }
+}
+
+/// An [IpSaltStore] keeping daily salts in `tyto.ip_salts`.
+pub struct PgIpSaltStore {
+    db_connection: Pool<Postgres>,
+}
+
+impl PgIpSaltStore {
+    /// Creates a new instance of [PgIpSaltStore]
+    pub fn new(db_connection: Pool<Postgres>) -> Self {
+        PgIpSaltStore { db_connection }
+    }
+}
+
+#[async_trait()]
+impl IpSaltStore for PgIpSaltStore {
+    async fn rotate(
+        &self,
+        salts: &[(NaiveDate, String)],
+        oldest: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, String)>, Error> {
+        for (day, salt) in salts {
+            sqlx::query!(
+                r#"INSERT INTO tyto.ip_salts (day, salt) VALUES ($1,$2) ON CONFLICT (day) DO NOTHING"#,
+                day,
+                salt,
+            )
+            .execute(&self.db_connection)
+            .await?;
+        }
+        sqlx::query!(r#"DELETE FROM tyto.ip_salts WHERE day < $1"#, oldest)
+            .execute(&self.db_connection)
+            .await?;
+
+        let salts = sqlx::query!(r#"SELECT day, salt FROM tyto.ip_salts"#)
+            .fetch_all(&self.db_connection)
+            .await?
+            .into_iter()
+            .map(|salt| (salt.day, salt.salt))
+            .collect();
+        Ok(salts)
+    }
+}
+
+/// A [VisitStore] adding visit counts to `tyto.urls` and keeping visits in `tyto.visits`, clicks
+/// in `tyto.clicks` and visitors in `tyto.visitor_sketches`, all in a single transaction.
+pub struct PgVisitStore {
+    db_connection: Pool<Postgres>,
+}
+
+impl PgVisitStore {
+    /// Creates a new instance of [PgVisitStore]
+    pub fn new(db_connection: Pool<Postgres>) -> Self {
+        PgVisitStore { db_connection }
+    }
+}
+
+#[async_trait()]
+impl VisitStore for PgVisitStore {
+    async fn record(&self, batch: &VisitBatch<'_>) -> Result<HashMap<i64, i32>, Error> {
+        let mut transaction = self.db_connection.begin().await?;
+
+        // URLs deleted since their visits were buffered are skipped.
+        let mut visit_counts = HashMap::new();
+        for (url_id, counts) in batch.counts.iter() {
+            let url = sqlx::query!(
+                r#"UPDATE tyto.urls SET visit_count=visit_count+$2, bot_visit_count=bot_visit_count+$3
+                   WHERE id=$1 RETURNING visit_count"#,
+                url_id,
+                counts.visits,
+                counts.bot_visits,
+            )
+            .fetch_optional(&mut transaction)
+            .await?;
+            if let Some(url) = url {
+                visit_counts.insert(*url_id, url.visit_count);
+            }
+        }
+
+        let visits: Vec<&BufferedVisit> = batch
+            .stored
+            .iter()
+            .copied()
+            .filter(|buffered| visit_counts.contains_key(&buffered.url_id))
+            .collect();
+        let column = |get: fn(&VisitEvent) -> Option<String>| -> Vec<Option<String>> {
+            visits.iter().map(|buffered| get(&buffered.visit)).collect()
+        };
+        // Macros type array parameters as arrays of non-null values, so runtime binding is used.
+        sqlx::query(
+            r#"INSERT INTO tyto.visits (url_id, address, referrer, referrer_domain, utm_source, utm_medium, utm_campaign,
+                                        user_agent, browser, os, device, ip_hash, country, region, city, is_bot, created_at)
+               SELECT * FROM UNNEST($1::int8[], $2::varchar[], $3::varchar[], $4::varchar[], $5::varchar[], $6::varchar[],
+                                    $7::varchar[], $8::varchar[], $9::varchar[], $10::varchar[], $11::varchar[], $12::varchar[],
+                                    $13::varchar[], $14::varchar[], $15::varchar[], $16::bool[], $17::timestamptz[])"#,
+        )
+        .bind(visits.iter().map(|buffered| buffered.url_id).collect::<Vec<i64>>())
+        .bind(column(|visit| Some(visit.address.clone())))
+        .bind(column(|visit| visit.referrer.clone()))
+        .bind(column(|visit| Some(visit.referrer_domain.clone())))
+        .bind(column(|visit| visit.utm_source.clone()))
+        .bind(column(|visit| visit.utm_medium.clone()))
+        .bind(column(|visit| visit.utm_campaign.clone()))
+        .bind(column(|visit| visit.user_agent.clone()))
+        .bind(column(|visit| Some(visit.browser.to_string())))
+        .bind(column(|visit| Some(visit.os.to_string())))
+        .bind(column(|visit| Some(visit.device.to_string())))
+        .bind(column(|visit| visit.ip_hash.clone()))
+        .bind(column(|visit| visit.country.clone()))
+        .bind(column(|visit| visit.region.clone()))
+        .bind(column(|visit| visit.city.clone()))
+        .bind(
+            visits
+                .iter()
+                .map(|buffered| buffered.visit.is_bot)
+                .collect::<Vec<bool>>(),
+        )
+        .bind(
+            visits
+                .iter()
+                .map(|buffered| buffered.visit.created_at)
+                .collect::<Vec<_>>(),
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let clicks: Vec<(String, i64, DateTime<Utc>)> = visits
+            .iter()
+            .filter_map(|buffered| {
+                let click_id = buffered.click_id.clone()?;
+                Some((click_id, buffered.url_id, buffered.visit.created_at))
+            })
+            .collect();
+        if !clicks.is_empty() {
+            sqlx::query(
+                r#"INSERT INTO tyto.clicks (id, url_id, created_at)
+                   SELECT * FROM UNNEST($1::varchar[], $2::int8[], $3::timestamptz[])"#,
+            )
+            .bind(
+                clicks
+                    .iter()
+                    .map(|click| click.0.clone())
+                    .collect::<Vec<String>>(),
+            )
+            .bind(clicks.iter().map(|click| click.1).collect::<Vec<i64>>())
+            .bind(clicks.iter().map(|click| click.2).collect::<Vec<_>>())
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        for ((url_id, day), sketch) in batch.sketches.iter() {
+            if !visit_counts.contains_key(url_id) {
+                continue;
+            }
+            sqlx::query!(
+                r#"INSERT INTO tyto.visitor_sketches (url_id, day, registers) VALUES ($1,$2,$3)
+                   ON CONFLICT (url_id, day) DO UPDATE
+                   SET registers=tyto.hll_merge(visitor_sketches.registers, EXCLUDED.registers), updated_at=now()"#,
+                url_id,
+                day,
+                sketch.as_bytes(),
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(visit_counts)
+    }
+}
@@ -0,0 +1,64 @@
+use crate::constants::store::REVOKED_TOKEN_PREFIX;
+use crate::core::traits::Store;
+use crate::error::Error;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Keeps tokens revoked on logout in a [Store] until they expire, so they are refused before
+/// their expiry. With Redis store every process refuses them.
+/// How does it work:
+/// 1. A revoked token is kept as a SHA-256 of it, so the store never holds a usable token.
+/// 2. It is kept until the token expires, after which the token is refused anyway.
+/// 3. Every authenticated request checks the list. Errors of the store fail the request, so a
+///    revoked token is never accepted while the store can not be reached.
+pub struct TokenRevocationList {
+    store: Arc<dyn Store>,
+}
+
+/// Returns the key a revoked token is kept under.
+fn key(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{}{}", REVOKED_TOKEN_PREFIX, hex::encode(hasher.finalize()))
+}
+
+impl TokenRevocationList {
+    /// Creates a new instance of [TokenRevocationList]
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        TokenRevocationList { store }
+    }
+
+    /// Revokes a token for the time it would still be accepted.
+    pub async fn revoke(&self, token: &str, ttl: Duration) -> Result<(), Error> {
+        self.store.set(&key(token), "", ttl).await
+    }
+
+    /// Returns true when a token is revoked.
+    pub async fn is_revoked(&self, token: &str) -> Result<bool, Error> {
+        Ok(self.store.get(&key(token)).await?.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    #[tokio::test]
+    async fn tokens_are_revoked_until_they_expire() {
+        let store = Arc::new(MemoryStore::new(None));
+        let revocations = TokenRevocationList::new(store.clone());
+        let ttl = Duration::from_millis(50);
+        revocations.revoke("token", ttl).await.unwrap();
+
+        assert!(revocations.is_revoked("token").await.unwrap());
+        assert!(!revocations.is_revoked("other").await.unwrap());
+        // The store holds a hash of the token only.
+        assert_eq!(store.get(&key("token")).await.unwrap().as_deref(), Some(""));
+        assert!(!key("token").contains("token"));
+
+        tokio::time::sleep(ttl * 2).await;
+        assert!(!revocations.is_revoked("token").await.unwrap());
+    }
+}
@@ -0,0 +1,39 @@
+use crate::core::traits::Job;
+use std::sync::Arc;
+
+/// Runs registered [Job]s periodically on separate tokio tasks.
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Arc<dyn Job>>,
+}
+
+impl Scheduler {
+    /// Creates a new [Scheduler] without any jobs.
+    pub fn new() -> Self {
+        Scheduler { jobs: Vec::new() }
+    }
+
+    /// Registers a job to be run once [Scheduler::start] is called.
+    pub fn register(&mut self, job: Arc<dyn Job>) {
+        self.jobs.push(job);
+    }
+
+    /// Starts running all the registered jobs. Every job runs right away and then after every
+    /// interval. A run that takes longer than the interval delays the next one instead of
+    /// overlapping it. Errors are logged and do not stop the job.
+    pub fn start(self) {
+        for job in self.jobs {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(job.interval());
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    // TODO: Use log here
+                    if let Err(e) = job.run().await {
+                        println!("Job {} failed. Error: {:?}", job.name(), e);
+                    }
+                }
+            });
+        }
+    }
+}
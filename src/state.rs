@@ -1,24 +1,136 @@
-use crate::config::Config;
+use crate::cache::RedirectCache;
+use crate::config::{Config, RepositoryBackend};
+use crate::constants;
+use crate::core::traits::{
+    BotSignatureStore, IpSaltStore, UrlStore, UserStore, VisitStore, WebhookStore,
+};
+use crate::error::Error;
+use crate::rate_limit::RateLimiter;
+use crate::repositories::memory::{
+    MemoryBotSignatureStore, MemoryIpSaltStore, MemoryUrlStore, MemoryUserStore, MemoryWebhookStore,
+};
+use crate::repositories::postgres::{
+    PgBotSignatureStore, PgIpSaltStore, PgUrlStore, PgUserStore, PgVisitStore, PgWebhookStore,
+};
+use crate::revocations::TokenRevocationList;
+use crate::store;
 use jwt_simple::prelude::HS256Key;
 use sqlx::{self, Pool, Postgres};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+/// Repositories of a backend selected in `[repository]` section.
+struct Repositories {
+    urls: Arc<dyn UrlStore>,
+    users: Arc<dyn UserStore>,
+    visits: Arc<dyn VisitStore>,
+    bot_signatures: Arc<dyn BotSignatureStore>,
+    webhooks: Arc<dyn WebhookStore>,
+    ip_salts: Arc<dyn IpSaltStore>,
+}
 
 #[derive(Clone)]
 pub struct State {
     pub config: Config,
     pub db_connection: sqlx::Pool<Postgres>,
+    /// Pool of the read replica, or of the primary when there is none. Listing, statistics and
+    /// redirect lookups read from it, so they may lag behind writes a little.
+    pub read_connection: sqlx::Pool<Postgres>,
     pub jwt_key: HS256Key,
+    /// Short links, kept where `[repository]` section says.
+    pub urls: Arc<dyn UrlStore>,
+    /// User accounts, kept where `[repository]` section says.
+    pub users: Arc<dyn UserStore>,
+    /// Visits written by the click buffer, kept where `[repository]` section says.
+    pub visits: Arc<dyn VisitStore>,
+    /// Signatures of bots, kept where `[repository]` section says.
+    pub bot_signatures: Arc<dyn BotSignatureStore>,
+    /// Webhooks and their deliveries, kept where `[repository]` section says.
+    pub webhooks: Arc<dyn WebhookStore>,
+    /// Daily salts of client IP addresses, kept where `[repository]` section says.
+    pub ip_salts: Arc<dyn IpSaltStore>,
+    /// Short links resolved by redirects.
+    pub redirect_cache: Arc<RedirectCache>,
+    /// Counters of rate limited actions.
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Tokens revoked on logout.
+    pub revoked_tokens: Arc<TokenRevocationList>,
 }
 
 impl State {
-    pub fn new(config: Config, db_connection: Pool<Postgres>) -> State {
+    /// Creates a new instance of [State] and opens the stores configured in `[store]`
+    /// section. Memory store of redirect cache is bounded, while that of rate limit counters
+    /// and revoked tokens is not, so they are never evicted before they expire.
+    pub async fn new(
+        config: Config,
+        db_connection: Pool<Postgres>,
+        read_connection: Pool<Postgres>,
+    ) -> Result<State, Error> {
         let key = config.auth.key.clone();
         let key = base64::decode(key).expect("Failed to base64-decode JWT key");
         let key = HS256Key::from_bytes(&key);
 
-        State {
+        let repositories = match config.repository.backend {
+            RepositoryBackend::Postgres => {
+                let replica = config
+                    .database
+                    .replica_url
+                    .as_ref()
+                    .map(|_| read_connection.clone());
+                Repositories {
+                    urls: Arc::new(PgUrlStore::new(db_connection.clone(), replica)),
+                    users: Arc::new(PgUserStore::new(
+                        db_connection.clone(),
+                        read_connection.clone(),
+                    )),
+                    visits: Arc::new(PgVisitStore::new(db_connection.clone())),
+                    bot_signatures: Arc::new(PgBotSignatureStore::new(db_connection.clone())),
+                    webhooks: Arc::new(PgWebhookStore::new(db_connection.clone())),
+                    ip_salts: Arc::new(PgIpSaltStore::new(db_connection.clone())),
+                }
+            }
+            RepositoryBackend::Memory => {
+                let users = MemoryUserStore::new();
+                if let Some(admin) = &config.repository.admin {
+                    users.seed_admin(&admin.email, &admin.password);
+                }
+                let urls = Arc::new(MemoryUrlStore::new());
+                Repositories {
+                    urls: urls.clone(),
+                    users: Arc::new(users),
+                    visits: urls,
+                    bot_signatures: Arc::new(MemoryBotSignatureStore::new()),
+                    webhooks: Arc::new(MemoryWebhookStore::new(
+                        constants::webhook::DELIVERY_LOG_LIMIT as usize,
+                    )),
+                    ip_salts: Arc::new(MemoryIpSaltStore::new()),
+                }
+            }
+        };
+
+        let redirect_store = match NonZeroUsize::new(config.cache.redirect_capacity) {
+            Some(capacity) => Some(store::open(&config.store, Some(capacity)).await?),
+            None => None,
+        };
+        let redirect_cache = Arc::new(RedirectCache::new(redirect_store, &config.cache));
+        let shared_store = store::open(&config.store, None).await?;
+        let rate_limiter = Arc::new(RateLimiter::new(shared_store.clone(), &config.rate_limit));
+        let revoked_tokens = Arc::new(TokenRevocationList::new(shared_store));
+
+        Ok(State {
             config,
             db_connection,
+            read_connection,
             jwt_key: key,
-        }
+            urls: repositories.urls,
+            users: repositories.users,
+            visits: repositories.visits,
+            bot_signatures: repositories.bot_signatures,
+            webhooks: repositories.webhooks,
+            ip_salts: repositories.ip_salts,
+            redirect_cache,
+            rate_limiter,
+            revoked_tokens,
+        })
     }
 }
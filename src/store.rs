@@ -0,0 +1,23 @@
+pub mod memory;
+pub mod redis;
+
+use crate::config::{StoreBackend, StoreConfig};
+use crate::core::traits::Store;
+use crate::error::Error;
+use memory::MemoryStore;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+/// Opens the store configured in `[store]` section. Memory store keeps up to capacity entries
+/// and evicts the least recently used ones beyond it, or keeps every entry until it expires
+/// when capacity is absent. Redis server decides what it evicts itself.
+pub async fn open(
+    cfg: &StoreConfig,
+    capacity: Option<NonZeroUsize>,
+) -> Result<Arc<dyn Store>, Error> {
+    let store: Arc<dyn Store> = match cfg.backend {
+        StoreBackend::Memory => Arc::new(MemoryStore::new(capacity)),
+        StoreBackend::Redis => Arc::new(self::redis::RedisStore::connect(cfg).await?),
+    };
+    Ok(store)
+}
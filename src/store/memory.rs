@@ -0,0 +1,211 @@
+use crate::constants::store::SWEEP_MIN_WRITES;
+use crate::core::traits::Store;
+use crate::error::Error;
+use async_trait::async_trait;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A value kept along with the moment it expires at.
+struct Entry {
+    value: String,
+    expires_at: Instant,
+}
+
+/// Entries of a [MemoryStore] along with writes made since they were last swept.
+struct Entries {
+    cache: LruCache<String, Entry>,
+    writes: usize,
+}
+
+impl Entries {
+    /// Inserts an entry and drops expired ones once enough writes are made since the last
+    /// sweep, so keys that are never read again do not pile up.
+    fn put(&mut self, key: &str, entry: Entry) {
+        self.cache.put(key.to_string(), entry);
+        self.writes += 1;
+        if self.writes < SWEEP_MIN_WRITES.max(self.cache.len()) {
+            return;
+        }
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .cache
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.cache.pop(&key);
+        }
+        self.writes = 0;
+    }
+}
+
+/// A [Store] keeping entries in the server process. Entries are not shared with other
+/// processes and are lost on restart.
+/// How does it work:
+/// 1. Entries are kept in an LRU cache along with the moment they expire at. Expired entries
+///    are treated as absent and dropped when they are read.
+/// 2. When bounded, the least recently used entry is evicted once the store is full.
+/// 3. Expired entries that are never read again are swept every so many writes, the size of
+///    the store at least, so a sweep costs a constant time per write on average.
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+    capacity: Option<NonZeroUsize>,
+}
+
+impl MemoryStore {
+    /// Creates a new instance of [MemoryStore]. It is unbounded when capacity is absent.
+    pub fn new(capacity: Option<NonZeroUsize>) -> Self {
+        let cache = match capacity {
+            Some(capacity) => LruCache::new(capacity),
+            None => LruCache::unbounded(),
+        };
+        MemoryStore {
+            entries: Mutex::new(Entries { cache, writes: 0 }),
+            capacity,
+        }
+    }
+}
+
+#[async_trait()]
+impl Store for MemoryStore {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.cache.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Ok(Some(entry.value.clone())),
+            Some(_) => {
+                entries.cache.pop(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), Error> {
+        let entry = Entry {
+            value: value.to_string(),
+            expires_at: Instant::now() + ttl,
+        };
+        self.entries.lock().unwrap().put(key, entry);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.entries.lock().unwrap().cache.pop(key);
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<u64, Error> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let (count, expires_at) = match entries.cache.get(key) {
+            Some(entry) if entry.expires_at > now => (
+                entry.value.parse::<u64>().unwrap_or(0) + 1,
+                entry.expires_at,
+            ),
+            _ => (1, now + ttl),
+        };
+        let entry = Entry {
+            value: count.to_string(),
+            expires_at,
+        };
+        entries.put(key, entry);
+        Ok(count)
+    }
+
+    fn entries(&self) -> Option<usize> {
+        Some(self.entries.lock().unwrap().cache.len())
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.capacity.map(NonZeroUsize::get)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_millis(50);
+
+    fn bounded(capacity: usize) -> MemoryStore {
+        MemoryStore::new(NonZeroUsize::new(capacity))
+    }
+
+    #[tokio::test]
+    async fn entries_expire_after_their_ttl() {
+        let store = MemoryStore::new(None);
+        store.set("short", "a", TTL).await.unwrap();
+        store
+            .set("long", "b", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(store.get("short").await.unwrap().as_deref(), Some("a"));
+
+        tokio::time::sleep(TTL * 2).await;
+        assert_eq!(store.get("short").await.unwrap(), None);
+        assert_eq!(store.get("long").await.unwrap().as_deref(), Some("b"));
+        // Expired entries are dropped when they are read.
+        assert_eq!(store.entries(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_swept() {
+        let store = MemoryStore::new(None);
+        for i in 0..SWEEP_MIN_WRITES / 2 {
+            store.set(&format!("old{}", i), "", TTL).await.unwrap();
+        }
+        tokio::time::sleep(TTL * 2).await;
+        for i in 0..SWEEP_MIN_WRITES / 2 {
+            let ttl = Duration::from_secs(60);
+            store.set(&format!("new{}", i), "", ttl).await.unwrap();
+        }
+        assert_eq!(store.entries(), Some(SWEEP_MIN_WRITES / 2));
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted() {
+        let store = bounded(2);
+        let ttl = Duration::from_secs(60);
+        store.set("a", "1", ttl).await.unwrap();
+        store.set("b", "2", ttl).await.unwrap();
+        // Reading a makes b the least recently used one.
+        store.get("a").await.unwrap();
+        store.set("c", "3", ttl).await.unwrap();
+
+        assert_eq!(store.get("a").await.unwrap().as_deref(), Some("1"));
+        assert_eq!(store.get("b").await.unwrap(), None);
+        assert_eq!(store.get("c").await.unwrap().as_deref(), Some("3"));
+        assert_eq!(store.entries(), Some(2));
+        assert_eq!(store.capacity(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn deleted_entries_are_absent() {
+        let store = MemoryStore::new(None);
+        store.set("a", "1", TTL).await.unwrap();
+        store.delete("a").await.unwrap();
+        store.delete("absent").await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn counters_expire_with_their_window() {
+        let store = MemoryStore::new(None);
+        assert_eq!(store.increment("k", TTL).await.unwrap(), 1);
+        assert_eq!(store.increment("k", TTL).await.unwrap(), 2);
+        assert_eq!(store.increment("other", TTL).await.unwrap(), 1);
+
+        // Incrementing does not extend the window.
+        tokio::time::sleep(TTL * 3 / 5).await;
+        assert_eq!(store.increment("k", TTL).await.unwrap(), 3);
+        tokio::time::sleep(TTL * 3 / 5).await;
+        assert_eq!(store.increment("k", TTL).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::config::StoreConfig;
+use crate::core::traits::Store;
+use crate::error::Error;
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use std::time::Duration;
+
+/// Returns milliseconds of a TTL. Redis refuses zero, so it is one millisecond at least.
+fn milliseconds(ttl: Duration) -> u64 {
+    (ttl.as_millis() as u64).max(1)
+}
+
+/// A [Store] keeping entries in a Redis compatible server, like Redis, Valkey, KeyDB or
+/// Dragonfly, so every process behind a load balancer shares them.
+/// How does it work:
+/// 1. Every key is prefixed with `key_prefix` of `[store]` section.
+/// 2. Entries are written with a TTL in milliseconds, so the server expires them.
+/// 3. A counter is created with its TTL and incremented in a single transaction, so it expires
+///    however many processes increment it.
+/// 4. Commands are sent over a multiplexed connection that reconnects when it breaks.
+///    Commands sent while the server can not be reached fail.
+pub struct RedisStore {
+    connection: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RedisStore {
+    /// Connects to the server at `redis_url` of `[store]` section.
+    pub async fn connect(cfg: &StoreConfig) -> Result<Self, Error> {
+        let client = redis::Client::open(cfg.redis_url.as_str())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(RedisStore {
+            connection,
+            key_prefix: cfg.key_prefix.clone(),
+        })
+    }
+
+    /// Returns a key with the configured prefix.
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.key_prefix, key)
+    }
+}
+
+#[async_trait()]
+impl Store for RedisStore {
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let value: Option<String> = redis::cmd("GET")
+            .arg(self.key(key))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(value)
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), Error> {
+        redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(value)
+            .arg("PX")
+            .arg(milliseconds(ttl))
+            .query_async::<_, ()>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        redis::cmd("DEL")
+            .arg(self.key(key))
+            .query_async::<_, ()>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<u64, Error> {
+        let key = self.key(key);
+        let (count,): (u64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("PX")
+            .arg(milliseconds(ttl))
+            .arg("NX")
+            .ignore()
+            .cmd("INCR")
+            .arg(&key)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(count)
+    }
+
+    fn entries(&self) -> Option<usize> {
+        None
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Tests against a Redis compatible server at `REDIS_URL`, like redis://127.0.0.1:6379/0. Run
+/// them with `REDIS_URL=... cargo test store::redis -- --ignored`. Keys are written under a
+/// random prefix.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::RedirectCache;
+    use crate::config::{CacheConfig, StoreBackend};
+    use crate::core::traits::UrlStore;
+    use crate::repositories::memory::MemoryUrlStore;
+    use crate::revocations::TokenRevocationList;
+    use crate::types::{CreateURLRequest, UpdateURLRequest};
+    use crate::utils::generate_token;
+    use std::sync::Arc;
+
+    async fn store() -> RedisStore {
+        let cfg = StoreConfig {
+            backend: StoreBackend::Redis,
+            redis_url: std::env::var("REDIS_URL").expect("REDIS_URL is not set"),
+            key_prefix: format!("tyto-test:{}:", generate_token(8)),
+        };
+        RedisStore::connect(&cfg).await.unwrap()
+    }
+
+    /// Returns milliseconds a key of the store expires in.
+    async fn pttl(store: &RedisStore, key: &str) -> i64 {
+        redis::cmd("PTTL")
+            .arg(store.key(key))
+            .query_async(&mut store.connection.clone())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_URL of a Redis server"]
+    async fn entries_are_set_read_and_deleted() {
+        let store = store().await;
+        let ttl = Duration::from_millis(200);
+        store.set("a", "1", ttl).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap().as_deref(), Some("1"));
+        assert!((1..=200).contains(&pttl(&store, "a").await));
+
+        store.delete("a").await.unwrap();
+        store.delete("absent").await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), None);
+
+        store.set("b", "2", ttl).await.unwrap();
+        tokio::time::sleep(ttl * 2).await;
+        assert_eq!(store.get("b").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_URL of a Redis server"]
+    async fn counters_expire_with_their_window() {
+        let store = store().await;
+        let ttl = Duration::from_millis(300);
+        assert_eq!(store.increment("k", ttl).await.unwrap(), 1);
+        let first = pttl(&store, "k").await;
+        assert!((1..=300).contains(&first));
+
+        // SET NX leaves the TTL of an existing counter alone, so the window is not extended.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(store.increment("k", ttl).await.unwrap(), 2);
+        assert!(pttl(&store, "k").await < first);
+
+        tokio::time::sleep(ttl).await;
+        assert_eq!(store.increment("k", ttl).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_URL of a Redis server"]
+    async fn revoked_tokens_are_seen_by_every_process() {
+        let store = Arc::new(store().await);
+        let revoking = TokenRevocationList::new(store.clone());
+        let checking = TokenRevocationList::new(store);
+        revoking
+            .revoke("token", Duration::from_millis(200))
+            .await
+            .unwrap();
+
+        assert!(checking.is_revoked("token").await.unwrap());
+        assert!(!checking.is_revoked("other").await.unwrap());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(!checking.is_revoked("token").await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_URL of a Redis server"]
+    async fn invalidated_redirects_are_dropped_for_every_process() {
+        let store: Arc<dyn Store> = Arc::new(store().await);
+        let serving = RedirectCache::new(Some(store.clone()), &CacheConfig::default());
+        let updating = RedirectCache::new(Some(store), &CacheConfig::default());
+        let urls = MemoryUrlStore::new();
+        let request = CreateURLRequest {
+            target: "https://a.example".to_string(),
+            description: None,
+            banned: false,
+            user_id: 1,
+            track_visits: true,
+            public_stats: false,
+            track_conversions: false,
+            expires_at: None,
+        };
+        let id = urls.create("abc", &request).await.unwrap();
+        let target = |redirect: Option<crate::cache::Redirect>| redirect.map(|r| r.target);
+
+        let redirect = serving.resolve(&urls, "abc").await.unwrap();
+        assert_eq!(target(redirect).as_deref(), Some("https://a.example"));
+
+        let request = UpdateURLRequest {
+            target: Some("https://b.example".to_string()),
+            description: None,
+            banned: None,
+            track_visits: None,
+            public_stats: None,
+            track_conversions: None,
+            expires_at: None,
+        };
+        urls.update(id, &request).await.unwrap();
+        let redirect = serving.resolve(&urls, "abc").await.unwrap();
+        assert_eq!(target(redirect).as_deref(), Some("https://a.example"));
+
+        updating.invalidate("abc").await;
+        let redirect = serving.resolve(&urls, "abc").await.unwrap();
+        assert_eq!(target(redirect).as_deref(), Some("https://b.example"));
+    }
+}
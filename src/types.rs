@@ -1,6 +1,7 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::{self, value};
+use std::str::FromStr;
 
 /// Represents a request status
 #[derive(Serialize)]
@@ -17,7 +18,7 @@ pub struct Response {
 }
 
 /// A struct to represent a single URL record
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Url {
     pub id: i64,
     pub user_id: i64,
@@ -26,6 +27,11 @@ pub struct Url {
     pub banned: bool,
     pub target: String,
     pub visit_count: i32,
+    pub bot_visit_count: i32,
+    pub track_visits: bool,
+    pub public_stats: bool,
+    pub track_conversions: bool,
+    pub expires_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -37,6 +43,35 @@ pub struct CreateURLRequest {
     pub description: Option<String>,
     pub banned: bool,
     pub user_id: i64,
+    /// Indicates if details of every visit are stored. Defaults to true.
+    #[serde(default = "default_track_visits")]
+    pub track_visits: bool,
+    /// Indicates if statistics are public at the address followed by +. Defaults to false.
+    #[serde(default)]
+    pub public_stats: bool,
+    /// Indicates if a click identifier is appended to the target on redirect, so conversions
+    /// can be reported. Defaults to false.
+    #[serde(default)]
+    pub track_conversions: bool,
+    /// Time the link stops redirecting at. Never when absent.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_track_visits() -> bool {
+    true
+}
+
+/// A struct used to represent a request input for /urls/{id} PATCH. Absent fields are left
+/// unchanged.
+#[derive(Deserialize)]
+pub struct UpdateURLRequest {
+    pub target: Option<String>,
+    pub description: Option<String>,
+    pub banned: Option<bool>,
+    pub track_visits: Option<bool>,
+    pub public_stats: Option<bool>,
+    pub track_conversions: Option<bool>,
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize)]
@@ -45,9 +80,18 @@ pub struct CreateUserRequest {
     pub email: String,
     /// Password of a user.
     pub password: String,
+    /// Preferred language of a user. Negotiated from Accept-Language header when absent.
+    pub language: Option<String>,
 }
 
-#[derive(Deserialize, Serialize)]
+/// A struct used to represent a request input for /users/{id}/language PUT
+#[derive(Deserialize)]
+pub struct SetLanguageRequest {
+    /// Preferred language of a user like de.
+    pub language: String,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct User {
     /// Unique ID of a user.
     pub id: Option<i64>,
@@ -59,6 +103,8 @@ pub struct User {
     pub email: String,
     /// Password of a user.
     pub password: String,
+    /// Preferred language of a user.
+    pub language: Option<String>,
     /// Timestamp when user is created in database.
     pub created_at: DateTime<Utc>,
     /// Timestamp when user is last updated in database.
@@ -76,14 +122,896 @@ pub struct LoginRequest {
 /// A structure to represent JWT Claim
 #[derive(Deserialize, Serialize)]
 pub struct UserClaim {
+    /// Unique ID of a user
+    pub id: i64,
     /// Email of a user
     pub email: String,
     /// Role of a user.
     pub role: String,
 }
 
-#[derive(Deserialize, Serialize)]
+/// Role of a user carried in [UserClaim].
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum UserRole {
-    Normal,
+    Regular,
     Admin,
 }
+
+impl FromStr for UserRole {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "regular" => Ok(UserRole::Regular),
+            "admin" => Ok(UserRole::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Events users can be notified about.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// A new account is created. Carries the activation link.
+    AccountCreated,
+    /// An account is activated.
+    AccountActivated,
+    /// A shortened URL is created.
+    LinkCreated,
+    /// A shortened URL is deleted.
+    LinkDeleted,
+    /// Periodic summary of links' performance.
+    LinkDigest,
+}
+
+impl NotificationEvent {
+    /// Returns the name used to store the event in database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::AccountCreated => "account_created",
+            NotificationEvent::AccountActivated => "account_activated",
+            NotificationEvent::LinkCreated => "link_created",
+            NotificationEvent::LinkDeleted => "link_deleted",
+            NotificationEvent::LinkDigest => "link_digest",
+        }
+    }
+
+    /// Channels used for the event when a user has not set any preference for it.
+    pub fn default_channels(&self) -> &'static [NotificationChannel] {
+        match self {
+            NotificationEvent::AccountCreated
+            | NotificationEvent::AccountActivated
+            | NotificationEvent::LinkDigest => &[NotificationChannel::Email],
+            NotificationEvent::LinkCreated | NotificationEvent::LinkDeleted => &[],
+        }
+    }
+}
+
+impl FromStr for NotificationEvent {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "account_created" => Ok(NotificationEvent::AccountCreated),
+            "account_activated" => Ok(NotificationEvent::AccountActivated),
+            "link_created" => Ok(NotificationEvent::LinkCreated),
+            "link_deleted" => Ok(NotificationEvent::LinkDeleted),
+            "link_digest" => Ok(NotificationEvent::LinkDigest),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Channels a notification can be delivered through.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Email,
+    Webhook,
+    Slack,
+    Log,
+}
+
+impl NotificationChannel {
+    /// Returns the name used to store the channel in database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::Email => "email",
+            NotificationChannel::Webhook => "webhook",
+            NotificationChannel::Slack => "slack",
+            NotificationChannel::Log => "log",
+        }
+    }
+}
+
+impl FromStr for NotificationChannel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(NotificationChannel::Email),
+            "webhook" => Ok(NotificationChannel::Webhook),
+            "slack" => Ok(NotificationChannel::Slack),
+            "log" => Ok(NotificationChannel::Log),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A notification message handed over to a [crate::core::traits::Notifier].
+#[derive(Clone, Debug, Serialize)]
+pub struct Notification {
+    /// Event the notification is about.
+    pub event: NotificationEvent,
+    /// Short summary. Used as email subject.
+    pub subject: String,
+    /// Full text of the notification.
+    pub body: String,
+}
+
+/// A user's choice to receive an event over a channel, or not to receive it at all.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NotificationPreference {
+    /// Event to be notified about.
+    pub event: NotificationEvent,
+    /// Channel to deliver the notification through. A preference without one opts out of the
+    /// event, unless other preferences choose channels for it.
+    pub channel: Option<NotificationChannel>,
+    /// Channel specific destination like a webhook URL. Email channel always uses the user's
+    /// email and the configured default is used for other channels when it is absent.
+    pub destination: Option<String>,
+}
+
+/// Events delivered to user registered webhooks.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum WebhookEvent {
+    /// A shortened URL is created.
+    #[serde(rename = "link.created")]
+    LinkCreated,
+    /// A shortened URL is updated.
+    #[serde(rename = "link.updated")]
+    LinkUpdated,
+    /// A shortened URL is deleted.
+    #[serde(rename = "link.deleted")]
+    LinkDeleted,
+    /// A shortened URL is banned.
+    #[serde(rename = "link.banned")]
+    LinkBanned,
+    /// Visit count of a shortened URL reached one of the configured thresholds.
+    #[serde(rename = "link.click_threshold")]
+    LinkClickThreshold,
+    /// An account is activated.
+    #[serde(rename = "user.activated")]
+    UserActivated,
+    /// Test event sent on demand to check a webhook endpoint.
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+impl WebhookEvent {
+    /// Returns the name used in payloads and to store the event in database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::LinkCreated => "link.created",
+            WebhookEvent::LinkUpdated => "link.updated",
+            WebhookEvent::LinkDeleted => "link.deleted",
+            WebhookEvent::LinkBanned => "link.banned",
+            WebhookEvent::LinkClickThreshold => "link.click_threshold",
+            WebhookEvent::UserActivated => "user.activated",
+            WebhookEvent::Ping => "ping",
+        }
+    }
+}
+
+/// A webhook endpoint registered by a user.
+#[derive(Clone, Serialize)]
+pub struct Webhook {
+    /// Unique ID of a webhook.
+    pub id: i64,
+    /// URL payloads are posted to.
+    pub url: String,
+    /// Events delivered to the webhook. All the events are delivered when empty.
+    pub events: Vec<String>,
+    /// Shows if deliveries are made to the webhook.
+    pub active: bool,
+    /// Secret used to sign payloads. Only returned when a webhook is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Timestamp when webhook is created in database.
+    pub created_at: DateTime<Utc>,
+    /// Timestamp when webhook is last updated in database.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A struct used to represent a request input for /webhooks POST
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    /// URL payloads are posted to.
+    pub url: String,
+    /// Events to deliver. All the events are delivered when empty or absent.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Secret used to sign payloads. A random one is generated when absent.
+    pub secret: Option<String>,
+}
+
+/// A struct used to represent a request input for /webhooks/{id} PATCH. Absent fields are left
+/// unchanged.
+#[derive(Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub active: Option<bool>,
+}
+
+/// A single attempt to deliver an event to a webhook, including all the retries.
+#[derive(Clone, Serialize)]
+pub struct WebhookDelivery {
+    /// Unique ID of a delivery.
+    pub id: i64,
+    /// ID of a webhook the delivery belongs to.
+    pub webhook_id: i64,
+    /// Name of the delivered event.
+    pub event: String,
+    /// JSON payload posted to the webhook.
+    pub payload: value::Value,
+    /// One of pending, succeeded or failed.
+    pub status: String,
+    /// Number of attempts made so far.
+    pub attempts: i32,
+    /// HTTP status code of the last response.
+    pub response_status: Option<i32>,
+    /// Body of the last response, truncated.
+    pub response_body: Option<String>,
+    /// Error of the last attempt if it could not reach the webhook.
+    pub error: Option<String>,
+    /// Timestamp when delivery is created.
+    pub created_at: DateTime<Utc>,
+    /// Timestamp when delivery succeeded.
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Where deliveries to a webhook are made.
+pub struct WebhookTarget {
+    /// ID of the webhook.
+    pub id: i64,
+    /// URL payloads are posted to.
+    pub url: String,
+    /// Secret used to sign payloads.
+    pub secret: String,
+}
+
+/// Outcome of an attempt to deliver a payload to a webhook.
+pub struct DeliveryAttempt {
+    /// Number of attempts made so far.
+    pub attempts: i32,
+    /// One of pending, succeeded or failed.
+    pub status: &'static str,
+    /// HTTP status code of the response.
+    pub response_status: Option<i32>,
+    /// Body of the response, truncated.
+    pub response_body: Option<String>,
+    /// Error of a request that got no response.
+    pub error: Option<String>,
+}
+
+/// How often a link performance digest is sent.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    /// Returns the name used to store the frequency in database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+}
+
+impl FromStr for DigestFrequency {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(DigestFrequency::Daily),
+            "weekly" => Ok(DigestFrequency::Weekly),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A user's subscription to link performance digests.
+#[derive(Deserialize, Serialize)]
+pub struct DigestSubscription {
+    /// How often digest is sent.
+    pub frequency: DigestFrequency,
+    /// IANA time zone of a user like Europe/Berlin. Defaults to UTC.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Hour of a day in user's time zone when digest is sent. Defaults to 8. Weekly digests are
+    /// sent on Mondays.
+    #[serde(default = "default_digest_hour")]
+    pub hour: i32,
+}
+
+fn default_timezone() -> String {
+    String::from("UTC")
+}
+
+fn default_digest_hour() -> i32 {
+    8
+}
+
+/// A single visit to a shortened URL.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Visit {
+    /// Unique ID of a visit.
+    pub id: i64,
+    /// ID of the visited URL.
+    pub url_id: i64,
+    /// Shortened URL part that is visited.
+    pub address: String,
+    /// Referer header of a visit.
+    pub referrer: Option<String>,
+    /// Referring domain, direct or unknown.
+    pub referrer_domain: Option<String>,
+    /// utm_source parameter of a request.
+    pub utm_source: Option<String>,
+    /// utm_medium parameter of a request.
+    pub utm_medium: Option<String>,
+    /// utm_campaign parameter of a request.
+    pub utm_campaign: Option<String>,
+    /// Browser family of a client. Raw User-Agent header is not exposed.
+    pub browser: Option<String>,
+    /// Operating system family of a client.
+    pub os: Option<String>,
+    /// Device class of a client.
+    pub device: Option<String>,
+    /// Salted hash of client IP address.
+    pub ip_hash: Option<String>,
+    /// Country code of a client.
+    pub country: Option<String>,
+    /// Region of a client, like a state.
+    pub region: Option<String>,
+    /// City of a client.
+    pub city: Option<String>,
+    /// Indicates if a visit is made by a bot, crawler or prefetcher.
+    pub is_bot: bool,
+    /// Timestamp when visit is made.
+    pub created_at: DateTime<Utc>,
+}
+
+/// A click streamed live to owners of a URL as it happens. Details are left out when they are
+/// not stored for the visit, like when the client opts out of tracking.
+#[derive(Clone, Serialize)]
+pub struct LiveClick {
+    /// ID of the clicked URL.
+    pub url_id: i64,
+    /// ID of a user the URL belongs to.
+    pub user_id: i64,
+    /// Shortened URL part that is clicked.
+    pub address: String,
+    /// Referring domain, direct or unknown.
+    pub referrer_domain: Option<String>,
+    /// Browser family of a client.
+    pub browser: Option<String>,
+    /// Operating system family of a client.
+    pub os: Option<String>,
+    /// Device class of a client.
+    pub device: Option<String>,
+    /// Country code of a client.
+    pub country: Option<String>,
+    /// Indicates if a click is made by a bot, crawler or prefetcher.
+    pub is_bot: bool,
+    /// Timestamp when click is made.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Number of visits sharing a value of a breakdown dimension, like a browser family.
+#[derive(Serialize)]
+pub struct BreakdownItem {
+    pub name: String,
+    pub count: i64,
+}
+
+/// Visits of a URL broken down by a dimension, most frequent values first.
+#[derive(Serialize)]
+pub struct Breakdown {
+    pub total: i64,
+    pub items: Vec<BreakdownItem>,
+}
+
+/// Query string parameters for top-N endpoints.
+#[derive(Deserialize)]
+pub struct TopQuery {
+    /// Number of values to return.
+    pub limit: Option<i64>,
+}
+
+/// UTM parameter of a short link request that clicks can be aggregated by.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UtmParameter {
+    Source,
+    Medium,
+    Campaign,
+}
+
+/// Query string parameters for paginated endpoints.
+#[derive(Deserialize)]
+pub struct PageQuery {
+    /// Page number starting from 1.
+    pub page: Option<i64>,
+    /// Number of records in a page.
+    pub per_page: Option<i64>,
+}
+
+/// A page of records along with the information needed to fetch others.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+/// Length of a bucket in click statistics.
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsInterval {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsInterval {
+    /// Returns name of an interval as understood by Postgres date_trunc.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatsInterval::Hour => "hour",
+            StatsInterval::Day => "day",
+            StatsInterval::Week => "week",
+            StatsInterval::Month => "month",
+        }
+    }
+
+    /// Returns the shortest possible length of an interval in seconds.
+    pub fn min_seconds(&self) -> i64 {
+        match self {
+            StatsInterval::Hour => 60 * 60,
+            StatsInterval::Day => 24 * 60 * 60,
+            StatsInterval::Week => 7 * 24 * 60 * 60,
+            StatsInterval::Month => 28 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Query string parameters for /urls/{id}/stats GET. Range is [from, to).
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    /// Start of a range. Defaults to 30 days before its end.
+    pub from: Option<DateTime<Utc>>,
+    /// End of a range. Defaults to now.
+    pub to: Option<DateTime<Utc>>,
+    /// Length of a bucket. Defaults to a day.
+    pub interval: Option<StatsInterval>,
+    /// IANA time zone that buckets are aligned to. Defaults to UTC.
+    pub tz: Option<String>,
+}
+
+/// Statistics of a URL shown publicly when its owner opts in. Owner of the URL is left out.
+#[derive(Serialize)]
+pub struct PublicStats {
+    /// Shortened URL part.
+    pub address: String,
+    /// URL the short link redirects to.
+    pub target: String,
+    /// Timestamp when the short link is created.
+    pub created_at: DateTime<Utc>,
+    /// Number of clicks made by humans since the short link is created.
+    pub clicks: i64,
+    /// Clicks per UTC day of the last days, oldest first.
+    pub daily: Vec<PublicStatsDay>,
+    /// Countries most clicks come from.
+    pub countries: Vec<BreakdownItem>,
+}
+
+/// Clicks of a day in [PublicStats].
+#[derive(Serialize)]
+pub struct PublicStatsDay {
+    /// UTC day.
+    pub date: NaiveDate,
+    /// Number of clicks made by humans.
+    pub clicks: i64,
+}
+
+/// Format of exported analytics.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma separated values with a header row.
+    #[default]
+    Csv,
+    /// A JSON object per line.
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// Returns content type of an export.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// Returns file name extension of an export.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Query string parameters for /urls/{id}/export/... and /users/{id}/export/... GET. Range is
+/// [from, to).
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// Format of an export. Defaults to csv.
+    pub format: Option<ExportFormat>,
+    /// Start of a range. Defaults to the first visit.
+    pub from: Option<DateTime<Utc>>,
+    /// End of a range. Defaults to now.
+    pub to: Option<DateTime<Utc>>,
+    /// Length of a bucket of exported statistics. Buckets are aligned to UTC. Defaults to a day.
+    pub interval: Option<StatsInterval>,
+}
+
+/// Query string parameters for /urls/{id}/live and /users/{id}/live GET.
+#[derive(Deserialize)]
+pub struct LiveQuery {
+    /// Live token of the stream, for clients that can not send Authorization header, like
+    /// EventSource of browsers.
+    pub token: Option<String>,
+}
+
+/// A structure to represent JWT Claim of a live token, which allows streaming clicks within a
+/// scope and nothing else.
+#[derive(Deserialize, Serialize, PartialEq, Eq)]
+pub struct LiveClaim {
+    /// Kind of the scope, either url or user.
+    pub live: String,
+    /// Unique ID of a URL or a user.
+    pub id: i64,
+}
+
+/// Clicks of a URL within a bucket of exported statistics.
+#[derive(Serialize)]
+pub struct ExportedStats {
+    /// ID of a URL.
+    pub url_id: i64,
+    /// Shortened URL part.
+    pub address: String,
+    /// Start of a bucket in UTC.
+    pub start: DateTime<Utc>,
+    /// Number of clicks made by humans.
+    pub clicks: i64,
+    /// Number of clicks made by bots.
+    pub bot_clicks: i64,
+    /// Estimated number of unique human visitors.
+    pub unique_visitors: i64,
+}
+
+/// Clicks within a single bucket of click statistics.
+#[derive(Serialize)]
+pub struct StatsBucket {
+    /// Start of a bucket in requested time zone.
+    pub start: String,
+    /// Number of clicks made by humans.
+    pub clicks: i64,
+    /// Number of clicks made by bots.
+    pub bot_clicks: i64,
+    /// Estimated number of unique human visitors.
+    pub unique_visitors: i64,
+}
+
+/// Click statistics of a URL over a time range.
+#[derive(Serialize)]
+pub struct Stats {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub interval: StatsInterval,
+    pub tz: String,
+    /// Number of clicks made by humans over the whole range.
+    pub clicks: i64,
+    /// Number of clicks made by bots over the whole range.
+    pub bot_clicks: i64,
+    /// Estimated number of unique human visitors over the whole range.
+    pub unique_visitors: i64,
+    /// Buckets in chronological order. Buckets without clicks are included.
+    pub buckets: Vec<StatsBucket>,
+}
+
+/// Level of detail of a geography breakdown.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeoLevel {
+    Country,
+    Region,
+    City,
+}
+
+/// Query string parameters for /urls/{id}/geography GET.
+#[derive(Deserialize)]
+pub struct GeographyQuery {
+    /// Level of detail. Defaults to country.
+    pub by: Option<GeoLevel>,
+    /// Number of values to return.
+    pub limit: Option<i64>,
+}
+
+/// A signature of bots matched against User-Agent header of a visit.
+#[derive(Clone, Serialize)]
+pub struct BotSignature {
+    pub id: i64,
+    /// Lowercase text that User-Agent header of a bot contains.
+    pub pattern: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A struct used to represent a request input for /admin/bots POST
+#[derive(Deserialize)]
+pub struct CreateBotSignatureRequest {
+    pub pattern: String,
+    pub description: Option<String>,
+}
+
+/// A group of links of a user compared together.
+#[derive(Serialize)]
+pub struct Campaign {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    /// Start of the campaign. Statistics of the campaign start here by default.
+    pub starts_at: Option<DateTime<Utc>>,
+    /// End of the campaign. Statistics of the campaign end here by default.
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Number of links in the campaign.
+    pub url_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A struct used to represent a request input for /campaigns POST
+#[derive(Deserialize)]
+pub struct CreateCampaignRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// A struct used to represent a request input for /campaigns/{id} PATCH. Absent fields are
+/// left unchanged.
+#[derive(Deserialize)]
+pub struct UpdateCampaignRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// Clicks of a single link over a time range.
+#[derive(Serialize)]
+pub struct UrlClicks {
+    pub url_id: i64,
+    pub address: String,
+    /// Number of clicks made by humans over the range.
+    pub clicks: i64,
+    /// Number of clicks made by bots over the range.
+    pub bot_clicks: i64,
+}
+
+/// Click statistics of all the links of a campaign over a time range.
+#[derive(Serialize)]
+pub struct CampaignStats {
+    /// Clicks of all the links added up.
+    #[serde(flatten)]
+    pub stats: Stats,
+    /// Clicks of every link of the campaign, most clicked first.
+    pub urls: Vec<UrlClicks>,
+}
+
+/// An action on a site of a user, like a signup or a purchase, that clicks of links lead to.
+#[derive(Serialize)]
+pub struct ConversionGoal {
+    pub id: i64,
+    pub name: String,
+    /// Random key identifying the goal in tracking pixels and postbacks.
+    pub key: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A struct used to represent a request input for /goals POST
+#[derive(Deserialize)]
+pub struct CreateGoalRequest {
+    pub name: String,
+}
+
+/// A struct used to represent a request input for /goals/{id} PATCH. Absent fields are left
+/// unchanged.
+#[derive(Deserialize)]
+pub struct UpdateGoalRequest {
+    pub name: Option<String>,
+}
+
+/// A struct used to represent a request input for /conversions POST and query string
+/// parameters for /conversions/pixel.gif GET
+#[derive(Deserialize)]
+pub struct ConversionRequest {
+    /// Key of a reached goal.
+    pub goal: String,
+    /// Click identifier tyto appended to the target of a link.
+    pub click_id: String,
+}
+
+/// Query string parameters for /urls/{id}/conversions GET. Range is [from, to).
+#[derive(Deserialize)]
+pub struct ConversionQuery {
+    /// Start of a range. Defaults to 30 days before its end.
+    pub from: Option<DateTime<Utc>>,
+    /// End of a range. Defaults to now.
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Conversions of a goal over a time range.
+#[derive(Serialize)]
+pub struct GoalConversions {
+    pub goal_id: i64,
+    pub name: String,
+    /// Number of clicks that reached the goal.
+    pub conversions: i64,
+    /// Conversions per human click. Zero when there are no clicks.
+    pub rate: f64,
+}
+
+/// Clicks and conversions of a single link over a time range.
+#[derive(Serialize)]
+pub struct UrlConversions {
+    pub url_id: i64,
+    pub address: String,
+    /// Number of clicks made by humans over the range.
+    pub clicks: i64,
+    pub goals: Vec<GoalConversions>,
+}
+
+/// Clicks and conversions of a link or all the links of a campaign over a time range.
+/// Conversions are counted when they are reported within the range.
+#[derive(Serialize)]
+pub struct ConversionStats {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Number of clicks made by humans over the range.
+    pub clicks: i64,
+    pub goals: Vec<GoalConversions>,
+    /// Clicks and conversions of every link of a campaign, most clicked first. Absent for a
+    /// single link.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urls: Option<Vec<UrlConversions>>,
+}
+
+/// Query string parameters for /admin/overview GET.
+#[derive(Deserialize)]
+pub struct OverviewQuery {
+    /// Number of days of a period compared with the period before it. Defaults to 30.
+    pub days: Option<i64>,
+    /// Number of top links and target domains to return.
+    pub limit: Option<i64>,
+}
+
+/// A count over all time along with its growth over the last period.
+#[derive(Serialize)]
+pub struct Growth {
+    /// Count over all time.
+    pub total: i64,
+    /// Count added within the last period.
+    pub current: i64,
+    /// Count added within the period before it.
+    pub previous: i64,
+    /// Change of the last period relative to the one before it, like 0.25 for 25% more.
+    /// Absent when nothing was added in the period before.
+    pub change: Option<f64>,
+}
+
+/// A link clicked the most within a period.
+#[derive(Serialize)]
+pub struct TopLink {
+    pub url_id: i64,
+    pub address: String,
+    pub target: String,
+    /// Number of clicks made by humans within the period.
+    pub clicks: i64,
+}
+
+/// A domain links lead to, with clicks of those links within a period.
+#[derive(Serialize)]
+pub struct TopDomain {
+    /// Host of targets without www.
+    pub domain: String,
+    /// Number of links leading to the domain.
+    pub links: i64,
+    /// Number of clicks made by humans within the period.
+    pub clicks: i64,
+}
+
+/// Accounts created within a period and how many of them are activated.
+#[derive(Serialize)]
+pub struct SignupFunnel {
+    pub created: i64,
+    pub activated: i64,
+    /// Activated accounts per created account. Zero when none is created.
+    pub activation_rate: f64,
+}
+
+/// Outcomes of an operation that can fail within a period.
+#[derive(Serialize)]
+pub struct ErrorRate {
+    /// Number of finished operations.
+    pub total: i64,
+    /// Number of failed operations.
+    pub failed: i64,
+    /// Failed operations per finished operation. Zero when none is finished.
+    pub rate: f64,
+}
+
+/// Error rates of operations whose outcomes are recorded.
+#[derive(Serialize)]
+pub struct ErrorRates {
+    /// Webhook deliveries that failed after all the attempts.
+    pub webhook_deliveries: ErrorRate,
+}
+
+/// Service-wide usage overview for administrators.
+#[derive(Serialize)]
+pub struct AdminOverview {
+    /// Start of the last period.
+    pub from: DateTime<Utc>,
+    /// End of the last period.
+    pub to: DateTime<Utc>,
+    /// Accounts that are not deleted.
+    pub users: Growth,
+    pub links: Growth,
+    /// Clicks made by humans.
+    pub clicks: Growth,
+    pub top_links: Vec<TopLink>,
+    pub top_domains: Vec<TopDomain>,
+    pub signup_funnel: SignupFunnel,
+    pub errors: ErrorRates,
+}
+
+/// Hit and miss counts of the redirect cache of a server process since it started.
+#[derive(Serialize)]
+pub struct CacheMetrics {
+    /// Backend of the store keeping the cache. Absent when the cache is turned off.
+    pub backend: Option<&'static str>,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits per lookup. Zero when nothing is looked up yet.
+    pub hit_rate: f64,
+    /// Number of short links in the cache, including unknown ones. Absent unless the cache is
+    /// kept in memory.
+    pub entries: Option<usize>,
+    /// Maximum number of short links in the cache. Absent unless the cache is kept in memory.
+    pub capacity: Option<usize>,
+}
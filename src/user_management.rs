@@ -1,9 +1,9 @@
 use crate::constants;
 use crate::error;
+use crate::i18n::Locale;
 use crate::types::CreateUserRequest;
 use crate::types::LoginRequest;
 use crate::types::UserClaim;
-use crate::utils::validate_token;
 use crate::{core::traits::UserManager, state::State, types::User};
 use actix_web::web;
 use async_trait::async_trait;
@@ -35,7 +35,7 @@ fn generate_activation_code(email: &str) -> String {
 impl UserManager for TytoUserManager {
     /// Creates a new user.
     /// How does it work:
-    /// At the moment is takes email and password of a user and stores in database. It stores password in
+    /// At the moment is takes email and password of a user and stores in user store. It stores password in
     /// plain text which is indeed a bad practice and soon it will be changed to store securely. It then
     /// generates activation code and returns it.
     async fn create(&self, user: CreateUserRequest) -> Result<(i64, String), error::Error> {
@@ -50,18 +50,9 @@ impl UserManager for TytoUserManager {
             decrypt it and compare.
         */
 
-        let db_connection = &self.state.db_connection;
         let activation_code = generate_activation_code(&user.email);
-
-        let rec = sqlx::query!(
-            r#"INSERT INTO tyto.users (email,password, activation_code) VALUES ($1,$2,$3) RETURNING id"#,
-            user.email,
-            user.password,
-            activation_code
-        )
-        .fetch_one(db_connection)
-        .await?;
-        Ok((rec.id, activation_code))
+        let id = self.state.users.create(&user, &activation_code).await?;
+        Ok((id, activation_code))
     }
 
     /// Returns an instance of a [User] for a user with supplied id.
@@ -70,20 +61,7 @@ impl UserManager for TytoUserManager {
     /// populated with record values.
     async fn get(&self, user_id: i64) -> Result<User, error::Error> {
         // TODO: Return error if a user is deleted.
-        let db_connection = &self.state.db_connection;
-        let user = sqlx::query!(r#"SELECT * FROM tyto.users WHERE id=$1"#, user_id)
-            .fetch_one(db_connection)
-            .await?;
-
-        Ok(User {
-            id: Some(user.id),
-            apikey: user.apikey,
-            email: user.email,
-            banned: user.banned,
-            password: user.password,
-            created_at: user.created_at,
-            updated_at: user.updated_at,
-        })
+        self.state.users.get(user_id).await
     }
 
     /// Returns all the users in database.
@@ -91,79 +69,28 @@ impl UserManager for TytoUserManager {
     /// It simply retrieves all the user records from the database and returns a list of [User]
     /// instances
     async fn get_all(&self) -> Result<Vec<User>, error::Error> {
-        let db_connection = &self.state.db_connection;
-        let found_users = sqlx::query!(r#"SELECT * FROM tyto.users ORDER BY created_at ASC"#)
-            .fetch_all(db_connection)
-            .await?;
-
-        let users: Vec<User> = found_users
-            .into_iter()
-            .map(|user| User {
-                id: Some(user.id),
-                apikey: user.apikey,
-                email: user.email,
-                banned: user.banned,
-                password: user.password,
-                created_at: user.created_at,
-                updated_at: user.updated_at,
-            })
-            .collect::<Vec<User>>();
-
-        Ok(users)
+        self.state.users.get_all().await
     }
 
     /// Deletes a user with supplied id.
     /// How does it work:
     /// It simply deletes a user from database.
     async fn delete(&self, user_id: i64) -> Result<(), error::Error> {
-        let db_connection = &self.state.db_connection;
-        let result = sqlx::query!(
-            r#"DELETE FROM tyto.users where id=$1 RETURNING id"#,
-            user_id
-        )
-        .fetch_optional(db_connection)
-        .await?;
-        if result.is_none() {
-            return Err(error::Error::UserNotFound);
-        }
-
-        Ok(())
+        self.state.users.delete(user_id).await
     }
 
-    /// Activates a user with supplied id.
+    /// Activates a user with supplied activation code and returns id of the user.
     /// How does it work:
     /// 1. Check if length is as expected. Return error if not.
     /// 2. Check if user is already activated. Return error if not.
     /// 3. Activate the user.
-    async fn activate(&self, activation_code: String) -> Result<(), error::Error> {
+    async fn activate(&self, activation_code: String) -> Result<i64, error::Error> {
         // Activation code must be of fixed and predefined length.
         if activation_code.len() != constants::user::ACTIVATION_CODE_LENGTH {
             return Err(error::Error::InvalidActivationToken);
         }
 
-        // Fetch user record
-        let db_connection = &self.state.db_connection;
-        let user_record = sqlx::query!(
-            r#"SELECT activated from tyto.users WHERE activation_code=$1"#,
-            activation_code
-        )
-        .fetch_one(db_connection)
-        .await?;
-
-        // Do not activate already activated account.
-        if user_record.activated {
-            return Err(error::Error::AccountAlreadyActivated);
-        }
-
-        // Activate un-activated account
-        sqlx::query!(
-            r#"UPDATE tyto.users SET activated=true WHERE activation_code=$1"#,
-            activation_code
-        )
-        .execute(db_connection)
-        .await?;
-
-        Ok(())
+        self.state.users.activate(&activation_code).await
     }
 
     /// Logs in the user and returns a JWT on success.
@@ -171,19 +98,13 @@ impl UserManager for TytoUserManager {
     /// 2. Create user claim to be encoded in JWT.
     /// 3. Generate JWT and return it.
     async fn login(&self, login_request: LoginRequest) -> Result<String, error::Error> {
-        let db_connection = &self.state.db_connection;
-        let _user_record = sqlx::query!(
-            r#"SELECT id, email, activated from tyto.users WHERE email=$1 and password=$2"#,
-            login_request.email,
-            login_request.password
-        )
-        .fetch_one(db_connection)
-        .await?;
+        let (id, role) = self.state.users.login(&login_request).await?;
 
         // User claim that we'll encode in token.
         let user_claim = UserClaim {
+            id,
             email: login_request.email.clone(),
-            role: "regular".to_string(),
+            role,
         };
 
         // Generate JWT
@@ -196,11 +117,25 @@ impl UserManager for TytoUserManager {
     }
 
     /// Allows user to logout.
-    /// Practically the logout is not possible. Client can just remove existing token from the
-    /// local storage. Still we return new token with that belongs to no user or role. Also, that
-    /// token would expire in 2 milliseconds.
+    /// How does it work:
+    /// 1. Validate the token, which can be supplied as it is or with `Bearer ` prefix.
+    /// 2. Revoke it for as long as it would be accepted, which is until it expires plus the
+    ///    clock skew tolerated by token validation. Requests carrying it are refused from then on.
+    /// 3. Return a new token that belongs to no user or role and expires in 2 milliseconds, for
+    ///    clients replacing their token with the one they receive.
     async fn logout(&self, token: String) -> Result<String, error::Error> {
-        let mut existing_claim = validate_token(&token, &self.state.jwt_key).await?;
+        let token = token.strip_prefix("Bearer ").unwrap_or(&token);
+        let claims = self.state.jwt_key.verify_token::<UserClaim>(token, None)?;
+        let expires_at = claims.expires_at.map(|at| at.as_secs()).unwrap_or(0);
+        let now = Clock::now_since_epoch().as_secs();
+        let ttl = expires_at.saturating_sub(now) + DEFAULT_TIME_TOLERANCE_SECS;
+        self.state
+            .revoked_tokens
+            .revoke(token, std::time::Duration::from_secs(ttl))
+            .await?;
+
+        let mut existing_claim = claims.custom;
+        existing_claim.id = 0;
         existing_claim.email = "".to_string();
         existing_claim.role = "".to_string();
 
@@ -208,6 +143,19 @@ impl UserManager for TytoUserManager {
         let token = self.state.jwt_key.authenticate(claim)?;
         Ok(token)
     }
+
+    /// Sets preferred language of a user.
+    /// How does it work:
+    /// It checks if the language is supported and stores its normalized tag, so de-AT becomes de.
+    async fn set_language(&self, user_id: i64, language: String) -> Result<(), error::Error> {
+        let locale =
+            Locale::from_tag(&language).ok_or(error::Error::UnsupportedLanguage { language })?;
+
+        self.state
+            .users
+            .set_language(user_id, locale.as_str())
+            .await
+    }
 }
 
 impl TytoUserManager {
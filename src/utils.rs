@@ -1,11 +1,66 @@
 use jwt_simple::prelude::HS256Key;
 
 use crate::error;
-use crate::types::UserClaim;
+use crate::state::State;
+use crate::types::{UserClaim, UserRole};
+use actix_web::{http::header, HttpRequest};
+use chrono_tz::Tz;
 use jwt_simple::algorithms::MACLike;
+use rand::Rng;
 
 /// Validates if the token is valid and returns the contained [UserClaim]
 pub async fn validate_token(token: &str, key: &HS256Key) -> Result<UserClaim, error::Error> {
     let claim = key.verify_token::<UserClaim>(token, None)?;
     Ok(claim.custom)
 }
+
+/// Validates the token from Authorization header of a request and returns the contained
+/// [UserClaim]. Token can be supplied as it is or with `Bearer ` prefix. Tokens revoked on
+/// logout are refused.
+pub async fn authenticate(req: &HttpRequest, state: &State) -> Result<UserClaim, error::Error> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(error::Error::MissingToken)?;
+    let token = token.strip_prefix("Bearer ").unwrap_or(token);
+    let claim = validate_token(token, &state.jwt_key).await?;
+    if state.revoked_tokens.is_revoked(token).await? {
+        return Err(error::Error::RevokedToken);
+    }
+    Ok(claim)
+}
+
+/// Validates the token from Authorization header of a request like [authenticate] and makes
+/// sure it belongs to an admin.
+pub async fn authorize_admin(req: &HttpRequest, state: &State) -> Result<UserClaim, error::Error> {
+    let claim = authenticate(req, state).await?;
+    if claim.role.parse() != Ok(UserRole::Admin) {
+        return Err(error::Error::AdminRequired);
+    }
+    Ok(claim)
+}
+
+/// Generates a hex encoded random token out of supplied number of random bytes.
+pub fn generate_token(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    let bytes: Vec<u8> = (0..bytes).map(|_| rng.gen::<u8>()).collect();
+    hex::encode(bytes)
+}
+
+/// Returns true when a database error is a violation of a unique constraint.
+pub fn is_unique_violation(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("23505"),
+        _ => false,
+    }
+}
+
+/// Parses an IANA time zone name.
+pub fn parse_timezone(timezone: &str) -> Result<Tz, error::Error> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|_| error::Error::InvalidTimezone {
+            timezone: timezone.to_string(),
+        })
+}
@@ -0,0 +1,512 @@
+use crate::config::WebhooksConfig;
+use crate::constants;
+use crate::core::traits::WebhookStore;
+use crate::error::Error;
+use crate::state::State;
+use crate::types::{
+    CreateWebhookRequest, DeliveryAttempt, UpdateWebhookRequest, Webhook, WebhookDelivery,
+    WebhookEvent, WebhookTarget,
+};
+use crate::utils::generate_token;
+use actix_web::web;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use serde_json::{json, value};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A delivery waiting to be made
+struct PendingDelivery {
+    /// ID of the delivery in delivery log
+    id: i64,
+    /// URL of the webhook
+    url: String,
+    /// Secret of the webhook
+    secret: String,
+    /// Name of the event
+    event: String,
+    /// JSON payload
+    payload: value::Value,
+}
+
+/// Manages user registered webhooks and delivers events to them.
+pub struct WebhookManager {
+    state: web::Data<State>,
+    /// HTTP client shared by all the deliveries
+    client: reqwest::Client,
+}
+
+/// Returns hex encoded HMAC-SHA256 of `{timestamp}.{body}` keyed with webhook secret. Receivers
+/// compute the same over the `X-Tyto-Timestamp` header and raw body and compare it with the
+/// `X-Tyto-Signature` header.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    // HMAC accepts keys of any length, so this can not fail.
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Returns value of the `X-Tyto-Signature` header of a delivery, the [sign]ature prefixed with
+/// the name of its algorithm.
+fn signature_header(secret: &str, timestamp: i64, body: &str) -> String {
+    format!("sha256={}", sign(secret, timestamp, body))
+}
+
+/// Returns true when an address belongs to the public internet. Loopback, private, link-local,
+/// shared, documentation, multicast and other special purpose addresses are not public.
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, _, _] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(&IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7, link-local fe80::/10 and documentation 2001:db8::/32
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+/// Resolves host names of webhooks to their public addresses only. Deliveries connect through
+/// it, so a host name can not be pointed at a private address after its webhook is registered.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(&addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(Error::WebhookUrlNotAllowed.to_string().into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Makes sure webhook URL is an absolute http(s) URL which reaches only public addresses,
+/// unless private networks are allowed.
+pub async fn validate_url(url: &str, cfg: &WebhooksConfig) -> Result<(), Error> {
+    let parsed = match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => parsed,
+        _ => return Err(Error::InvalidWebhookUrl),
+    };
+    if cfg.allow_private_networks {
+        return Ok(());
+    }
+
+    // IPv6 addresses are enclosed in brackets.
+    let host = parsed.host_str().ok_or(Error::InvalidWebhookUrl)?;
+    let addrs: Vec<IpAddr> = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => {
+            let port = parsed.port_or_known_default().unwrap_or_default();
+            tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| Error::WebhookUrlNotAllowed)?
+                .map(|addr| addr.ip())
+                .collect()
+        }
+    };
+    if addrs.is_empty() || !addrs.iter().all(is_public) {
+        return Err(Error::WebhookUrlNotAllowed);
+    }
+    Ok(())
+}
+
+/// Returns an HTTP client for requests to URLs supplied by users. It does not follow redirects
+/// and connects only to public addresses, unless private networks are allowed.
+pub fn client(cfg: &WebhooksConfig) -> Result<reqwest::Client, Error> {
+    let mut client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(cfg.timeout_seconds))
+        .redirect(reqwest::redirect::Policy::none());
+    if !cfg.allow_private_networks {
+        client = client.dns_resolver(Arc::new(PublicResolver));
+    }
+    Ok(client.build()?)
+}
+
+/// Delivers a payload to a webhook.
+/// How does it work:
+/// 1. Make sure webhook URL still reaches only public addresses. Fail the delivery otherwise.
+/// 2. Sign the payload with webhook secret and post it. Redirects are not followed.
+/// 3. Record the outcome of the attempt in delivery log.
+/// 4. Retry failed attempts with exponential backoff until configured attempts are exhausted.
+async fn deliver(
+    webhooks: Arc<dyn WebhookStore>,
+    client: reqwest::Client,
+    cfg: WebhooksConfig,
+    delivery: PendingDelivery,
+) {
+    let delivery_id = delivery.id;
+    let body = delivery.payload.to_string();
+
+    if let Err(e) = validate_url(&delivery.url, &cfg).await {
+        let outcome = DeliveryAttempt {
+            attempts: 1,
+            status: "failed",
+            response_status: None,
+            response_body: None,
+            error: Some(e.to_string()),
+        };
+        // TODO: Use log here
+        if let Err(e) = webhooks.record_attempt(delivery_id, &outcome).await {
+            println!("Error: {:?}", e);
+        }
+        return;
+    }
+
+    for attempt in 1..=cfg.max_attempts {
+        let timestamp = Utc::now().timestamp();
+        let signature = signature_header(&delivery.secret, timestamp, &body);
+
+        let result = client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Tyto-Event", &delivery.event)
+            .header("X-Tyto-Delivery", delivery_id)
+            .header("X-Tyto-Timestamp", timestamp)
+            .header("X-Tyto-Signature", signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        let (succeeded, response_status, response_body, error) = match result {
+            Ok(response) => {
+                let status = response.status();
+                let text = response.text().await.unwrap_or_default();
+                let text: String = text
+                    .chars()
+                    .take(constants::webhook::RESPONSE_BODY_LIMIT)
+                    .collect();
+                (
+                    status.is_success(),
+                    Some(status.as_u16() as i32),
+                    Some(text),
+                    None,
+                )
+            }
+            Err(e) => (false, None, None, Some(e.to_string())),
+        };
+
+        let last_attempt = attempt == cfg.max_attempts;
+        let status = if succeeded {
+            "succeeded"
+        } else if last_attempt {
+            "failed"
+        } else {
+            "pending"
+        };
+
+        let outcome = DeliveryAttempt {
+            attempts: attempt as i32,
+            status,
+            response_status,
+            response_body,
+            error,
+        };
+        if let Err(e) = webhooks.record_attempt(delivery_id, &outcome).await {
+            // TODO: Use log here
+            println!("Error: {:?}", e);
+        }
+
+        if succeeded || last_attempt {
+            return;
+        }
+
+        let backoff = cfg.backoff_seconds * 2_u64.pow(attempt - 1);
+        tokio::time::sleep(Duration::from_secs(backoff)).await;
+    }
+}
+
+impl WebhookManager {
+    /// Creates a new instance of [WebhookManager]
+    pub fn new(state: web::Data<State>) -> Result<Self, Error> {
+        let client = client(&state.config.webhooks)?;
+        Ok(WebhookManager { state, client })
+    }
+
+    /// Registers a new webhook for a user. The secret is returned only here.
+    pub async fn create(
+        &self,
+        user_id: i64,
+        request: CreateWebhookRequest,
+    ) -> Result<Webhook, Error> {
+        validate_url(&request.url, &self.state.config.webhooks).await?;
+        let secret = request
+            .secret
+            .unwrap_or_else(|| generate_token(constants::webhook::SECRET_BYTES));
+        let events: Vec<String> = request
+            .events
+            .iter()
+            .map(|event| event.as_str().to_string())
+            .collect();
+
+        let webhook = self
+            .state
+            .webhooks
+            .create(user_id, &request.url, &secret, &events)
+            .await?;
+
+        Ok(Webhook {
+            secret: Some(secret),
+            ..webhook
+        })
+    }
+
+    /// Returns all the webhooks of a user.
+    pub async fn get_all(&self, user_id: i64) -> Result<Vec<Webhook>, Error> {
+        self.state.webhooks.get_all(user_id).await
+    }
+
+    /// Updates URL, events or state of a webhook.
+    pub async fn update(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+        request: UpdateWebhookRequest,
+    ) -> Result<Webhook, Error> {
+        if let Some(url) = &request.url {
+            validate_url(url, &self.state.config.webhooks).await?;
+        }
+        let events: Option<Vec<String>> = request.events.map(|events| {
+            events
+                .iter()
+                .map(|event| event.as_str().to_string())
+                .collect()
+        });
+
+        self.state
+            .webhooks
+            .update(
+                user_id,
+                webhook_id,
+                request.url.as_deref(),
+                events.as_deref(),
+                request.active,
+            )
+            .await
+    }
+
+    /// Deletes a webhook along with its delivery log.
+    pub async fn delete(&self, user_id: i64, webhook_id: i64) -> Result<(), Error> {
+        self.state.webhooks.delete(user_id, webhook_id).await
+    }
+
+    /// Returns latest deliveries of a webhook.
+    pub async fn deliveries(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        // Make sure the webhook belongs to the user
+        self.state.webhooks.find(user_id, webhook_id).await?;
+
+        self.state
+            .webhooks
+            .deliveries(webhook_id, constants::webhook::DELIVERY_LOG_LIMIT)
+            .await
+    }
+
+    /// Delivers the payload of an earlier delivery again as a new delivery and returns its id.
+    pub async fn redeliver(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+        delivery_id: i64,
+    ) -> Result<i64, Error> {
+        let target = self.state.webhooks.find(user_id, webhook_id).await?;
+        let (event, payload) = self
+            .state
+            .webhooks
+            .delivery(webhook_id, delivery_id)
+            .await?;
+        self.enqueue(target, event, payload).await
+    }
+
+    /// Sends a ping event to a webhook, so receivers can be tested. Returns id of the delivery.
+    pub async fn ping(&self, user_id: i64, webhook_id: i64) -> Result<i64, Error> {
+        let target = self.state.webhooks.find(user_id, webhook_id).await?;
+        let payload =
+            WebhookManager::payload(WebhookEvent::Ping, json!({ "webhook_id": webhook_id }));
+        self.enqueue(target, WebhookEvent::Ping.as_str().to_string(), payload)
+            .await
+    }
+
+    /// Delivers an event to all the active webhooks of a user subscribed to it.
+    pub async fn emit(
+        &self,
+        user_id: i64,
+        event: WebhookEvent,
+        data: value::Value,
+    ) -> Result<(), Error> {
+        let targets = self
+            .state
+            .webhooks
+            .subscribers(user_id, event.as_str())
+            .await?;
+
+        let payload = WebhookManager::payload(event, data);
+        for target in targets {
+            self.enqueue(target, event.as_str().to_string(), payload.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Builds a JSON payload for an event.
+    fn payload(event: WebhookEvent, data: value::Value) -> value::Value {
+        json!({
+            "event": event.as_str(),
+            "created_at": Utc::now(),
+            "data": data,
+        })
+    }
+
+    /// Records a pending delivery and starts delivering it on a separate tokio task.
+    async fn enqueue(
+        &self,
+        target: WebhookTarget,
+        event: String,
+        payload: value::Value,
+    ) -> Result<i64, Error> {
+        let id = self
+            .state
+            .webhooks
+            .create_delivery(target.id, &event, &payload)
+            .await?;
+
+        tokio::spawn(deliver(
+            self.state.webhooks.clone(),
+            self.client.clone(),
+            self.state.config.webhooks.clone(),
+            PendingDelivery {
+                id,
+                url: target.url,
+                secret: target.secret,
+                event,
+                payload,
+            },
+        ));
+
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deliveries_are_signed_with_hmac_sha256() {
+        let body = r#"{"event":"link.created","data":{"id":1}}"#;
+        assert_eq!(
+            signature_header("whsec_test", 1_700_000_000, body),
+            "sha256=dfcc12fd6ebd1e7ce7c1c2f611e92df7e34914ea4d8efdb6c4e338a8c4f99b86"
+        );
+        assert_eq!(
+            sign("", 0, ""),
+            "b849d5a581847b281957065739df36df2463d1977ea8d6e1e4e6cf33fadc68c3"
+        );
+        // Timestamp, secret and body are all signed.
+        assert_ne!(
+            sign("whsec_test", 1_700_000_001, body),
+            sign("whsec_test", 1_700_000_000, body)
+        );
+        assert_ne!(
+            sign("whsec_other", 1_700_000_000, body),
+            sign("whsec_test", 1_700_000_000, body)
+        );
+        assert_ne!(
+            sign("whsec_test", 1_700_000_000, "{}"),
+            sign("whsec_test", 1_700_000_000, body)
+        );
+    }
+
+    fn public(ip: &str) -> bool {
+        is_public(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(public(ip), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_must_reach_public_addresses() {
+        let cfg = WebhooksConfig::default();
+        assert!(validate_url("https://93.184.216.34/hook", &cfg)
+            .await
+            .is_ok());
+        assert!(matches!(
+            validate_url("ftp://93.184.216.34/hook", &cfg).await,
+            Err(Error::InvalidWebhookUrl)
+        ));
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+        ] {
+            assert!(matches!(
+                validate_url(url, &cfg).await,
+                Err(Error::WebhookUrlNotAllowed)
+            ));
+        }
+
+        let cfg = WebhooksConfig {
+            allow_private_networks: true,
+            ..WebhooksConfig::default()
+        };
+        assert!(validate_url("http://127.0.0.1:8080/hook", &cfg)
+            .await
+            .is_ok());
+    }
+}